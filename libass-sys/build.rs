@@ -2,7 +2,7 @@ fn main() {
     /*
     let bindings = bindgen::Builder::default()
         .header("./libass/libass/ass.h")
-        .layout_tests(false).opaque_type("ass_library").opaque_type("ass_track").default_enum_style(bindgen::EnumVariation::ModuleConsts).allowlist_function("ass_.+").allowlist_type("ass_.+").allowlist_var("ass_.+").blocklist_type("(?i)ass_event").blocklist_type(".+[P|p]riv")
+        .layout_tests(false).opaque_type("ass_library").default_enum_style(bindgen::EnumVariation::ModuleConsts).allowlist_function("ass_.+").allowlist_type("ass_.+").allowlist_var("ass_.+").opaque_type(".+[P|p]riv")
         .generate().expect("oops");
 
     bindings.write_to_file("./src/lib.rs").expect("owie");
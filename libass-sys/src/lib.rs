@@ -1,4 +1,5 @@
 /* automatically generated by rust-bindgen 0.63.0 */
+#![allow(non_upper_case_globals, non_camel_case_types, non_snake_case)]
 
 pub type va_list = *mut ::std::os::raw::c_char;
 #[repr(C)]
@@ -45,10 +46,75 @@ pub struct ass_style {
 }
 pub type ASS_Style = ass_style;
 #[repr(C)]
-#[repr(align(8))]
+#[derive(Debug, Copy, Clone)]
+pub struct render_priv {
+    _unused: [u8; 0],
+}
+pub type ASS_RenderPriv = render_priv;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct parser_priv {
+    _unused: [u8; 0],
+}
+pub type ASS_ParserPriv = parser_priv;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ass_event {
+    pub Start: ::std::os::raw::c_longlong,
+    pub Duration: ::std::os::raw::c_longlong,
+    pub ReadOrder: ::std::os::raw::c_int,
+    pub Layer: ::std::os::raw::c_int,
+    pub Style: ::std::os::raw::c_int,
+    pub Name: *mut ::std::os::raw::c_char,
+    pub MarginL: ::std::os::raw::c_int,
+    pub MarginR: ::std::os::raw::c_int,
+    pub MarginV: ::std::os::raw::c_int,
+    pub Effect: *mut ::std::os::raw::c_char,
+    pub Text: *mut ::std::os::raw::c_char,
+    pub render_priv: *mut ASS_RenderPriv,
+}
+pub type ASS_Event = ass_event;
+pub mod ASS_YCbCrMatrix {
+    #[doc = " ASS YCbCr matrix types. Used in the \"YCbCr Matrix\" header."]
+    pub type Type = ::std::os::raw::c_int;
+    pub const YCBCR_DEFAULT: Type = 0;
+    pub const YCBCR_UNKNOWN: Type = 1;
+    pub const YCBCR_NONE: Type = 2;
+    pub const YCBCR_BT601_TV: Type = 3;
+    pub const YCBCR_BT601_PC: Type = 4;
+    pub const YCBCR_BT709_TV: Type = 5;
+    pub const YCBCR_BT709_PC: Type = 6;
+    pub const YCBCR_SMPTE240M_TV: Type = 7;
+    pub const YCBCR_SMPTE240M_PC: Type = 8;
+    pub const YCBCR_FCC_TV: Type = 9;
+    pub const YCBCR_FCC_PC: Type = 10;
+}
+#[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct ass_track {
-    pub _bindgen_opaque_blob: [u64; 17usize],
+    pub n_styles: ::std::os::raw::c_int,
+    pub max_styles: ::std::os::raw::c_int,
+    pub n_events: ::std::os::raw::c_int,
+    pub max_events: ::std::os::raw::c_int,
+    pub styles: *mut ASS_Style,
+    pub events: *mut ASS_Event,
+    pub style_format: *mut ::std::os::raw::c_char,
+    pub event_format: *mut ::std::os::raw::c_char,
+    pub track_type: ass_track__bindgen_ty_1::Type,
+    pub PlayResX: ::std::os::raw::c_int,
+    pub PlayResY: ::std::os::raw::c_int,
+    pub Timer: f64,
+    pub WrapStyle: ::std::os::raw::c_int,
+    pub ScaledBorderAndShadow: ::std::os::raw::c_int,
+    pub Kerning: ::std::os::raw::c_int,
+    pub Language: *mut ::std::os::raw::c_char,
+    pub YCbCrMatrix: ASS_YCbCrMatrix::Type,
+    pub default_style: ::std::os::raw::c_int,
+    pub name: *mut ::std::os::raw::c_char,
+    pub library: *mut ASS_Library,
+    pub parser_priv: *mut ASS_ParserPriv,
+    pub LayoutResX: ::std::os::raw::c_int,
+    pub LayoutResY: ::std::os::raw::c_int,
 }
 pub mod ass_track__bindgen_ty_1 {
     pub type Type = ::std::os::raw::c_int;
@@ -0,0 +1,196 @@
+//! ASS colors
+//!
+use std::fmt;
+
+/// A color as used by ASS styles and override tags.
+///
+/// ASS stores colors in BGR order, and the alpha is a transparency rather than an opacity, so an
+/// `alpha` of 0 is fully opaque and 255 is invisible.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[allow(missing_docs)]
+pub struct AssColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Transparency, 0 is opaque.
+    pub alpha: u8,
+}
+
+impl AssColor {
+    /// Opaque white.
+    pub const WHITE: Self = Self::rgb(255, 255, 255);
+    /// Opaque black.
+    pub const BLACK: Self = Self::rgb(0, 0, 0);
+
+    /// Construct an opaque color.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, alpha: 0 }
+    }
+
+    /// Construct a color with a transparency, where 0 is opaque.
+    pub const fn rgba(r: u8, g: u8, b: u8, alpha: u8) -> Self {
+        Self { r, g, b, alpha }
+    }
+
+    /// Convert from the `0xAABBGGRR` value written in ASS files.
+    pub const fn from_abgr(value: u32) -> Self {
+        let [alpha, b, g, r] = value.to_be_bytes();
+        Self { r, g, b, alpha }
+    }
+
+    /// Convert to the `0xAABBGGRR` value written in ASS files.
+    pub const fn to_abgr(self) -> u32 {
+        u32::from_be_bytes([self.alpha, self.b, self.g, self.r])
+    }
+
+    /// Convert from the `0xRRGGBBAA` value Libass stores in styles and images.
+    pub const fn from_libass(value: u32) -> Self {
+        let [r, g, b, alpha] = value.to_be_bytes();
+        Self { r, g, b, alpha }
+    }
+
+    /// Convert to the `0xRRGGBBAA` value Libass stores in styles and images.
+    pub const fn to_libass(self) -> u32 {
+        u32::from_be_bytes([self.r, self.g, self.b, self.alpha])
+    }
+
+    /// Parse a color as written in a style line.
+    ///
+    /// Accepts `&HAABBGGRR` hex, optionally with a trailing `&` or fewer digits, and the decimal
+    /// values used by SSA scripts. Like Libass, anything after the number is ignored, and an
+    /// unparsable value is black.
+    pub fn parse_style(value: &str) -> Self {
+        let value = value.trim();
+        let parsed = match strip_hex_prefix(value) {
+            Some(hex) => parse_hex_prefix(hex),
+            None => parse_decimal_prefix(value),
+        };
+        Self::from_abgr(parsed)
+    }
+
+    /// Format as written in a style line, `&HAABBGGRR`.
+    pub fn to_style_string(self) -> String {
+        format!("&H{:08X}", self.to_abgr())
+    }
+
+    /// Format as written in a color override tag, `&HBBGGRR&`.
+    ///
+    /// The alpha is not included, as it is set with separate tags.
+    pub fn to_override_string(self) -> String {
+        format!("&H{:02X}{:02X}{:02X}&", self.b, self.g, self.r)
+    }
+
+    /// Parse a color as written in HTML and CSS, as used by SRT, WebVTT and TTML.
+    ///
+    /// Accepts `#rgb`, `#rrggbb` and `#rrggbbaa`, with or without the `#`, `rgb()` and `rgba()`
+    /// with an alpha from 0 to 1, and the basic named colors.
+    pub fn from_css(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Some(args) = value
+            .strip_prefix("rgba(")
+            .or_else(|| value.strip_prefix("rgb("))
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let mut args = args.split(',').map(str::trim);
+            let mut channel = || args.next()?.parse::<u8>().ok();
+            let (r, g, b) = (channel()?, channel()?, channel()?);
+            let opacity = match args.next() {
+                Some(alpha) => alpha
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|a| (0.0..=1.0).contains(a))?,
+                None => 1.0,
+            };
+            return Some(Self::rgba(r, g, b, transparency(opacity)));
+        }
+        if let Some(color) = named(value) {
+            return Some(color);
+        }
+
+        let hex = value.strip_prefix('#').unwrap_or(value);
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).ok();
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        match hex.len() {
+            3 => Some(Self::rgb(digit(0)? * 17, digit(1)? * 17, digit(2)? * 17)),
+            6 => Some(Self::rgb(byte(0)?, byte(2)?, byte(4)?)),
+            8 => Some(Self::rgba(byte(0)?, byte(2)?, byte(4)?, 255 - byte(6)?)),
+            _ => None,
+        }
+    }
+}
+
+/// Convert an opacity from 0 to 1 to an ASS transparency.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn transparency(opacity: f64) -> u8 {
+    // In range after clamping, and float to int casts saturate anyway.
+    ((1.0 - opacity.clamp(0.0, 1.0)) * 255.0).round() as u8
+}
+
+/// The basic HTML named colors.
+fn named(name: &str) -> Option<AssColor> {
+    let (r, g, b) = match name.to_ascii_lowercase().as_str() {
+        "transparent" => return Some(AssColor::rgba(0, 0, 0, 255)),
+        "black" => (0, 0, 0),
+        "silver" => (192, 192, 192),
+        "gray" | "grey" => (128, 128, 128),
+        "white" => (255, 255, 255),
+        "maroon" => (128, 0, 0),
+        "red" => (255, 0, 0),
+        "purple" => (128, 0, 128),
+        "fuchsia" | "magenta" => (255, 0, 255),
+        "green" => (0, 128, 0),
+        "lime" => (0, 255, 0),
+        "olive" => (128, 128, 0),
+        "yellow" => (255, 255, 0),
+        "navy" => (0, 0, 128),
+        "blue" => (0, 0, 255),
+        "teal" => (0, 128, 128),
+        "aqua" | "cyan" => (0, 255, 255),
+        "orange" => (255, 165, 0),
+        _ => return None,
+    };
+    Some(AssColor::rgb(r, g, b))
+}
+
+impl fmt::Display for AssColor {
+    /// Formats as written in a style line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "&H{:08X}", self.to_abgr())
+    }
+}
+
+/// Remove the `&H` or `H` prefix of an ASS hex value.
+pub(crate) fn strip_hex_prefix(value: &str) -> Option<&str> {
+    let value = value.strip_prefix('&').unwrap_or(value);
+    value.strip_prefix('H').or_else(|| value.strip_prefix('h'))
+}
+
+/// Parse the leading hex digits of `value`, ignoring anything after them.
+///
+/// Overlong values keep their lowest 32 bits.
+pub(crate) fn parse_hex_prefix(value: &str) -> u32 {
+    value
+        .chars()
+        .map_while(|c| c.to_digit(16))
+        .fold(0, |acc, digit| (acc << 4) | digit)
+}
+
+/// Parse the leading decimal digits of `value`, ignoring anything after them.
+fn parse_decimal_prefix(value: &str) -> u32 {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let parsed = digits
+        .chars()
+        .map_while(|c| c.to_digit(10))
+        .fold(0u32, |acc, digit| acc.wrapping_mul(10).wrapping_add(digit));
+    if negative {
+        parsed.wrapping_neg()
+    } else {
+        parsed
+    }
+}
@@ -0,0 +1,545 @@
+//! Vector drawings
+//!
+//! Text in drawing mode, after `\p1`, and drawing `\clip`s are made of commands such as
+//! `m 0 0 l 100 0 100 100`. [`DrawingPath::parse`] reads them in to [`Segment`]s as leniently as
+//! Libass does, and [`DrawingPath`] displays as commands again, so paths can be generated or
+//! transformed before being put back in event text.
+use std::fmt::{self, Write};
+
+/// A point in drawing coordinates.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Point {
+    #[allow(missing_docs)]
+    pub x: f64,
+    #[allow(missing_docs)]
+    pub y: f64,
+}
+
+impl Point {
+    /// Construct a point.
+    pub const fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+/// A drawing command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    /// `m`, start a new figure, closing the current one.
+    Move(Point),
+    /// `n`, move without closing the current figure.
+    MoveNoClose(Point),
+    /// `l`, a straight line.
+    Line(Point),
+    /// `b`, a cubic Bézier curve with two control points and an end point.
+    Bezier(Point, Point, Point),
+    /// `s`, a cubic B-spline, with at least three control points after the current point.
+    ///
+    /// Points after the third are written with `p`, which extends the spline.
+    Spline(Vec<Point>),
+    /// `c`, close the preceding B-spline back to its start.
+    Close,
+}
+
+/// The extent of a drawing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoundingBox {
+    /// The top left corner.
+    pub min: Point,
+    /// The bottom right corner.
+    pub max: Point,
+}
+
+impl BoundingBox {
+    #[allow(missing_docs)]
+    pub fn width(&self) -> f64 {
+        self.max.x - self.min.x
+    }
+
+    #[allow(missing_docs)]
+    pub fn height(&self) -> f64 {
+        self.max.y - self.min.y
+    }
+
+    /// Grow to include a point.
+    const fn include(&mut self, point: Point) {
+        self.min.x = self.min.x.min(point.x);
+        self.min.y = self.min.y.min(point.y);
+        self.max.x = self.max.x.max(point.x);
+        self.max.y = self.max.y.max(point.y);
+    }
+}
+
+/// A vector drawing as a list of commands.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DrawingPath {
+    /// The commands in order.
+    pub segments: Vec<Segment>,
+}
+
+impl DrawingPath {
+    /// Create an empty drawing.
+    pub const fn new() -> Self {
+        Self {
+            segments: Vec::new(),
+        }
+    }
+
+    /// Parse drawing commands.
+    ///
+    /// Never fails, as Libass skips whatever it doesn't understand: unknown characters, an odd
+    /// coordinate left over at the end of a command, and curves without enough points are
+    /// ignored. Coordinates after a command letter repeat it, so `l 0 0 10 10` is two lines.
+    pub fn parse(commands: &str) -> Self {
+        let mut parser = Parser::default();
+        let mut rest = commands;
+        while let Some(c) = rest.chars().next() {
+            if let Some((value, len)) = number(rest) {
+                parser.number(value);
+                rest = &rest[len..];
+                continue;
+            }
+            if c.is_ascii_alphabetic() {
+                parser.command(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+        parser.flush();
+        parser.path
+    }
+
+    /// Add an `m` command.
+    pub fn move_to(mut self, x: f64, y: f64) -> Self {
+        self.segments.push(Segment::Move(Point::new(x, y)));
+        self
+    }
+
+    /// Add an `l` command.
+    pub fn line_to(mut self, x: f64, y: f64) -> Self {
+        self.segments.push(Segment::Line(Point::new(x, y)));
+        self
+    }
+
+    /// Add a `b` command.
+    pub fn bezier_to(mut self, control1: Point, control2: Point, end: Point) -> Self {
+        self.segments.push(Segment::Bezier(control1, control2, end));
+        self
+    }
+
+    /// Add an `s` command, extended with `p` for points after the third.
+    pub fn spline_to(mut self, points: Vec<Point>) -> Self {
+        self.segments.push(Segment::Spline(points));
+        self
+    }
+
+    /// Add a `c` command.
+    pub fn close(mut self) -> Self {
+        self.segments.push(Segment::Close);
+        self
+    }
+
+    /// Apply a function to every point.
+    ///
+    /// Curves are transformed exactly by any affine transform, such as those below.
+    pub fn map_points(mut self, mut f: impl FnMut(Point) -> Point) -> Self {
+        for segment in &mut self.segments {
+            match segment {
+                Segment::Move(point) | Segment::MoveNoClose(point) | Segment::Line(point) => {
+                    *point = f(*point);
+                }
+                Segment::Bezier(control1, control2, end) => {
+                    *control1 = f(*control1);
+                    *control2 = f(*control2);
+                    *end = f(*end);
+                }
+                Segment::Spline(points) => {
+                    for point in points {
+                        *point = f(*point);
+                    }
+                }
+                Segment::Close => {}
+            }
+        }
+        self
+    }
+
+    /// Move every point.
+    pub fn translate(self, dx: f64, dy: f64) -> Self {
+        self.map_points(|point| Point::new(point.x + dx, point.y + dy))
+    }
+
+    /// Scale around the origin.
+    pub fn scale(self, sx: f64, sy: f64) -> Self {
+        self.map_points(|point| Point::new(point.x * sx, point.y * sy))
+    }
+
+    /// Rotate around the origin, counterclockwise as displayed like `\frz`.
+    pub fn rotate(self, degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        // The y axis points down, so this is a clockwise rotation in drawing coordinates.
+        self.map_points(|point| {
+            Point::new(point.x * cos + point.y * sin, point.y * cos - point.x * sin)
+        })
+    }
+
+    /// The smallest box containing every point and the curves between them, or `None` for an
+    /// empty drawing.
+    ///
+    /// Curves are measured exactly rather than by their control points, so a curve that bulges
+    /// less than its control points gives a tighter box.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut bbox: Option<BoundingBox> = None;
+        let mut include = |point: Point| match &mut bbox {
+            Some(bbox) => bbox.include(point),
+            None => {
+                bbox = Some(BoundingBox {
+                    min: point,
+                    max: point,
+                });
+            }
+        };
+
+        let mut pen = Point::default();
+        for (index, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Move(point) | Segment::MoveNoClose(point) | Segment::Line(point) => {
+                    include(*point);
+                    pen = *point;
+                }
+                Segment::Bezier(control1, control2, end) => {
+                    cubic_bounds([pen, *control1, *control2, *end], &mut include);
+                    pen = *end;
+                }
+                Segment::Spline(points) => {
+                    let mut controls = vec![pen];
+                    controls.extend_from_slice(points);
+                    // Libass closes a spline by repeating its first three control points.
+                    if matches!(self.segments.get(index + 1), Some(Segment::Close)) {
+                        controls.extend_from_within(..3.min(controls.len()));
+                    }
+                    for window in controls.windows(4) {
+                        let bezier = spline_to_bezier([window[0], window[1], window[2], window[3]]);
+                        cubic_bounds(bezier, &mut include);
+                        pen = bezier[3];
+                    }
+                }
+                Segment::Close => {}
+            }
+        }
+        bbox
+    }
+}
+
+impl fmt::Display for DrawingPath {
+    /// Formats as drawing commands, with coordinates rounded to three decimal places.
+    ///
+    /// Consecutive lines and Bézier curves share a command letter.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut previous = None;
+        let mut first = true;
+        let mut write_command = |f: &mut fmt::Formatter<'_>, command: char| {
+            let repeat = previous == Some(command) && matches!(command, 'l' | 'b');
+            previous = Some(command);
+            if repeat {
+                return Ok(());
+            }
+            if !first {
+                f.write_char(' ')?;
+            }
+            first = false;
+            f.write_char(command)
+        };
+        let point = |f: &mut fmt::Formatter<'_>, point: &Point| {
+            write!(f, " {} {}", round(point.x), round(point.y))
+        };
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Move(p) => {
+                    write_command(f, 'm')?;
+                    point(f, p)?;
+                }
+                Segment::MoveNoClose(p) => {
+                    write_command(f, 'n')?;
+                    point(f, p)?;
+                }
+                Segment::Line(p) => {
+                    write_command(f, 'l')?;
+                    point(f, p)?;
+                }
+                Segment::Bezier(control1, control2, end) => {
+                    write_command(f, 'b')?;
+                    point(f, control1)?;
+                    point(f, control2)?;
+                    point(f, end)?;
+                }
+                Segment::Spline(points) => {
+                    write_command(f, 's')?;
+                    for (index, p) in points.iter().enumerate() {
+                        if index == 3 {
+                            f.write_str(" p")?;
+                        }
+                        point(f, p)?;
+                    }
+                }
+                Segment::Close => write_command(f, 'c')?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a path from the numbers and command letters of a drawing.
+#[derive(Debug, Default)]
+struct Parser {
+    #[allow(clippy::missing_docs_in_private_items)]
+    path: DrawingPath,
+    /// The current command letter.
+    command: Option<char>,
+    /// An x coordinate waiting for its y.
+    x: Option<f64>,
+    /// Points of the current command that don't yet make a whole segment.
+    points: Vec<Point>,
+    /// Whether points extend the spline at the end of the path, after `p`.
+    extending: bool,
+}
+
+impl Parser {
+    /// Start a new command.
+    fn command(&mut self, command: char) {
+        match command {
+            'm' | 'n' | 'l' | 'b' | 's' => {
+                self.flush();
+                self.command = Some(command);
+            }
+            'p' if self.command == Some('s') => {
+                self.flush();
+                self.extending = matches!(self.path.segments.last(), Some(Segment::Spline(_)));
+                self.command = Some('s');
+            }
+            'c' => {
+                self.flush();
+                self.path.segments.push(Segment::Close);
+                self.command = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Add a coordinate.
+    fn number(&mut self, value: f64) {
+        let Some(command) = self.command else {
+            return;
+        };
+        let Some(x) = self.x.take() else {
+            self.x = Some(value);
+            return;
+        };
+        let point = Point::new(x, value);
+        let segments = &mut self.path.segments;
+        match command {
+            'm' => segments.push(Segment::Move(point)),
+            'n' => segments.push(Segment::MoveNoClose(point)),
+            'l' => segments.push(Segment::Line(point)),
+            'b' => {
+                self.points.push(point);
+                if let [control1, control2, end] = self.points[..] {
+                    segments.push(Segment::Bezier(control1, control2, end));
+                    self.points.clear();
+                }
+            }
+            _ => match segments.last_mut() {
+                Some(Segment::Spline(points)) if self.extending => points.push(point),
+                _ => self.points.push(point),
+            },
+        }
+    }
+
+    /// Finish the current command, dropping an odd coordinate or an incomplete curve.
+    fn flush(&mut self) {
+        self.x = None;
+        let points = std::mem::take(&mut self.points);
+        if self.command == Some('s') && points.len() >= 3 {
+            self.path.segments.push(Segment::Spline(points));
+        }
+        self.extending = false;
+    }
+}
+
+/// Parse a number at the start of drawing commands, returning it and its length in bytes.
+fn number(text: &str) -> Option<(f64, usize)> {
+    let sign = usize::from(text.starts_with(['-', '+']));
+    let len = sign
+        + text[sign..]
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(text.len() - sign);
+    let digits = &text[sign..len];
+    if !digits.bytes().any(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((text[..len].parse().ok()?, len))
+}
+
+/// Convert four uniform cubic B-spline control points to a Bézier curve.
+fn spline_to_bezier([p0, p1, p2, p3]: [Point; 4]) -> [Point; 4] {
+    let mix = |a: f64, b: f64, c: f64, d: f64| {
+        Point::new(
+            (a * p0.x + b * p1.x + c * p2.x + d * p3.x) / 6.0,
+            (a * p0.y + b * p1.y + c * p2.y + d * p3.y) / 6.0,
+        )
+    };
+    [
+        mix(1.0, 4.0, 1.0, 0.0),
+        mix(0.0, 4.0, 2.0, 0.0),
+        mix(0.0, 2.0, 4.0, 0.0),
+        mix(0.0, 1.0, 4.0, 1.0),
+    ]
+}
+
+/// Include a cubic Bézier curve in a bounding box, using its extremes on each axis.
+fn cubic_bounds(points: [Point; 4], include: &mut impl FnMut(Point)) {
+    let [p0, p1, p2, p3] = points;
+    let at = |t: f64| {
+        let u = 1.0 - t;
+        let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+        Point::new(
+            a * p0.x + b * p1.x + c * p2.x + d * p3.x,
+            a * p0.y + b * p1.y + c * p2.y + d * p3.y,
+        )
+    };
+    include(p0);
+    include(p3);
+    for axis in [|p: Point| p.x, |p: Point| p.y] {
+        let (v0, v1, v2, v3) = (axis(p0), axis(p1), axis(p2), axis(p3));
+        // The derivative divided by 3 is a t^2 + b t + c.
+        let a = v3 - 3.0 * v2 + 3.0 * v1 - v0;
+        let b = 2.0 * (v2 - 2.0 * v1 + v0);
+        let c = v1 - v0;
+        let roots = if a.abs() < 1e-12 {
+            [(b.abs() > 1e-12).then(|| -c / b), None]
+        } else {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant < 0.0 {
+                [None, None]
+            } else {
+                let root = discriminant.sqrt();
+                [Some((-b + root) / (2.0 * a)), Some((-b - root) / (2.0 * a))]
+            }
+        };
+        for t in roots.into_iter().flatten() {
+            if t > 0.0 && t < 1.0 {
+                include(at(t));
+            }
+        }
+    }
+}
+
+/// Round a coordinate to three decimal places, without a negative zero.
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0 + 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A point from integer coordinates.
+    fn p(x: i32, y: i32) -> Point {
+        Point::new(f64::from(x), f64::from(y))
+    }
+
+    #[test]
+    fn parse() {
+        let path =
+            DrawingPath::parse("m 0 0 l 10 0 10 10 b 1 2 3 4 5 6 s 1 1 2 2 3 3 p 4 4 c n -1.5 +2");
+        assert_eq!(
+            path.segments,
+            [
+                Segment::Move(p(0, 0)),
+                Segment::Line(p(10, 0)),
+                Segment::Line(p(10, 10)),
+                Segment::Bezier(p(1, 2), p(3, 4), p(5, 6)),
+                Segment::Spline(vec![p(1, 1), p(2, 2), p(3, 3), p(4, 4)]),
+                Segment::Close,
+                Segment::MoveNoClose(Point::new(-1.5, 2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        // An odd coordinate, an incomplete curve, junk and numbers before a command are skipped.
+        let path = DrawingPath::parse("5 5 m 1 2 3 x l,4,5 b 1 1 2 2 s 1 1 2 2 l 6 - . 7 é 8");
+        assert_eq!(
+            path.segments,
+            [
+                Segment::Move(p(1, 2)),
+                Segment::Line(p(4, 5)),
+                Segment::Line(p(6, 7)),
+            ]
+        );
+        assert_eq!(DrawingPath::parse("p 1 2 3 4").segments, []);
+        assert_eq!(DrawingPath::parse("").to_string(), "");
+    }
+
+    #[test]
+    fn round_trip() {
+        for commands in [
+            "m 0 0 l 100 0 100 100 0 100",
+            "m 0 0 b 10 0 20 10 20 20 30 30 40 30 50 20 l 0 0",
+            "m 0 0 s 10 0 10 10 0 10 p 5 5 -5 5 c",
+            "n 0.5 -0.25 l 1.125 2",
+        ] {
+            let path = DrawingPath::parse(commands);
+            assert_eq!(path.to_string(), commands);
+            assert_eq!(DrawingPath::parse(&path.to_string()), path);
+        }
+        let path = DrawingPath::new()
+            .move_to(0.0, 0.0)
+            .line_to(1.0 / 3.0, -0.0001)
+            .spline_to(vec![p(1, 1), p(2, 2), p(3, 3)])
+            .close();
+        assert_eq!(path.to_string(), "m 0 0 l 0.333 0 s 1 1 2 2 3 3 c");
+    }
+
+    #[test]
+    fn transforms() {
+        let path = DrawingPath::new()
+            .move_to(10.0, 0.0)
+            .bezier_to(p(0, 0), p(0, 10), p(10, 10));
+        assert_eq!(
+            path.clone()
+                .translate(1.0, -1.0)
+                .scale(2.0, 3.0)
+                .to_string(),
+            "m 22 -3 b 2 -3 2 27 22 27"
+        );
+        // Counterclockwise on screen, where y points down.
+        assert_eq!(path.rotate(90.0).to_string(), "m 0 -10 b 0 0 10 0 10 -10");
+    }
+
+    #[test]
+    fn bounding_box() {
+        assert_eq!(DrawingPath::new().bounding_box(), None);
+
+        let bbox = DrawingPath::parse("m 0 0 l 10 -5 n 20 5")
+            .bounding_box()
+            .unwrap();
+        assert_eq!((bbox.min, bbox.max), (p(0, -5), p(20, 5)));
+        assert_eq!((bbox.width(), bbox.height()), (20.0, 10.0));
+
+        // The curve only reaches three quarters of the way to its control points.
+        let bbox = DrawingPath::parse("m 0 0 b 0 -10 10 -10 10 0")
+            .bounding_box()
+            .unwrap();
+        assert_eq!((bbox.min, bbox.max), (Point::new(0.0, -7.5), p(10, 0)));
+
+        // A closed spline around a square stays inside its control points.
+        let bbox = DrawingPath::parse("m 0 0 s 6 0 6 6 0 6 c")
+            .bounding_box()
+            .unwrap();
+        assert!(bbox.min.x >= 0.0 && bbox.max.x <= 6.0 && bbox.max.y <= 6.0);
+        // Halfway along a side, the spline weights its two nearest corners by 23/48 each.
+        assert!((bbox.max.x - 5.75).abs() < 1e-9, "{bbox:?}");
+    }
+}
@@ -0,0 +1,359 @@
+//! Text encoding detection for subtitle files
+//!
+//! Libass needs to be told the codepage of a file that isn't UTF-8, which users rarely know for
+//! old subtitles. [`detect`] guesses it instead: from a byte order mark, then by checking whether
+//! the data looks like UTF-16 or is valid UTF-8, and otherwise by decoding it with each supported
+//! legacy encoding and scoring how plausible the resulting text is. The candidates are Shift_JIS,
+//! EUC-JP, GBK, Big5, EUC-KR, windows-1251, KOI8-R, windows-1252 and windows-1250.
+//!
+//! The scores look for characters that are common in each language, such as hiragana for
+//! Japanese or lower case letters for Cyrillic, and penalize ones that usually come from decoding
+//! with the wrong encoding, such as half width katakana. The guess is best with a few lines of
+//! text or more.
+//!
+//! Requires the `encoding` feature.
+use std::borrow::Cow;
+
+pub use encoding_rs::Encoding;
+use encoding_rs::{
+    BIG5, EUC_JP, EUC_KR, GBK, KOI8_R, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1250,
+    WINDOWS_1251, WINDOWS_1252,
+};
+
+use crate::{error::Result, library::Library, track::Track};
+
+/// Number of bytes checked for the zero bytes of UTF-16.
+const UTF16_SAMPLE: usize = 4096;
+
+/// Common simplified Chinese characters.
+const COMMON_SIMPLIFIED: &str = "的一是不了人我在有他这中大来上个国们到说和地也子时道出而要于就\
+    下得可你年生自会那后能对着事其里所去行过家十用发天如然作方成者多日都三小军二无同么经法当起与\
+    好看学进种将还分此心前面又定见只主没公从己吗呢吧啊她它么谢样没想知道";
+
+/// Common traditional Chinese characters.
+const COMMON_TRADITIONAL: &str = "的一是不了人我在有他這中大來上個國們到說和地也子時道出而要於就\
+    下得可你年生自會那後能對著事其裡所去行過家十用發天如然作方成者多日都三小軍二無同麼經法當起與\
+    好看學進種將還分此心前面又定見只主沒公從己嗎呢吧啊她它謝樣想知";
+
+/// Common Hangul syllables.
+const COMMON_HANGUL: &str = "이다는에의하고가를서지기로요아어한습니그도있을것수사나게들해보시자대\
+    라리마면우만내일때주없안여저오거까말했은된나요네죠뭐왜좀잘너우리";
+
+/// A detected encoding and how it was found.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DetectedEncoding {
+    /// The encoding.
+    pub encoding: &'static Encoding,
+    /// How the encoding was found.
+    pub method: DetectionMethod,
+}
+
+impl DetectedEncoding {
+    /// The WHATWG name of the encoding, such as `Shift_JIS` or `windows-1251`.
+    ///
+    /// Most names are also understood by iconv, so they can be passed to
+    /// [`Library::read_memory`] as the codepage.
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+}
+
+/// How an encoding was detected.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DetectionMethod {
+    /// From a byte order mark.
+    Bom,
+    /// The data is valid UTF-8, or has the zero bytes of mostly ASCII UTF-16.
+    Valid,
+    /// From scoring the text decoded with each candidate.
+    Guess {
+        /// How plausible the text was, from 0 to 1. Values below about 0.5 are unreliable.
+        score: f64,
+    },
+}
+
+/// Detect the encoding of subtitle data.
+///
+/// Data that no candidate decodes plausibly is reported as windows-1252, which can decode
+/// anything.
+pub fn detect(data: &[u8]) -> DetectedEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(data) {
+        return DetectedEncoding {
+            encoding,
+            method: DetectionMethod::Bom,
+        };
+    }
+    let valid = |encoding| DetectedEncoding {
+        encoding,
+        method: DetectionMethod::Valid,
+    };
+    // Checked first as ASCII UTF-16 is also valid UTF-8.
+    if let Some(encoding) = utf16(data) {
+        return valid(encoding);
+    }
+    if std::str::from_utf8(data).is_ok() {
+        return valid(UTF_8);
+    }
+
+    let mut best = (WINDOWS_1252, 0.0);
+    for (encoding, language) in candidates() {
+        let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(data) else {
+            continue;
+        };
+        let score = score(&text, language, encoding.is_single_byte());
+        if score > best.1 {
+            best = (encoding, score);
+        }
+    }
+    DetectedEncoding {
+        encoding: best.0,
+        method: DetectionMethod::Guess { score: best.1 },
+    }
+}
+
+/// Detect the encoding of subtitle data and decode it.
+///
+/// Any byte order mark is removed, and malformed sequences are replaced with U+FFFD.
+pub fn decode(data: &[u8]) -> (Cow<'_, str>, DetectedEncoding) {
+    let detected = detect(data);
+    let (text, _) = detected.encoding.decode_with_bom_removal(data);
+    (text, detected)
+}
+
+impl Library {
+    /// Parse a whole subtitle file in memory in to a new `Track`, detecting its encoding.
+    ///
+    /// The data is converted to UTF-8 before Libass parses it, so this works even if Libass was
+    /// built without iconv. See [`detect`](crate::encoding::detect) for how the encoding is found.
+    pub fn read_memory_detect(&self, data: &[u8]) -> Result<(Track, DetectedEncoding)> {
+        let (text, detected) = decode(data);
+        Ok((self.read_memory(text.as_bytes(), None)?, detected))
+    }
+}
+
+/// UTF-16 without a byte order mark, from the zero bytes of ASCII characters.
+fn utf16(data: &[u8]) -> Option<&'static Encoding> {
+    let sample = &data[..data.len().min(UTF16_SAMPLE) & !1];
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+    let (mut even, mut odd) = (0, 0);
+    for pair in sample.chunks_exact(2) {
+        even += usize::from(pair[0] == 0);
+        odd += usize::from(pair[1] == 0);
+    }
+    // Most subtitle text is ASCII, so at least a third of the characters should be.
+    if odd * 3 >= pairs && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 3 >= pairs && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// The language a candidate encoding is scored for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Language {
+    #[allow(clippy::missing_docs_in_private_items)]
+    Japanese,
+    #[allow(clippy::missing_docs_in_private_items)]
+    SimplifiedChinese,
+    #[allow(clippy::missing_docs_in_private_items)]
+    TraditionalChinese,
+    #[allow(clippy::missing_docs_in_private_items)]
+    Korean,
+    #[allow(clippy::missing_docs_in_private_items)]
+    Cyrillic,
+    /// Western European languages.
+    Western,
+    /// Central European languages.
+    Central,
+}
+
+/// Legacy encodings in order of preference when they score the same.
+fn candidates() -> [(&'static Encoding, Language); 9] {
+    [
+        (SHIFT_JIS, Language::Japanese),
+        (EUC_JP, Language::Japanese),
+        (GBK, Language::SimplifiedChinese),
+        (BIG5, Language::TraditionalChinese),
+        (EUC_KR, Language::Korean),
+        (WINDOWS_1251, Language::Cyrillic),
+        (KOI8_R, Language::Cyrillic),
+        (WINDOWS_1252, Language::Western),
+        (WINDOWS_1250, Language::Central),
+    ]
+}
+
+/// Score decoded text from -1 to 1 by the average weight of its non-ASCII characters, per byte.
+fn score(text: &str, language: Language, single_byte: bool) -> f64 {
+    let chars: Vec<char> = text.chars().collect();
+    let width = if single_byte { 1.0 } else { 2.0 };
+    let (mut total, mut bytes) = (0.0, 0.0);
+    for (index, c) in chars.iter().enumerate() {
+        if c.is_ascii() {
+            continue;
+        }
+        let latin = |index: Option<usize>| {
+            index
+                .and_then(|index| chars.get(index))
+                .is_some_and(char::is_ascii_alphabetic)
+        };
+        let next_to_latin = latin(index.checked_sub(1)) || latin(Some(index + 1));
+        total += weight(*c, language, next_to_latin) * width;
+        bytes += width;
+    }
+    if bytes == 0.0 {
+        0.0
+    } else {
+        total / bytes
+    }
+}
+
+/// How likely a character is in text in a language.
+///
+/// `next_to_latin` is whether an ASCII letter is next to it, which accented letters usually are
+/// and Cyrillic letters usually aren't.
+fn weight(c: char, language: Language, next_to_latin: bool) -> f64 {
+    if matches!(
+        c,
+        '\u{a0}' | '‘' | '’' | '“' | '”' | '–' | '—' | '…' | '«' | '»'
+    ) {
+        return 0.5;
+    }
+    let cjk_punctuation = matches!(c, '\u{3000}'..='\u{303f}' | '\u{ff01}'..='\u{ff5e}');
+    let han = matches!(c, '\u{4e00}'..='\u{9fff}');
+    match language {
+        Language::Japanese => match c {
+            '\u{3041}'..='\u{30ff}' => 1.0,
+            _ if cjk_punctuation => 1.0,
+            _ if han => 0.3,
+            _ => -1.0,
+        },
+        Language::SimplifiedChinese | Language::TraditionalChinese => {
+            let common = match language {
+                Language::SimplifiedChinese => COMMON_SIMPLIFIED,
+                _ => COMMON_TRADITIONAL,
+            };
+            match c {
+                _ if cjk_punctuation || common.contains(c) => 1.0,
+                _ if han => 0.1,
+                _ => -1.0,
+            }
+        }
+        Language::Korean => match c {
+            _ if cjk_punctuation || COMMON_HANGUL.contains(c) => 1.0,
+            '\u{ac00}'..='\u{d7a3}' => 0.3,
+            _ if han => 0.0,
+            _ => -1.0,
+        },
+        Language::Cyrillic => match c {
+            '\u{400}'..='\u{4ff}' if next_to_latin => -0.5,
+            '\u{400}'..='\u{4ff}' if c.is_lowercase() => 1.0,
+            '\u{400}'..='\u{4ff}' => 0.3,
+            '№' => 0.5,
+            _ => -1.0,
+        },
+        Language::Western | Language::Central => {
+            let letter = match language {
+                Language::Western => matches!(c, '\u{c0}'..='\u{ff}'),
+                _ => matches!(c, '\u{c0}'..='\u{17f}'),
+            };
+            match c {
+                '×' | '÷' => -1.0,
+                _ if letter && c.is_alphabetic() => f64::from(u8::from(next_to_latin)),
+                '¡' | '¿' | '°' | '©' | '®' | '€' | '·' => 0.5,
+                _ => -1.0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The encoding detected for text encoded with `encoding`.
+    fn detected(text: &str, encoding: &'static Encoding) -> &'static str {
+        let (data, _, unmappable) = encoding.encode(text);
+        assert!(
+            !unmappable,
+            "{text} can't be encoded as {}",
+            encoding.name()
+        );
+        detect(&data).name()
+    }
+
+    #[test]
+    fn bom() {
+        for (data, name) in [
+            (&b"\xef\xbb\xbfabc"[..], "UTF-8"),
+            (b"\xff\xfea\0", "UTF-16LE"),
+            (b"\xfe\xff\0a", "UTF-16BE"),
+        ] {
+            let detected = detect(data);
+            assert_eq!(
+                (detected.name(), detected.method),
+                (name, DetectionMethod::Bom)
+            );
+        }
+    }
+
+    #[test]
+    fn valid() {
+        let utf16 = |text: &str, big_endian: bool| -> Vec<u8> {
+            text.encode_utf16()
+                .flat_map(|unit| match big_endian {
+                    true => unit.to_be_bytes(),
+                    false => unit.to_le_bytes(),
+                })
+                .collect()
+        };
+        let text = "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Grüße, «мир»\n";
+        for (data, name) in [
+            (text.as_bytes().to_vec(), "UTF-8"),
+            (utf16(text, false), "UTF-16LE"),
+            (utf16(text, true), "UTF-16BE"),
+        ] {
+            let detected = detect(&data);
+            assert_eq!(
+                (detected.name(), detected.method),
+                (name, DetectionMethod::Valid)
+            );
+        }
+        assert_eq!(detect(b"").name(), "UTF-8");
+    }
+
+    #[test]
+    fn legacy() {
+        assert_eq!(
+            detected("こんにちは、世界。今日はいい天気ですね。", SHIFT_JIS),
+            "Shift_JIS"
+        );
+        assert_eq!(detected("我们今天去看电影，你想一起来吗？", GBK), "GBK");
+        assert_eq!(
+            detected("Привет, как дела? Всё хорошо, спасибо.", WINDOWS_1251),
+            "windows-1251"
+        );
+        assert_eq!(
+            detected(
+                "Où est la bibliothèque? Señor, ça va très bien.",
+                WINDOWS_1252
+            ),
+            "windows-1252"
+        );
+    }
+
+    #[test]
+    fn decoding() {
+        let (data, _, _) = WINDOWS_1251.encode("Да, конечно.");
+        let (text, detected) = decode(&data);
+        assert_eq!(text, "Да, конечно.");
+        assert!(matches!(detected.method, DetectionMethod::Guess { score } if score > 0.5));
+
+        let (text, detected) = decode(b"\xef\xbb\xbfText");
+        assert_eq!((&*text, detected.method), ("Text", DetectionMethod::Bom));
+    }
+}
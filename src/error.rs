@@ -0,0 +1,102 @@
+//! Crate error type
+//!
+use std::ffi::OsString;
+
+use thiserror::Error;
+
+use crate::track::Feature;
+
+/// Result type of fallible Ferass APIs.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by every fallible Ferass API.
+#[derive(Error, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// Libass failed to allocate memory.
+    #[error("Allocation failure in Libass function {function}")]
+    Alloc {
+        /// The Libass function that failed.
+        function: &'static str,
+    },
+    /// A path was not valid UTF-8.
+    ///
+    /// Because I don't want to deal with non-UTF-8 for now.
+    #[error("Invalid UTF-8 found in path {path:?}")]
+    PathNotUtf8 {
+        /// The offending path.
+        path: OsString,
+    },
+    /// A path contained a nul byte, so it cannot be passed to Libass as a C string.
+    #[error("Path {path:?} contains a nul byte at {position}")]
+    PathContainsNul {
+        /// The offending path.
+        path: String,
+        /// Byte offset of the first nul.
+        position: usize,
+    },
+    /// Input was too long for Libass to index.
+    #[error(
+        "Input of length {len} is too long for Libass function {function}, the maximum is {max}"
+    )]
+    InvalidLength {
+        /// Length of the input.
+        len: usize,
+        /// The longest input accepted.
+        max: usize,
+        /// The Libass function the input was for.
+        function: &'static str,
+    },
+    /// A track feature is not supported by this build of Libass.
+    #[error("Track feature {0:?} is not supported by Libass")]
+    UnsupportedFeature(Feature),
+    /// Libass failed to load subtitles.
+    #[error("Libass function {function} failed to load subtitles")]
+    LoadFailed {
+        /// The Libass function that failed.
+        function: &'static str,
+    },
+    /// The library still has other handles, tracks, or renderers referencing it.
+    #[error("Library is still referenced by other handles, tracks, or renderers")]
+    LibraryInUse,
+    /// A timestamp is outside of the range Libass can represent.
+    #[error("Timestamp is out of range for Libass")]
+    TimestampOutOfRange,
+    /// A frame rate was not a positive, finite number.
+    #[error("Invalid frame rate {0}")]
+    InvalidFrameRate(f64),
+    /// A script resolution was not positive.
+    #[error("Invalid script resolution {width}x{height}")]
+    InvalidResolution {
+        #[allow(missing_docs)]
+        width: i32,
+        #[allow(missing_docs)]
+        height: i32,
+    },
+    /// A YUV plane was too small for its frame, or its stride was shorter than a row.
+    #[error("YUV plane {plane} with stride {stride} and length {len} is too small for the frame")]
+    InvalidPlane {
+        /// Name of the plane, such as `Y` or `UV`.
+        plane: &'static str,
+        #[allow(missing_docs)]
+        stride: usize,
+        #[allow(missing_docs)]
+        len: usize,
+    },
+    /// Libass returned a value that doesn't correspond to any known variant of a type.
+    #[error("Failed to convert from int to {type_name}. Invalid value of {value} found instead.")]
+    InvalidValue {
+        /// The type being converted to.
+        type_name: &'static str,
+        /// The unknown value.
+        value: i32,
+    },
+    /// A TTML document was not well formed XML.
+    #[cfg(feature = "ttml")]
+    #[error("Failed to parse TTML: {0}")]
+    TtmlXml(#[from] roxmltree::Error),
+    /// A document's root element was not TTML's `<tt>`.
+    #[cfg(feature = "ttml")]
+    #[error("Root element <{0}> is not a TTML <tt> element")]
+    NotTtml(String),
+}
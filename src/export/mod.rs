@@ -0,0 +1,505 @@
+//! Exporters to plain subtitle formats
+//!
+//! SRT and WebVTT can only show text, so events are reduced to their text with italic, bold,
+//! underline and color kept as markup, and alignment or a `\pos` as the placement of the cue.
+//! Events shown at the same time in the same place, such as the layers of a typeset sign, are
+//! merged in to one cue, with identical text only kept once. Drawings and comments are dropped.
+use time::Duration;
+
+use crate::{
+    color::AssColor,
+    script::{EventKind, ScriptDocument, Style},
+    tags::{Item, Tag},
+};
+
+mod srt;
+mod vtt;
+
+/// Text formatting that both formats can show.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+struct Format {
+    #[allow(clippy::missing_docs_in_private_items)]
+    italic: bool,
+    #[allow(clippy::missing_docs_in_private_items)]
+    bold: bool,
+    #[allow(clippy::missing_docs_in_private_items)]
+    underline: bool,
+    /// The text color, `None` for the default white.
+    color: Option<AssColor>,
+}
+
+impl Format {
+    /// The formatting of a style.
+    fn of(style: Option<&Style>) -> Self {
+        let Some(style) = style else {
+            return Self::default();
+        };
+        let color = style.primary_color;
+        Self {
+            italic: style.italic,
+            bold: style.weight() >= 600,
+            underline: style.underline,
+            color: (AssColor { alpha: 0, ..color } != AssColor::WHITE).then_some(color),
+        }
+    }
+
+    /// The formatting attributes that are set, outermost first.
+    fn attributes(self) -> Vec<Attribute> {
+        let mut attributes = Vec::new();
+        if let Some(color) = self.color {
+            attributes.push(Attribute::Color(color));
+        }
+        if self.bold {
+            attributes.push(Attribute::Bold);
+        }
+        if self.italic {
+            attributes.push(Attribute::Italic);
+        }
+        if self.underline {
+            attributes.push(Attribute::Underline);
+        }
+        attributes
+    }
+}
+
+/// A markup element.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Attribute {
+    #[allow(clippy::missing_docs_in_private_items)]
+    Color(AssColor),
+    #[allow(clippy::missing_docs_in_private_items)]
+    Bold,
+    #[allow(clippy::missing_docs_in_private_items)]
+    Italic,
+    #[allow(clippy::missing_docs_in_private_items)]
+    Underline,
+}
+
+/// Text with the same formatting. Line breaks are `\n`.
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+    #[allow(clippy::missing_docs_in_private_items)]
+    text: String,
+    #[allow(clippy::missing_docs_in_private_items)]
+    format: Format,
+}
+
+/// Where a cue is shown.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Placement {
+    /// Numpad alignment.
+    alignment: i32,
+    /// A `\pos` or the start of a `\move`, as a fraction of the script resolution.
+    position: Option<(f64, f64)>,
+}
+
+/// Text shown over a span of time.
+#[derive(Debug, Clone, PartialEq)]
+struct Cue {
+    #[allow(clippy::missing_docs_in_private_items)]
+    start: Duration,
+    #[allow(clippy::missing_docs_in_private_items)]
+    end: Duration,
+    #[allow(clippy::missing_docs_in_private_items)]
+    placement: Placement,
+    /// Text of each merged event, in order.
+    texts: Vec<Vec<Run>>,
+}
+
+/// Convert the events of a document to cues, ordered by start time.
+fn cues(doc: &ScriptDocument) -> Vec<Cue> {
+    let play_res = play_res(doc);
+    let events: Vec<Cue> = doc
+        .events
+        .iter()
+        .filter(|event| event.kind == EventKind::Dialogue && event.end > event.start)
+        .filter_map(|event| {
+            let style = doc.style(&event.style);
+            let (runs, placement) = convert(doc, style, &event.text, play_res);
+            let has_text = runs.iter().any(|run| !run.text.trim().is_empty());
+            has_text.then(|| Cue {
+                start: event.start,
+                end: event.end,
+                placement,
+                texts: vec![runs],
+            })
+        })
+        .collect();
+
+    let mut times: Vec<Duration> = events
+        .iter()
+        .flat_map(|event| [event.start, event.end])
+        .collect();
+    times.sort();
+    times.dedup();
+
+    let mut by_start: Vec<usize> = (0..events.len()).collect();
+    by_start.sort_by_key(|&index| events[index].start);
+    let mut by_start = by_start.into_iter().peekable();
+
+    let mut cues: Vec<Cue> = Vec::new();
+    // Indices of the events shown during the current span, in document order.
+    let mut active: Vec<usize> = Vec::new();
+    // Indices in `cues` of the cues of the previous span, which are the only ones it can extend.
+    let mut previous_span: Vec<usize> = Vec::new();
+    for window in times.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        // Every start and end is a span boundary, so an event covers a span if it has started
+        // and not yet ended.
+        active.retain(|&index| events[index].end > start);
+        while let Some(index) = by_start.next_if(|&index| events[index].start <= start) {
+            let position = active.partition_point(|&active| active < index);
+            active.insert(position, index);
+        }
+
+        let mut merged: Vec<Cue> = Vec::new();
+        for event in active.iter().map(|&index| &events[index]) {
+            match merged
+                .iter_mut()
+                .find(|cue| cue.placement == event.placement)
+            {
+                Some(cue) if cue.texts.contains(&event.texts[0]) => {}
+                Some(cue) => cue.texts.push(event.texts[0].clone()),
+                None => merged.push(Cue {
+                    start,
+                    end,
+                    ..event.clone()
+                }),
+            }
+        }
+
+        let mut span = Vec::with_capacity(merged.len());
+        for cue in merged {
+            // Extend the cue from the last span if nothing changed.
+            let previous = previous_span.iter().copied().find(|&index| {
+                cues[index].placement == cue.placement && cues[index].texts == cue.texts
+            });
+            match previous {
+                Some(index) => {
+                    cues[index].end = end;
+                    span.push(index);
+                }
+                None => {
+                    span.push(cues.len());
+                    cues.push(cue);
+                }
+            }
+        }
+        previous_span = span;
+    }
+    cues.sort_by_key(|cue| cue.start);
+    cues
+}
+
+/// The script resolution, defaulting like Libass.
+fn play_res(doc: &ScriptDocument) -> (f64, f64) {
+    let (width, height) = match doc.info.play_res() {
+        (Some(width), Some(height)) => (width, height),
+        (Some(1280), None) => (1280, 1024),
+        (Some(width), None) => (width, width * 3 / 4),
+        (None, Some(1024)) => (1280, 1024),
+        (None, Some(height)) => (height * 4 / 3, height),
+        (None, None) => (384, 288),
+    };
+    (f64::from(width.max(1)), f64::from(height.max(1)))
+}
+
+/// Convert event text to formatted runs, and find its placement.
+fn convert(
+    doc: &ScriptDocument,
+    style: Option<&Style>,
+    text: &str,
+    play_res: (f64, f64),
+) -> (Vec<Run>, Placement) {
+    let base = Format::of(style);
+    let mut format = base;
+    let mut alignment = None;
+    let mut position = None;
+    let mut drawing = false;
+    let mut runs: Vec<Run> = Vec::new();
+
+    for item in crate::tags::parse(text).items {
+        match item.value {
+            Item::Text(text) if !drawing => {
+                let text = unescape(&text);
+                match runs.last_mut() {
+                    Some(run) if run.format == format => run.text.push_str(&text),
+                    _ => runs.push(Run { text, format }),
+                }
+            }
+            Item::Tag(tag) => match tag {
+                Tag::Italic(italic) => format.italic = italic.unwrap_or(base.italic),
+                Tag::Bold(bold) => format.bold = bold.map_or(base.bold, is_bold),
+                Tag::Underline(underline) => format.underline = underline.unwrap_or(base.underline),
+                Tag::Color { index: 1, color } => {
+                    format.color = match color {
+                        Some(color) => Some(color).filter(|color| *color != AssColor::WHITE),
+                        None => base.color,
+                    };
+                }
+                Tag::Reset(name) => {
+                    let reset = name.as_deref().and_then(|name| doc.style(name));
+                    format = reset.map_or(base, |style| Format::of(Some(style)));
+                }
+                Tag::Alignment(an) => {
+                    alignment.get_or_insert(an);
+                }
+                Tag::LegacyAlignment(a) => {
+                    alignment.get_or_insert(crate::script::legacy_to_numpad(a));
+                }
+                Tag::Position { x, y } | Tag::Move { from: (x, y), .. } => {
+                    position.get_or_insert((x / play_res.0, y / play_res.1));
+                }
+                Tag::Drawing(scale) => drawing = scale > 0,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    let placement = Placement {
+        alignment: alignment
+            .filter(|an| (1..=9).contains(an))
+            .or(style.map(|style| style.alignment))
+            .unwrap_or(2),
+        position,
+    };
+    (runs, placement)
+}
+
+/// Whether a `\b` value is bold, treating values other than 0 and 1 as weights.
+const fn is_bold(value: i32) -> bool {
+    match value {
+        0 => false,
+        1 => true,
+        weight => weight >= 600,
+    }
+}
+
+/// Replace the `\N`, `\n`, `\h`, `\{` and `\}` escapes of event text.
+///
+/// `\n` is a soft line break, which is only a break with `WrapStyle: 2`, so it becomes a space.
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('N') => out.push('\n'),
+            Some('n') => out.push(' '),
+            Some('h') => out.push('\u{a0}'),
+            Some(c @ ('{' | '}')) => out.push(*c),
+            // Inserted by `escape_text` to keep a literal backslash.
+            Some('\u{2060}') => {
+                out.push('\\');
+            }
+            _ => {
+                out.push('\\');
+                continue;
+            }
+        }
+        chars.next();
+    }
+    out
+}
+
+/// Write the runs of a cue as markup, with one line per line break.
+///
+/// Elements are closed and reopened as needed to nest properly, and blank lines are removed as
+/// they would end the cue.
+fn markup(
+    texts: &[Vec<Run>],
+    open: impl Fn(Attribute) -> String,
+    close: impl Fn(Attribute) -> String,
+    escape: impl Fn(&str) -> String,
+) -> String {
+    let mut out = String::new();
+    for (index, runs) in texts.iter().enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        let mut stack: Vec<Attribute> = Vec::new();
+        for run in runs.iter().filter(|run| !run.text.is_empty()) {
+            let wanted = run.format.attributes();
+            let keep = stack
+                .iter()
+                .zip(&wanted)
+                .take_while(|(open, wanted)| open == wanted)
+                .count();
+            while stack.len() > keep {
+                if let Some(attribute) = stack.pop() {
+                    out.push_str(&close(attribute));
+                }
+            }
+            for attribute in &wanted[keep..] {
+                out.push_str(&open(*attribute));
+                stack.push(*attribute);
+            }
+            out.push_str(&escape(&run.text));
+        }
+        while let Some(attribute) = stack.pop() {
+            out.push_str(&close(attribute));
+        }
+    }
+
+    let lines: Vec<&str> = out
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1000x500 script with the given events, one `Dialogue` or `Comment` line each.
+    pub(super) fn script(events: &str) -> ScriptDocument {
+        ScriptDocument::parse(&format!(
+            "[Script Info]\nPlayResX: 1000\nPlayResY: 500\n\n\
+             [V4+ Styles]\n\
+             Format: Name, Fontname, Fontsize, PrimaryColour, Bold, Italic, Alignment\n\
+             Style: Default,Arial,20,&H00FFFFFF,0,0,2\n\
+             Style: Top,Arial,20,&H0000FFFF,-1,0,8\n\n\
+             [Events]\n\
+             Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             {events}"
+        ))
+    }
+
+    /// The cues of a script as (ms, ms, texts of the merged events).
+    fn spans(doc: &ScriptDocument) -> Vec<(i128, i128, Vec<String>)> {
+        cues(doc)
+            .into_iter()
+            .map(|cue| {
+                let texts = cue
+                    .texts
+                    .iter()
+                    .map(|runs| runs.iter().map(|run| run.text.as_str()).collect())
+                    .collect();
+                (
+                    cue.start.whole_milliseconds(),
+                    cue.end.whole_milliseconds(),
+                    texts,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merging() {
+        let doc = script(
+            "Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Sign\n\
+             Dialogue: 1,0:00:01.00,0:00:03.00,Default,,0,0,0,,{\\bord5}Sign\n\
+             Dialogue: 0,0:00:02.00,0:00:04.00,Default,,0,0,0,,Second\n\
+             Dialogue: 0,0:00:02.00,0:00:03.00,Top,,0,0,0,,Top\n\
+             Comment: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Comment\n\
+             Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,{\\p1}m 0 0 l 10 0 10 10{\\p0}\n\
+             Dialogue: 0,0:00:05.00,0:00:05.00,Default,,0,0,0,,Empty\n",
+        );
+        let texts = |texts: &[&str]| texts.iter().map(|&text| text.to_owned()).collect();
+        assert_eq!(
+            spans(&doc),
+            [
+                (1000, 2000, texts(&["Sign"])),
+                (2000, 3000, texts(&["Sign", "Second"])),
+                (2000, 3000, texts(&["Top"])),
+                (3000, 4000, texts(&["Second"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsorted_events() {
+        // Merged texts stay in document order, whichever event starts first.
+        let doc = script(
+            "Dialogue: 0,0:00:02.00,0:00:06.00,Default,,0,0,0,,Long\n\
+             Dialogue: 0,0:00:07.00,0:00:08.00,Default,,0,0,0,,After gap\n\
+             Dialogue: 0,0:00:00.00,0:00:03.00,Default,,0,0,0,,Early\n",
+        );
+        let texts = |texts: &[&str]| texts.iter().map(|&text| text.to_owned()).collect();
+        assert_eq!(
+            spans(&doc),
+            [
+                (0, 2000, texts(&["Early"])),
+                (2000, 3000, texts(&["Long", "Early"])),
+                (3000, 6000, texts(&["Long"])),
+                (7000, 8000, texts(&["After gap"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn formatting_and_placement() {
+        let doc = script(
+            "Dialogue: 0,0:00:00.00,0:00:01.00,Top,,0,0,0,,\
+             {\\an1\\an9\\pos(250,100)}a{\\i1}b{\\b0\\1c&H0000FF&}c{\\r}d{\\rDefault}e\n",
+        );
+        let (runs, placement) =
+            convert(&doc, doc.style("Top"), &doc.events[0].text, (1000.0, 500.0));
+        assert_eq!(
+            placement,
+            Placement {
+                alignment: 1,
+                position: Some((0.25, 0.2)),
+            }
+        );
+        let yellow = Some(AssColor::rgb(255, 255, 0));
+        let formats: Vec<_> = runs
+            .iter()
+            .map(|run| (run.text.as_str(), run.format))
+            .collect();
+        let format = |italic, bold, color| Format {
+            italic,
+            bold,
+            underline: false,
+            color,
+        };
+        assert_eq!(
+            formats,
+            [
+                ("a", format(false, true, yellow)),
+                ("b", format(true, true, yellow)),
+                ("c", format(true, false, Some(AssColor::rgb(255, 0, 0)))),
+                ("d", format(false, true, yellow)),
+                ("e", format(false, false, None)),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape(r"a\Nb\nc\hd\{e\}\x\"), "a\nb c\u{a0}d{e}\\x\\");
+        assert_eq!(unescape(&crate::tags::escape_text(r"C:\new")), r"C:\new");
+    }
+
+    #[test]
+    fn markup_nesting() {
+        let run = |text: &str, italic, bold| Run {
+            text: text.to_owned(),
+            format: Format {
+                italic,
+                bold,
+                ..Format::default()
+            },
+        };
+        let texts = [
+            vec![
+                run("a", false, true),
+                run("b", true, true),
+                run("c\n\n", true, false),
+            ],
+            vec![run("d ", false, false)],
+        ];
+        let tag = |attribute| format!("<{attribute:?}>");
+        let end = |attribute| format!("</{attribute:?}>");
+        assert_eq!(
+            markup(&texts, tag, end, str::to_owned),
+            "<Bold>a<Italic>b</Italic></Bold><Italic>c\n</Italic>\nd"
+        );
+    }
+}
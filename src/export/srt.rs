@@ -0,0 +1,94 @@
+//! SubRip writer
+//!
+use std::io;
+
+use super::{cues, markup, Attribute};
+use crate::{script::ScriptDocument, timing::AssTime, track::Track};
+
+impl ScriptDocument {
+    /// Write the dialogue events as SubRip (`.srt`) subtitles.
+    ///
+    /// Italic, bold, underline and color become HTML tags, and other override tags are removed.
+    /// Alignment other than bottom center is kept as an `{\anN}` tag, which most players
+    /// understand. Events shown at the same time in the same place are merged in to one cue.
+    pub fn write_srt<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.to_srt_string().as_bytes())
+    }
+
+    /// Format the dialogue events as SubRip subtitles. See [`ScriptDocument::write_srt`].
+    pub fn to_srt_string(&self) -> String {
+        let mut out = String::new();
+        for (index, cue) in cues(self).iter().enumerate() {
+            let mut text = markup(&cue.texts, open, close, str::to_owned);
+            if cue.placement.alignment != 2 {
+                text.insert_str(0, &format!("{{\\an{}}}", cue.placement.alignment));
+            }
+            out.push_str(&format!(
+                "{}\n{} --> {}\n{text}\n\n",
+                index + 1,
+                AssTime::from(cue.start).to_srt_string(),
+                AssTime::from(cue.end).to_srt_string()
+            ));
+        }
+        out
+    }
+}
+
+impl Track {
+    /// Write the events loaded in to the track as SubRip subtitles.
+    ///
+    /// See [`ScriptDocument::write_srt`].
+    pub fn write_srt<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        ScriptDocument::from_track(self).write_srt(writer)
+    }
+}
+
+/// The start tag of an attribute.
+fn open(attribute: Attribute) -> String {
+    match attribute {
+        Attribute::Color(color) => {
+            format!(
+                "<font color=\"#{:02x}{:02x}{:02x}\">",
+                color.r, color.g, color.b
+            )
+        }
+        Attribute::Bold => "<b>".to_owned(),
+        Attribute::Italic => "<i>".to_owned(),
+        Attribute::Underline => "<u>".to_owned(),
+    }
+}
+
+/// The end tag of an attribute.
+fn close(attribute: Attribute) -> String {
+    match attribute {
+        Attribute::Color(_) => "</font>",
+        Attribute::Bold => "</b>",
+        Attribute::Italic => "</i>",
+        Attribute::Underline => "</u>",
+    }
+    .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{export::tests::script, script::ScriptDocument};
+
+    #[test]
+    fn cues() {
+        // Cues in different places overlap rather than being split.
+        let doc = script(
+            "Dialogue: 0,0:00:01.00,1:02:03.45,Default,,0,0,0,,{\\i1}One{\\i0}\\Ntwo \\{x\\} <tag>\n\
+             Dialogue: 0,0:00:02.00,0:00:03.00,Default,,0,0,0,,{\\an8\\b1\\c&HFF8000&}Top\n",
+        );
+        assert_eq!(
+            doc.to_srt_string(),
+            "1\n00:00:01,000 --> 01:02:03,450\n<i>One</i>\ntwo {x} <tag>\n\n\
+             2\n00:00:02,000 --> 00:00:03,000\n{\\an8}<font color=\"#0080ff\"><b>Top</b></font>\n\n"
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(ScriptDocument::new().to_srt_string(), "");
+    }
+}
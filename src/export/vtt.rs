@@ -0,0 +1,208 @@
+//! WebVTT writer
+//!
+use std::io;
+
+use super::{cues, markup, Attribute, Placement};
+use crate::{color::AssColor, script::ScriptDocument, timing::AssTime, track::Track};
+
+/// The color classes WebVTT defines, other than white which is the default.
+const COLOR_CLASSES: [(&str, AssColor); 7] = [
+    ("lime", AssColor::rgb(0, 255, 0)),
+    ("cyan", AssColor::rgb(0, 255, 255)),
+    ("red", AssColor::rgb(255, 0, 0)),
+    ("yellow", AssColor::rgb(255, 255, 0)),
+    ("magenta", AssColor::rgb(255, 0, 255)),
+    ("blue", AssColor::rgb(0, 0, 255)),
+    ("black", AssColor::rgb(0, 0, 0)),
+];
+
+impl ScriptDocument {
+    /// Write the dialogue events as WebVTT (`.vtt`) subtitles.
+    ///
+    /// Italic, bold and underline become tags, and colors become classes, using WebVTT's own
+    /// color classes where possible and a `STYLE` block for the others. Other override tags are
+    /// removed. Alignment and `\pos` become cue settings. Events shown at the same time in the
+    /// same place are merged in to one cue.
+    pub fn write_vtt<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.to_vtt_string().as_bytes())
+    }
+
+    /// Format the dialogue events as WebVTT subtitles. See [`ScriptDocument::write_vtt`].
+    pub fn to_vtt_string(&self) -> String {
+        let cues = cues(self);
+        let mut out = String::from("WEBVTT\n\n");
+
+        let mut colors: Vec<AssColor> = Vec::new();
+        for run in cues.iter().flat_map(|cue| cue.texts.iter().flatten()) {
+            if let Some(color) = run.format.color.map(opaque) {
+                if class(color).is_none() && !colors.contains(&color) {
+                    colors.push(color);
+                }
+            }
+        }
+        if !colors.is_empty() {
+            out.push_str("STYLE\n");
+            for color in colors {
+                let hex = hex(color);
+                out.push_str(&format!("::cue(.color_{hex}) {{ color: #{hex}; }}\n"));
+            }
+            out.push('\n');
+        }
+
+        for cue in &cues {
+            let text = markup(&cue.texts, open, close, escape);
+            out.push_str(&format!(
+                "{} --> {}{}\n{text}\n\n",
+                AssTime::from(cue.start).to_vtt_string(),
+                AssTime::from(cue.end).to_vtt_string(),
+                settings(cue.placement)
+            ));
+        }
+        out
+    }
+}
+
+impl Track {
+    /// Write the events loaded in to the track as WebVTT subtitles.
+    ///
+    /// See [`ScriptDocument::write_vtt`].
+    pub fn write_vtt<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        ScriptDocument::from_track(self).write_vtt(writer)
+    }
+}
+
+/// Cue settings for a placement, with a leading space if there are any.
+fn settings(placement: Placement) -> String {
+    let column = (placement.alignment - 1) % 3;
+    let row = (placement.alignment - 1) / 3;
+    let mut settings = Vec::new();
+    match placement.position {
+        Some((x, y)) => {
+            let line_align = match row {
+                0 => "end",
+                1 => "center",
+                _ => "start",
+            };
+            let position_align = match column {
+                0 => "line-left",
+                1 => "center",
+                _ => "line-right",
+            };
+            settings.push(format!("position:{}%,{position_align}", percent(x)));
+            settings.push(format!("line:{}%,{line_align}", percent(y)));
+        }
+        None => match row {
+            1 => settings.push("line:50%,center".to_owned()),
+            2 => settings.push("line:0".to_owned()),
+            _ => {}
+        },
+    }
+    match column {
+        0 => settings.push("align:left".to_owned()),
+        2 => settings.push("align:right".to_owned()),
+        _ => {}
+    }
+    settings
+        .iter()
+        .map(|setting| format!(" {setting}"))
+        .collect()
+}
+
+/// Format a fraction as a percentage with up to two decimal places.
+fn percent(fraction: f64) -> f64 {
+    (fraction.clamp(0.0, 1.0) * 10_000.0).round() / 100.0
+}
+
+/// The start tag of an attribute.
+fn open(attribute: Attribute) -> String {
+    match attribute {
+        Attribute::Color(color) => {
+            let color = opaque(color);
+            match class(color) {
+                Some(class) => format!("<c.{class}>"),
+                None => format!("<c.color_{}>", hex(color)),
+            }
+        }
+        Attribute::Bold => "<b>".to_owned(),
+        Attribute::Italic => "<i>".to_owned(),
+        Attribute::Underline => "<u>".to_owned(),
+    }
+}
+
+/// The end tag of an attribute.
+fn close(attribute: Attribute) -> String {
+    match attribute {
+        Attribute::Color(_) => "</c>",
+        Attribute::Bold => "</b>",
+        Attribute::Italic => "</i>",
+        Attribute::Underline => "</u>",
+    }
+    .to_owned()
+}
+
+/// Escape the characters WebVTT cue text reserves.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A color without its transparency, which cue text can't show.
+const fn opaque(color: AssColor) -> AssColor {
+    AssColor::rgb(color.r, color.g, color.b)
+}
+
+/// The WebVTT color class matching a color exactly.
+fn class(color: AssColor) -> Option<&'static str> {
+    COLOR_CLASSES
+        .iter()
+        .find(|(_, class)| *class == color)
+        .map(|(name, _)| *name)
+}
+
+/// A color as `rrggbb`.
+fn hex(color: AssColor) -> String {
+    format!("{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::script;
+
+    #[test]
+    fn cues() {
+        let doc = script(
+            "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,a < b && {\\u1}c\n\
+             Dialogue: 0,0:00:02.00,0:00:03.00,Default,,0,0,0,,{\\an7\\c&H00FFFF&}Yellow\n\
+             Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,\
+             {\\an3\\pos(500,125)\\1c&H80FF0000&}Blue{\\c&H123456&}Other\n",
+        );
+        assert_eq!(
+            doc.to_vtt_string(),
+            "WEBVTT\n\n\
+             STYLE\n::cue(.color_563412) { color: #563412; }\n\n\
+             00:00:01.000 --> 00:00:02.000\na &lt; b &amp;&amp; <u>c</u>\n\n\
+             00:00:02.000 --> 00:00:03.000 line:0 align:left\n<c.yellow>Yellow</c>\n\n\
+             00:00:03.000 --> 00:00:04.000 position:50%,line-right line:25%,end align:right\n\
+             <c.blue>Blue</c><c.color_563412>Other</c>\n\n"
+        );
+    }
+
+    #[test]
+    fn settings_for_alignment() {
+        let placement = |alignment| Placement {
+            alignment,
+            position: None,
+        };
+        assert_eq!(settings(placement(2)), "");
+        assert_eq!(settings(placement(4)), " line:50%,center align:left");
+        assert_eq!(
+            settings(Placement {
+                alignment: 5,
+                position: Some((1.5, 0.123_456)),
+            }),
+            " position:100%,center line:12.35%,center"
+        );
+    }
+}
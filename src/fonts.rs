@@ -0,0 +1,285 @@
+//! Fonts used by scripts
+//!
+//! Releases attach every font a script uses, so it renders the same everywhere.
+//! [`ScriptDocument::referenced_fonts`] finds them by following each event's style and its
+//! `\fn`, `\b`, `\i` and `\r` tags, and [`Renderer::check_fonts`] asks Libass which of them the
+//! renderer's font provider can find.
+use std::collections::BTreeSet;
+
+use time::Duration;
+
+use crate::{
+    error::Result,
+    render::Renderer,
+    script::{Event, EventKind, ScriptDocument, Style},
+    tags::{Item, Tag, TagBlock},
+    track::Track,
+};
+
+/// A font face used for text in a script.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReferencedFont {
+    /// Font family name, without the `@` of vertical text.
+    pub family: String,
+    /// Font weight, where 400 is regular and 700 is bold.
+    pub weight: i32,
+    #[allow(missing_docs)]
+    pub italic: bool,
+}
+
+impl ReferencedFont {
+    /// The font of a style.
+    fn of(style: &Style) -> Self {
+        Self {
+            family: family(&style.font_name),
+            weight: style.weight(),
+            italic: style.italic,
+        }
+    }
+}
+
+/// Which fonts a font provider can find.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FontReport {
+    /// Fonts that were found.
+    pub found: Vec<ReferencedFont>,
+    /// Fonts that Libass would replace with a fallback.
+    pub missing: Vec<ReferencedFont>,
+}
+
+impl ScriptDocument {
+    /// Every font used to show text, sorted and without duplicates.
+    ///
+    /// Only dialogue events with visible text are followed, so fonts of unused styles and of
+    /// drawings aren't included. Events with a style that doesn't exist use `Default`, or Libass'
+    /// default style if there is none.
+    pub fn referenced_fonts(&self) -> Vec<ReferencedFont> {
+        let fallback = Style::default();
+        let style = |name: &str| {
+            self.style(name)
+                .or_else(|| self.style("Default"))
+                .unwrap_or(&fallback)
+        };
+
+        let mut fonts = BTreeSet::new();
+        for event in self.events.iter().filter(|e| e.kind == EventKind::Dialogue) {
+            let base = ReferencedFont::of(style(&event.style));
+            let mut font = base.clone();
+            let mut drawing = false;
+            for item in event.parse_text().items {
+                match item.value {
+                    Item::Text(text) if !drawing && has_glyphs(&text) => {
+                        fonts.insert(font.clone());
+                    }
+                    Item::Tag(tag) => match tag {
+                        Tag::FontName(name) => {
+                            font.family = name.map_or_else(|| base.family.clone(), |n| family(&n));
+                        }
+                        Tag::Bold(bold) => font.weight = bold.map_or(base.weight, weight),
+                        Tag::Italic(italic) => font.italic = italic.unwrap_or(base.italic),
+                        Tag::Reset(name) => {
+                            font = match name.as_deref().and_then(|name| self.style(name)) {
+                                Some(style) => ReferencedFont::of(style),
+                                None => base.clone(),
+                            };
+                        }
+                        Tag::Drawing(scale) => drawing = scale > 0,
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+        }
+        fonts.into_iter().collect()
+    }
+}
+
+impl Track {
+    /// Every font used to show text, the same as [`ScriptDocument::referenced_fonts`].
+    pub fn referenced_fonts(&self) -> Vec<ReferencedFont> {
+        ScriptDocument::from_track(self).referenced_fonts()
+    }
+}
+
+impl Renderer {
+    /// Find which fonts the renderer's font provider and the library's fonts can supply.
+    ///
+    /// Each font is looked up by rendering a line with it in a new renderer with the same
+    /// settings, and reading the messages Libass logs about which font it chose. Libass has no
+    /// API to look a font up directly. A family only needs to exist for a font to be found, as
+    /// Libass makes a bold or italic face itself if there isn't one. Fonts must have been set up
+    /// with `Renderer::set_fonts`, otherwise nothing is found.
+    ///
+    /// Returns `Error::Alloc` if the renderer or track could not be allocated.
+    pub fn check_fonts(&self, fonts: &[ReferencedFont]) -> Result<FontReport> {
+        let renderer = self.duplicate()?;
+        renderer.set_frame_size(640, 360);
+        renderer.set_storage_size(640, 360);
+
+        let mut doc = ScriptDocument::new();
+        doc.info.set("PlayResX", "640");
+        doc.info.set("PlayResY", "360");
+        doc.styles.push(Style::default());
+        for (second, font) in (0..).zip(fonts) {
+            let block = TagBlock::new()
+                .font_name(&font.family)
+                .tag(Tag::Bold(Some(font.weight)))
+                .italic(font.italic);
+            doc.events.push(Event {
+                start: Duration::seconds(second),
+                end: Duration::seconds(second + 1),
+                text: block.apply_to("Aa"),
+                ..Event::default()
+            });
+        }
+        let track = doc.to_track(self.library())?;
+
+        let mut report = FontReport::default();
+        for (second, font) in (0..).zip(fonts) {
+            // A repeated font is cached by the renderer, so isn't looked up again.
+            if report.found.contains(font) || report.missing.contains(font) {
+                continue;
+            }
+            let timestamp = Duration::seconds(second) + Duration::milliseconds(500);
+            let (rendered, messages) = self
+                .library()
+                .capture_messages(|| renderer.render_frame(&track, &timestamp, &mut None));
+            rendered?;
+            match lookup(&messages, font) {
+                Some(true) => report.found.push(font.clone()),
+                _ => report.missing.push(font.clone()),
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Whether the messages of a font lookup show `font` was found, or `None` if it wasn't looked up.
+///
+/// This relies on the messages of Libass' font selection, which are the same from 0.13.0, where
+/// it was added, to at least 0.17. A successful lookup is reported as
+/// `fontselect: (family, bold, italic) -> path, index, name`. When the family isn't found,
+/// Libass first reports using a default or fallback family with `fontselect: Using ...`, or that
+/// it failed to find any. Every level is passed to callbacks, so this doesn't depend on which
+/// levels are shown.
+fn lookup(messages: &[String], font: &ReferencedFont) -> Option<bool> {
+    let request = format!("({}, ", font.family);
+    messages.iter().find_map(|message| {
+        let message = message.strip_prefix("fontselect: ")?;
+        if message.starts_with("Using ") || message.starts_with("failed") {
+            Some(false)
+        } else {
+            message.starts_with(&request).then_some(true)
+        }
+    })
+}
+
+/// A family name as Libass looks it up.
+fn family(name: &str) -> String {
+    name.trim().trim_start_matches('@').to_owned()
+}
+
+/// The font weight of a `\b` value.
+const fn weight(value: i32) -> i32 {
+    match value {
+        0 => 400,
+        1 => 700,
+        weight => weight,
+    }
+}
+
+/// Whether event text has anything other than whitespace and line breaks.
+fn has_glyphs(text: &str) -> bool {
+    let text = text
+        .replace("\\N", "")
+        .replace("\\n", "")
+        .replace("\\h", "");
+    !text.trim().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A font from its family, weight and whether it's italic.
+    fn font(family: &str, weight: i32, italic: bool) -> ReferencedFont {
+        ReferencedFont {
+            family: family.to_owned(),
+            weight,
+            italic,
+        }
+    }
+
+    #[test]
+    fn referenced_fonts() {
+        let doc = ScriptDocument::parse(
+            "[V4+ Styles]\n\
+             Format: Name, Fontname, Fontsize, Bold, Italic\n\
+             Style: Default,Arial,20,0,0\n\
+             Style: Sign,@Noto Sans,20,-1,0\n\
+             Style: Unused,Unused Font,20,0,0\n\n\
+             [Events]\n\
+             Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,0:00:00.00,0:00:01.00,Sign,,0,0,0,,Bold {\\b0}regular{\\i1}italic\n\
+             Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\\fnComic Sans}a{\\fn}b\
+             {\\b300}c{\\rSign\\i1}d{\\r}e\n\
+             Dialogue: 0,0:00:00.00,0:00:01.00,Missing,,0,0,0,,{\\fnOnly Spaces} \\N\\h{\\fn}x\n\
+             Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\\fnDrawing\\p1}m 0 0 l 1 1{\\p0}\n\
+             Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\\fnComment}text\n",
+        );
+        assert_eq!(
+            doc.referenced_fonts(),
+            [
+                font("Arial", 300, false),
+                font("Arial", 400, false),
+                font("Comic Sans", 400, false),
+                font("Noto Sans", 400, false),
+                font("Noto Sans", 400, true),
+                font("Noto Sans", 700, false),
+                font("Noto Sans", 700, true),
+            ]
+        );
+        assert_eq!(ScriptDocument::new().referenced_fonts(), []);
+    }
+
+    #[test]
+    fn lookup_messages() {
+        let arial = font("Arial", 400, false);
+        let found = [
+            "fontselect: (Arial, 400, 0) -> /usr/share/fonts/TTF/Arial.ttf, 0, ArialMT".to_owned(),
+        ];
+        assert_eq!(lookup(&found, &arial), Some(true));
+
+        let fallback = [
+            "fontselect: Using default font family: (Arial, 400, 0) -> \
+             /usr/share/fonts/noto/NotoSans-Regular.ttf, 0, NotoSans-Regular"
+                .to_owned(),
+            "fontselect: (Arial, 400, 0) -> /usr/share/fonts/noto/NotoSans-Regular.ttf, 0, \
+             NotoSans-Regular"
+                .to_owned(),
+        ];
+        assert_eq!(lookup(&fallback, &arial), Some(false));
+
+        let failed = [
+            "fontselect: failed to find any fallback with glyph 0x41 for font: (Arial, 400, 0)"
+                .to_owned(),
+        ];
+        assert_eq!(lookup(&failed, &arial), Some(false));
+
+        // Messages about other fonts, such as from another line, aren't results for this one.
+        let other = [
+            "Glyph 0x41 not found, selecting one more font for (Arialic, 400, 0)".to_owned(),
+            "fontselect: (Arial Black, 400, 0) -> /fonts/ariblk.ttf, 0, Arial-Black".to_owned(),
+        ];
+        assert_eq!(lookup(&other, &arial), None);
+        assert_eq!(lookup(&[], &arial), None);
+    }
+
+    #[test]
+    fn names_and_weights() {
+        assert_eq!(family(" @MS Gothic "), "MS Gothic");
+        assert_eq!((weight(0), weight(1), weight(900)), (400, 700, 900));
+        assert!(!has_glyphs(r" \N\n\h "));
+        assert!(has_glyphs(r"\Na"));
+    }
+}
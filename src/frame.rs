@@ -0,0 +1,141 @@
+//! Rendered frames
+//!
+use imgref::{ImgRef, ImgVec};
+
+/// A single pixel with straight (not premultiplied) alpha.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+#[allow(missing_docs)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// An RGBA image that Libass output has been composited on to.
+///
+/// Pixels start fully transparent, so the frame can be blended on top of video afterwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgbaFrame {
+    /// Pixel buffer.
+    image: ImgVec<Rgba>,
+}
+
+impl RgbaFrame {
+    /// Create a fully transparent frame.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            image: ImgVec::new(vec![Rgba::default(); width * height], width, height),
+        }
+    }
+
+    /// Width in pixels.
+    pub const fn width(&self) -> usize {
+        self.image.width()
+    }
+
+    /// Height in pixels.
+    pub const fn height(&self) -> usize {
+        self.image.height()
+    }
+
+    /// Borrow the pixels.
+    pub fn as_img(&self) -> ImgRef<'_, Rgba> {
+        self.image.as_ref()
+    }
+
+    /// Take the pixel buffer.
+    pub fn into_img(self) -> ImgVec<Rgba> {
+        self.image
+    }
+
+    /// Make every pixel fully transparent again.
+    pub fn clear(&mut self) {
+        self.image.buf_mut().fill(Rgba::default());
+    }
+
+    /// Composite a list of Libass images on to the frame, in order.
+    ///
+    /// Parts of images that are outside of the frame are clipped.
+    ///
+    /// # Safety
+    ///
+    /// `image` must be null or the head of a list of images returned by `ass_render_frame` that
+    /// is still valid, i.e. the renderer has not been used or dropped since.
+    pub(crate) unsafe fn composite(&mut self, mut image: *const libass_sys::ASS_Image) {
+        while let Some(img) = image.as_ref() {
+            self.blend(img);
+            image = img.next;
+        }
+    }
+
+    /// Blend a single Libass alpha bitmap on to the frame.
+    ///
+    /// # Safety
+    ///
+    /// The bitmap of `img` must be valid for `stride * h` bytes.
+    unsafe fn blend(&mut self, img: &libass_sys::ASS_Image) {
+        let (Ok(width), Ok(height), Ok(stride)) = (
+            usize::try_from(img.w),
+            usize::try_from(img.h),
+            usize::try_from(img.stride),
+        ) else {
+            return;
+        };
+        if img.bitmap.is_null() || width == 0 || height == 0 {
+            return;
+        }
+        let bitmap = core::slice::from_raw_parts(img.bitmap, stride * (height - 1) + width);
+
+        // Color is RRGGBBAA, where AA is transparency rather than opacity.
+        let [r, g, b, transparency] = img.color.to_be_bytes();
+        let opacity = 255 - u32::from(transparency);
+
+        let (frame_width, frame_height) = (self.image.width(), self.image.height());
+        let stride_out = self.image.stride();
+        let pixels = self.image.buf_mut();
+        for row in 0..height {
+            let Some(y) = offset(img.dst_y, row).filter(|y| *y < frame_height) else {
+                continue;
+            };
+            for col in 0..width {
+                let Some(x) = offset(img.dst_x, col).filter(|x| *x < frame_width) else {
+                    continue;
+                };
+                let coverage = u32::from(bitmap[row * stride + col]);
+                let src_a = coverage * opacity / 255;
+                if src_a == 0 {
+                    continue;
+                }
+                let px = &mut pixels[y * stride_out + x];
+                *px = over(*px, [r, g, b], src_a);
+            }
+        }
+    }
+}
+
+/// Position of `index` pixels past `start`, if it isn't negative.
+pub(crate) fn offset(start: i32, index: usize) -> Option<usize> {
+    usize::try_from(start).ok()?.checked_add(index)
+}
+
+/// Porter-Duff "over" of a solid color with alpha `src_a` on to `dst`, with straight alpha.
+fn over(dst: Rgba, src: [u8; 3], src_a: u32) -> Rgba {
+    let dst_a = u32::from(dst.a);
+    // Output alpha scaled by 255.
+    let out_a = src_a * 255 + dst_a * (255 - src_a);
+    if out_a == 0 {
+        return Rgba::default();
+    }
+    let channel = |s: u8, d: u8| {
+        let c = (u32::from(s) * src_a * 255 + u32::from(d) * dst_a * (255 - src_a)) / out_a;
+        u8::try_from(c).unwrap_or(u8::MAX)
+    };
+    Rgba {
+        r: channel(src[0], dst.r),
+        g: channel(src[1], dst.g),
+        b: channel(src[2], dst.b),
+        a: u8::try_from((out_a + 127) / 255).unwrap_or(u8::MAX),
+    }
+}
@@ -0,0 +1,65 @@
+//! Importers for other subtitle formats
+//!
+//! Each importer converts to a [`ScriptDocument`], which can then be edited, saved, or loaded in
+//! to a [`Track`](crate::Track) to render through Libass.
+
+use crate::script::{ScriptDocument, Style};
+
+pub mod srt;
+#[cfg(feature = "ttml")]
+pub mod ttml;
+pub mod vtt;
+
+/// Options shared by every importer.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ImportOptions {
+    /// Style given to every imported event.
+    ///
+    /// Importers that create their own styles base them on this one.
+    pub style: Style,
+    /// `PlayResX` and `PlayResY` of the script, which style sizes and positions are relative to.
+    pub play_res: (i32, i32),
+}
+
+impl Default for ImportOptions {
+    /// The default style at 1920x1080, which is what Aegisub gives new scripts.
+    fn default() -> Self {
+        Self {
+            style: Style::default(),
+            play_res: (1920, 1080),
+        }
+    }
+}
+
+impl ImportOptions {
+    /// Set the default style.
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Set the script resolution.
+    pub const fn play_res(mut self, width: i32, height: i32) -> Self {
+        self.play_res = (width, height);
+        self
+    }
+
+    /// An empty document with the options' resolution and style.
+    pub(crate) fn document(&self) -> ScriptDocument {
+        let mut doc = ScriptDocument::new();
+        doc.info.set("PlayResX", self.play_res.0.to_string());
+        doc.info.set("PlayResY", self.play_res.1.to_string());
+        doc.info.set("WrapStyle", "0");
+        doc.info.set("ScaledBorderAndShadow", "yes");
+        doc.styles.push(self.style.clone());
+        doc
+    }
+}
+
+/// Round a position or size to whole pixels.
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) const fn to_px(value: f64) -> i32 {
+    // Float to int casts saturate, and subtitle coordinates are far from the limits.
+    value.round() as i32
+}
@@ -0,0 +1,297 @@
+//! SubRip (`.srt`) import
+//!
+//! Cues are found by their timing lines rather than their numbers, so missing, duplicated or out
+//! of order numbering is tolerated. The `<i>`, `<b>`, `<u>`, `<s>` and `<font>` HTML tags are
+//! converted to override tags, other HTML tags are removed, and ASS override blocks such as
+//! `{\an8}`, which many SRT files use, are kept.
+use time::Duration;
+
+use super::ImportOptions;
+use crate::{
+    color::AssColor,
+    error::Result,
+    library::Library,
+    script::{Event, ScriptDocument},
+    tags::{escape_text, Tag, TagBlock},
+    timing::AssTime,
+    track::Track,
+};
+
+/// Convert SRT text to a script, giving every event the options' style.
+pub fn parse(text: &str, options: &ImportOptions) -> ScriptDocument {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+    let mut cues: Vec<(Duration, Duration, Vec<&str>)> = Vec::new();
+    for line in text.lines() {
+        if let Some((start, end)) = timing(line) {
+            cues.push((start, end, Vec::new()));
+        } else if let Some((_, _, lines)) = cues.last_mut() {
+            lines.push(line);
+        }
+    }
+
+    let mut doc = options.document();
+    let count = cues.len();
+    for (index, (start, end, mut lines)) in cues.into_iter().enumerate() {
+        trim_blank(&mut lines);
+        // The number of the next cue.
+        if index + 1 < count && lines.last().is_some_and(|line| is_number(line)) {
+            lines.pop();
+            trim_blank(&mut lines);
+        }
+        lines.retain(|line| !line.trim().is_empty());
+
+        doc.events.push(Event {
+            start,
+            end,
+            style: options.style.name.clone(),
+            text: convert_markup(&lines.join("\n")),
+            ..Event::default()
+        });
+    }
+    doc
+}
+
+impl Track {
+    /// Import SRT text in to a new track. See [`parse`](crate::import::srt::parse).
+    pub fn from_srt(library: &Library, text: &str, options: &ImportOptions) -> Result<Track> {
+        parse(text, options).to_track(library)
+    }
+}
+
+/// Parse a `start --> end` timing line, ignoring any position after the end time.
+fn timing(line: &str) -> Option<(Duration, Duration)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    Some((
+        AssTime::parse_srt(start)?.to_duration(),
+        AssTime::parse_srt(end)?.to_duration(),
+    ))
+}
+
+/// Whether a line is a cue number.
+fn is_number(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Remove blank lines from the end.
+fn trim_blank(lines: &mut Vec<&str>) {
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+}
+
+/// Attributes of an open `<font>` tag that were applied.
+#[derive(Debug, Default)]
+struct Font {
+    /// From the `color` attribute.
+    color: Option<AssColor>,
+    /// From the `face` attribute.
+    face: Option<String>,
+}
+
+/// Convert the text of a cue to event text.
+fn convert_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut plain = String::new();
+    let mut fonts: Vec<Font> = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let element = match c {
+            '<' => rest.find('>').map(|end| (end, &rest[1..end])),
+            _ => None,
+        };
+        if let Some((end, element)) = element.filter(|(_, element)| is_element(element)) {
+            let block = html_tag(element, &mut fonts);
+            out.push_str(&escape_text(&plain));
+            plain.clear();
+            out.push_str(&block.to_string());
+            rest = &rest[end + 1..];
+            continue;
+        }
+        if rest.starts_with("{\\") {
+            if let Some(end) = rest.find('}') {
+                out.push_str(&escape_text(&plain));
+                plain.clear();
+                out.push_str(&rest[..=end]);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out.push_str(&escape_text(&plain));
+    out
+}
+
+/// Whether the text between `<` and `>` is an HTML tag rather than text such as `a < b > c`.
+fn is_element(element: &str) -> bool {
+    let name = element.strip_prefix('/').unwrap_or(element);
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+/// Convert an HTML tag to override tags. Unsupported tags give an empty block.
+fn html_tag(element: &str, fonts: &mut Vec<Font>) -> TagBlock {
+    let (closing, element) = match element.strip_prefix('/') {
+        Some(element) => (true, element.trim()),
+        None => (false, element.trim()),
+    };
+    let name_end = element
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(element.len());
+    let name = element[..name_end].to_ascii_lowercase();
+    let block = TagBlock::new();
+    match name.as_str() {
+        "i" => block.italic(!closing),
+        "b" => block.bold(!closing),
+        "u" => block.underline(!closing),
+        "s" => block.strike_out(!closing),
+        "font" if !closing => {
+            let attributes = &element[name_end..];
+            let font = Font {
+                color: attribute(attributes, "color").and_then(AssColor::from_css),
+                face: attribute(attributes, "face").map(str::to_owned),
+            };
+            let mut block = block;
+            if let Some(color) = font.color {
+                block = block.color(color);
+            }
+            if let Some(face) = &font.face {
+                block = block.font_name(face);
+            }
+            fonts.push(font);
+            block
+        }
+        "font" => {
+            let Some(font) = fonts.pop() else {
+                return block;
+            };
+            // Restore what the enclosing font tags set, or the style.
+            let mut block = block;
+            if font.color.is_some() {
+                let color = fonts.iter().rev().find_map(|font| font.color);
+                block = block.tag(Tag::Color { index: 1, color });
+            }
+            if font.face.is_some() {
+                let face = fonts.iter().rev().find_map(|font| font.face.clone());
+                block = match face {
+                    Some(face) => block.font_name(&face),
+                    None => block.tag(Tag::FontName(None)),
+                };
+            }
+            block
+        }
+        _ => block,
+    }
+}
+
+/// Find the value of an HTML attribute, quoted or not.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attributes;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].split_whitespace().last().unwrap_or("");
+        let after = rest[eq + 1..].trim_start();
+        let (value, remaining) = match after.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let inner = &after[1..];
+                let end = inner.find(quote).unwrap_or(inner.len());
+                (&inner[..end], &inner[(end + 1).min(inner.len())..])
+            }
+            _ => {
+                let end = after
+                    .find(|c: char| c.is_whitespace())
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        rest = remaining;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The start, end and text of every event.
+    fn cues(text: &str) -> Vec<(i64, i64, String)> {
+        parse(text, &ImportOptions::default())
+            .events
+            .into_iter()
+            .map(|event| {
+                let millis = |time| AssTime::from(time).as_millis();
+                (millis(event.start), millis(event.end), event.text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cues_and_numbering() {
+        let text = "\u{feff}1\r\n\
+                    00:00:01,000 --> 00:00:02,500\r\n\
+                    First line\r\n\
+                    second line\r\n\
+                    \r\n\
+                    7\r\n\
+                    00:00:03,000 --> 00:00:04,000 X1:10 X2:20 Y1:10 Y2:20\r\n\
+                    \r\n\
+                    Blank line above\r\n\
+                    42\r\n";
+        assert_eq!(
+            cues(text),
+            [
+                (1000, 2500, r"First line\Nsecond line".to_owned()),
+                // The last cue has no next cue, so a trailing number is text.
+                (3000, 4000, r"Blank line above\N42".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_numbers_and_blank_lines() {
+        let text = "00:00:00,000 --> 00:00:01,000\nOne\n00:00:01,000 --> 00:00:02,000\nTwo\n\n\n";
+        let cues = cues(text);
+        assert_eq!(cues.len(), 2);
+        assert_eq!(cues[1], (1000, 2000, "Two".to_owned()));
+    }
+
+    #[test]
+    fn malformed_timings() {
+        let text =
+            "1\n00:00:xx,000 --> 00:00:02,000\nNot a cue\n\n2\n00:00:03,000 --> nonsense\n\n\
+                    3\n00:00:05,000 --> 00:00:06,000\nCue\n";
+        // Lines before the first valid timing have nowhere to go.
+        assert_eq!(cues(text), [(5000, 6000, "Cue".to_owned())]);
+    }
+
+    #[test]
+    fn markup() {
+        assert_eq!(
+            convert_markup("<i>it</i> <B>bold</B> <u>u</u><s>s</s>"),
+            r"{\i1}it{\i0} {\b1}bold{\b0} {\u1}u{\u0}{\s1}s{\s0}"
+        );
+        assert_eq!(
+            convert_markup(r##"<font color="#FF0000" face='Arial'>red</font> plain"##),
+            r"{\c&H0000FF&\fnArial}red{\c\fn} plain"
+        );
+        assert_eq!(
+            convert_markup("<font color=red>a<font color=#00ff00>b</font>c</font>"),
+            r"{\c&H0000FF&}a{\c&H00FF00&}b{\c&H0000FF&}c{\c}"
+        );
+    }
+
+    #[test]
+    fn ass_blocks_and_escapes() {
+        assert_eq!(convert_markup(r"{\an8}Top"), r"{\an8}Top");
+        assert_eq!(convert_markup("a < b > c {curly}"), r"a < b > c \{curly\}");
+        assert_eq!(convert_markup("<span>gone</span>"), "gone");
+        assert_eq!(convert_markup("</font>"), "");
+    }
+}
@@ -0,0 +1,1063 @@
+//! TTML import, including DFXP and the IMSC1 text profile
+//!
+//! Each `<p>` becomes an event. Spans with their own timing split their paragraph in to an event
+//! per change, so roll-up and paint-on captions display as authored. Times are relative to the
+//! parent element, or in a `timeContainer="seq"` element, to the end of the previous child, which
+//! lasts until the parent ends if it has no `end` or `dur`. Text directly inside a `seq` element
+//! takes no time, so isn't shown. Clock times with frames, and offsets in frames and ticks, use
+//! the document's `ttp:frameRate`, `ttp:frameRateMultiplier`, `ttp:subFrameRate` and
+//! `ttp:tickRate`.
+//!
+//! A paragraph's computed styling, including what it inherits from its region, becomes an ASS
+//! style, and spans that differ from it get override tags. The region sets the event's margins,
+//! or a `\pos` for `tts:displayAlign="center"`. Pixel lengths are relative to the root
+//! `tts:extent`, or to the script resolution without one, and cell lengths use
+//! `ttp:cellResolution`. Properties a document doesn't set keep the values of the options' style.
+//!
+//! Requires the `ttml` feature.
+use std::collections::{BTreeMap, HashMap};
+
+use roxmltree::{Document, Node, ParsingOptions};
+use time::Duration;
+
+use super::{to_px, ImportOptions};
+use crate::{
+    color::AssColor,
+    error::{Error, Result},
+    library::Library,
+    script::{Event, ScriptDocument, Style},
+    tags::{escape_text, Tag, TagBlock},
+    timing::round_to_i64,
+    track::Track,
+};
+
+/// Namespace of `xml:id` and `xml:space`.
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Seconds to show unbounded content that starts after every bounded event.
+const UNBOUNDED_DURATION: f64 = 5.0;
+
+/// Properties that only apply to regions, or to the element they are set on.
+const NOT_INHERITED: [&str; 9] = [
+    "origin",
+    "extent",
+    "position",
+    "displayAlign",
+    "padding",
+    "showBackground",
+    "zIndex",
+    "opacity",
+    "display",
+];
+
+/// Convert a TTML document to a script.
+///
+/// Fails if the text isn't well formed XML or its root isn't a `<tt>` element.
+pub fn parse(text: &str, options: &ImportOptions) -> Result<ScriptDocument> {
+    let xml = Document::parse_with_options(
+        text,
+        ParsingOptions {
+            allow_dtd: true,
+            ..ParsingOptions::default()
+        },
+    )?;
+    let root = xml.root_element();
+    if root.tag_name().name() != "tt" {
+        return Err(Error::NotTtml(root.tag_name().name().to_owned()));
+    }
+
+    let mut importer = Importer::new(root, options);
+    if let Some(body) = children(root, "body").next() {
+        let context = Context {
+            begin: 0.0,
+            end: f64::INFINITY,
+            seq: false,
+            props: Props::new(),
+            region: None,
+            preserve: false,
+        };
+        let (begin, end) = importer.interval(body, &context, 0.0);
+        if let Some(context) = importer.context(body, &context, begin, end) {
+            importer.container(body, &context);
+        }
+    }
+    Ok(importer.finish())
+}
+
+impl Track {
+    /// Import a TTML document in to a new track. See [`parse`](crate::import::ttml::parse).
+    pub fn from_ttml(library: &Library, text: &str, options: &ImportOptions) -> Result<Track> {
+        parse(text, options)?.to_track(library)
+    }
+}
+
+/// Styling properties by their local name, with font sizes resolved to script pixels.
+type Props<'input> = BTreeMap<&'input str, String>;
+
+/// Inherited state while walking the body.
+#[derive(Debug, Clone)]
+struct Context<'a, 'input> {
+    /// Start in seconds.
+    begin: f64,
+    /// End in seconds, infinite when unbounded.
+    end: f64,
+    /// Whether children are timed one after another, in a `seq` time container.
+    seq: bool,
+    /// Computed styling.
+    props: Props<'input>,
+    /// The region content is placed in.
+    region: Option<Node<'a, 'input>>,
+    /// Whether `xml:space="preserve"` applies.
+    preserve: bool,
+}
+
+/// Text or a line break in a paragraph, with when it is shown.
+#[derive(Debug)]
+struct Segment<'input> {
+    /// The text, or `None` for a `<br/>`.
+    text: Option<String>,
+    #[allow(clippy::missing_docs_in_private_items)]
+    begin: f64,
+    #[allow(clippy::missing_docs_in_private_items)]
+    end: f64,
+    /// Computed styling of the enclosing element.
+    props: Props<'input>,
+    /// Whether whitespace is kept as is.
+    preserve: bool,
+}
+
+/// Time expression parameters.
+#[derive(Debug)]
+struct Clock {
+    /// Effective frames per second, after the multiplier.
+    frame_rate: f64,
+    /// Sub-frames per frame.
+    sub_frame_rate: f64,
+    /// Ticks per second.
+    tick_rate: f64,
+}
+
+impl Clock {
+    /// Read the timing parameters of the root element.
+    fn new(root: Node<'_, '_>) -> Self {
+        let number = |name| {
+            parameter(root, name)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .filter(|value| value.is_finite() && *value > 0.0)
+        };
+        let multiplier = parameter(root, "frameRateMultiplier")
+            .and_then(|value| {
+                let mut parts = value.split_whitespace().map(str::parse::<f64>);
+                let (numerator, denominator) = (parts.next()?.ok()?, parts.next()?.ok()?);
+                (denominator > 0.0).then(|| numerator / denominator)
+            })
+            .unwrap_or(1.0);
+        let frame_rate = number("frameRate");
+        let sub_frame_rate = number("subFrameRate").unwrap_or(1.0);
+        let tick_rate = number("tickRate")
+            .or_else(|| frame_rate.map(|rate| rate * sub_frame_rate))
+            .unwrap_or(1.0);
+        Self {
+            frame_rate: frame_rate.unwrap_or(30.0) * multiplier,
+            sub_frame_rate,
+            tick_rate,
+        }
+    }
+
+    /// Parse a clock time or offset time to seconds.
+    fn seconds(&self, value: &str) -> Option<f64> {
+        let value = value.trim();
+        let number = |value: &str| {
+            let valid = !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit() || b == b'.');
+            valid.then(|| value.parse::<f64>().ok()).flatten()
+        };
+
+        if value.contains(':') {
+            let parts: Vec<&str> = value.split(':').collect();
+            if !(3..=4).contains(&parts.len()) {
+                return None;
+            }
+            let mut seconds =
+                number(parts[0])? * 3600.0 + number(parts[1])? * 60.0 + number(parts[2])?;
+            if let Some(frames) = parts.get(3) {
+                let (frames, sub_frames) = match frames.split_once('.') {
+                    Some((frames, sub_frames)) => (frames, number(sub_frames)?),
+                    None => (*frames, 0.0),
+                };
+                seconds += (number(frames)? + sub_frames / self.sub_frame_rate) / self.frame_rate;
+            }
+            return Some(seconds);
+        }
+
+        let split = value.find(|c: char| c.is_ascii_alphabetic())?;
+        let (value, metric) = value.split_at(split);
+        let value = number(value)?;
+        match metric {
+            "h" => Some(value * 3600.0),
+            "m" => Some(value * 60.0),
+            "s" => Some(value),
+            "ms" => Some(value / 1000.0),
+            "f" => Some(value / self.frame_rate),
+            "t" => Some(value / self.tick_rate),
+            _ => None,
+        }
+    }
+}
+
+/// Converts the body of a document.
+#[derive(Debug)]
+struct Importer<'a, 'input> {
+    /// `<style>` elements in the head by ID.
+    styles: HashMap<&'a str, Node<'a, 'input>>,
+    /// `<region>` elements in the head by ID.
+    regions: HashMap<&'a str, Node<'a, 'input>>,
+    #[allow(clippy::missing_docs_in_private_items)]
+    clock: Clock,
+    /// Size of the root container in TTML pixels.
+    extent: (f64, f64),
+    /// Size of a cell in TTML pixels.
+    cell: (f64, f64),
+    /// Script pixels per TTML pixel.
+    scale: (f64, f64),
+    /// Events with their times in seconds, as ends may be unbounded.
+    events: Vec<(f64, f64, Event)>,
+    /// The document being built.
+    doc: ScriptDocument,
+}
+
+impl<'a, 'input> Importer<'a, 'input> {
+    /// Read the head and parameters of a document.
+    fn new(root: Node<'a, 'input>, options: &ImportOptions) -> Self {
+        let play_res = (f64::from(options.play_res.0), f64::from(options.play_res.1));
+        let mut styles = HashMap::new();
+        let mut regions = HashMap::new();
+        for head in children(root, "head") {
+            for node in head.descendants().filter(Node::is_element) {
+                let Some(id) = id(node) else {
+                    continue;
+                };
+                match node.tag_name().name() {
+                    "style" => {
+                        styles.insert(id, node);
+                    }
+                    "region" => {
+                        regions.insert(id, node);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let extent = root
+            .attributes()
+            .find(|attribute| attribute.name() == "extent" && is_styling(attribute.namespace()))
+            .and_then(|attribute| {
+                let mut parts = attribute.value().split_whitespace();
+                let width = parts.next()?.strip_suffix("px")?.parse::<f64>().ok()?;
+                let height = parts.next()?.strip_suffix("px")?.parse::<f64>().ok()?;
+                (width > 0.0 && height > 0.0).then_some((width, height))
+            })
+            .unwrap_or(play_res);
+        let cells = parameter(root, "cellResolution")
+            .and_then(|value| {
+                let mut parts = value.split_whitespace().map(str::parse::<f64>);
+                let (columns, rows) = (parts.next()?.ok()?, parts.next()?.ok()?);
+                (columns > 0.0 && rows > 0.0).then_some((columns, rows))
+            })
+            .unwrap_or((32.0, 15.0));
+
+        Self {
+            styles,
+            regions,
+            clock: Clock::new(root),
+            extent,
+            cell: (extent.0 / cells.0, extent.1 / cells.1),
+            scale: (play_res.0 / extent.0, play_res.1 / extent.1),
+            events: Vec::new(),
+            doc: options.document(),
+        }
+    }
+
+    /// Convert the paragraphs in a `<body>` or `<div>`.
+    fn container(&mut self, node: Node<'a, 'input>, context: &Context<'a, 'input>) {
+        let mut sync = context.begin;
+        for child in node.children().filter(Node::is_element) {
+            let name = child.tag_name().name();
+            if name != "div" && name != "p" {
+                continue;
+            }
+            let (begin, end) = self.interval(child, context, sync);
+            if context.seq {
+                sync = end;
+            }
+            let Some(context) = self.context(child, context, begin, end) else {
+                continue;
+            };
+            if name == "div" {
+                self.container(child, &context);
+            } else {
+                self.paragraph(child, &context);
+            }
+        }
+    }
+
+    /// When an element begins and ends, with times relative to `sync`.
+    ///
+    /// `sync` is the parent's begin, or the previous child's end in a `seq` container. The end
+    /// is never before the begin or after the parent's end.
+    fn interval(&self, node: Node<'_, '_>, parent: &Context<'_, '_>, sync: f64) -> (f64, f64) {
+        let begin = sync + self.time(node, "begin").unwrap_or(0.0);
+        let end = [
+            self.time(node, "end").map(|end| sync + end),
+            self.time(node, "dur").map(|dur| begin + dur),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(parent.end, f64::min);
+        (begin, end.max(begin.min(parent.end)))
+    }
+
+    /// The state inside an element shown from `begin` to `end`, or `None` if it is never shown.
+    fn context(
+        &self,
+        node: Node<'a, 'input>,
+        parent: &Context<'a, 'input>,
+        begin: f64,
+        end: f64,
+    ) -> Option<Context<'a, 'input>> {
+        if begin >= end {
+            return None;
+        }
+
+        let region = match node.attribute("region") {
+            Some(id) => Some(*self.regions.get(id)?),
+            None => parent.region,
+        };
+        let props = self.compute(&parent.props, node);
+        if props
+            .get("display")
+            .is_some_and(|display| display == "none")
+        {
+            return None;
+        }
+        let preserve = match node.attribute((XML_NAMESPACE, "space")) {
+            Some(space) => space == "preserve",
+            None => parent.preserve,
+        };
+        Some(Context {
+            begin,
+            end,
+            seq: node.attribute("timeContainer") == Some("seq"),
+            props,
+            region,
+            preserve,
+        })
+    }
+
+    /// A time attribute in seconds.
+    fn time(&self, node: Node<'_, '_>, name: &str) -> Option<f64> {
+        self.clock.seconds(node.attribute(name)?)
+    }
+
+    /// Convert a paragraph to events.
+    fn paragraph(&mut self, node: Node<'a, 'input>, context: &Context<'a, 'input>) {
+        let mut context = context.clone();
+        // Region styles are inherited with the lowest priority.
+        if let Some(region) = context.region {
+            let mut props = self.compute(&Props::new(), region);
+            props.retain(|key, _| !NOT_INHERITED.contains(key));
+            props.append(&mut context.props);
+            context.props = props;
+        }
+
+        let mut segments = Vec::new();
+        self.segments(node, &context, &mut segments);
+        let style = self.style(&context, node);
+
+        let mut times: Vec<f64> = segments
+            .iter()
+            .flat_map(|segment| [segment.begin, segment.end])
+            .chain([context.begin, context.end])
+            .collect();
+        times.sort_by(f64::total_cmp);
+        times.dedup();
+
+        for window in times.windows(2) {
+            let (begin, end) = (window[0], window[1]);
+            let active: Vec<&Segment<'_>> = segments
+                .iter()
+                .filter(|segment| segment.begin <= begin && segment.end >= end)
+                .collect();
+            let text = self.text(&active, &style);
+            if text.is_empty() {
+                continue;
+            }
+            if let Some((_, last_end, last)) = self.events.last_mut() {
+                if *last_end == begin && last.text == text && last.style == style.name {
+                    *last_end = end;
+                    continue;
+                }
+            }
+
+            let mut event = Event {
+                style: style.name.clone(),
+                ..Event::default()
+            };
+            let block = match context.region {
+                Some(region) => self.position(region, &style, &mut event),
+                None => TagBlock::new(),
+            };
+            event.text = format!("{block}{text}");
+            self.events.push((begin, end, event));
+        }
+    }
+
+    /// Collect the text and line breaks of an element.
+    fn segments(
+        &self,
+        node: Node<'a, 'input>,
+        context: &Context<'a, 'input>,
+        out: &mut Vec<Segment<'input>>,
+    ) {
+        let mut sync = context.begin;
+        for child in node.children() {
+            let text = if child.is_text() {
+                Some(child.text().unwrap_or_default().to_owned())
+            } else if child.has_tag_name("br") {
+                None
+            } else {
+                if child.has_tag_name("span") {
+                    let (begin, end) = self.interval(child, context, sync);
+                    if context.seq {
+                        sync = end;
+                    }
+                    if let Some(context) = self.context(child, context, begin, end) {
+                        self.segments(child, &context, out);
+                    }
+                }
+                continue;
+            };
+            if context.seq {
+                continue;
+            }
+            out.push(Segment {
+                text,
+                begin: context.begin,
+                end: context.end,
+                props: context.props.clone(),
+                preserve: context.preserve,
+            });
+        }
+    }
+
+    /// Event text for the segments shown at the same time.
+    fn text(&self, segments: &[&Segment<'_>], style: &Style) -> String {
+        let mut out = String::new();
+        let mut current = style.clone();
+        let mut line_start = true;
+        let mut pending_space = false;
+        for segment in segments {
+            let Some(text) = &segment.text else {
+                out.push_str("\\N");
+                line_start = true;
+                pending_space = false;
+                continue;
+            };
+            let text = if segment.preserve {
+                text.clone()
+            } else {
+                if text.trim().is_empty() {
+                    pending_space |= !text.is_empty() && !line_start;
+                    continue;
+                }
+                pending_space |= text.starts_with(char::is_whitespace) && !line_start;
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            };
+
+            if pending_space {
+                out.push(' ');
+            }
+            let mut span_style = self.doc.styles[0].clone();
+            self.apply(&mut span_style, &segment.props);
+            out.push_str(&overrides(&current, &span_style, style).to_string());
+            current = span_style;
+            out.push_str(&escape_text(&text));
+            pending_space = !segment.preserve
+                && segment
+                    .text
+                    .as_ref()
+                    .is_some_and(|text| text.ends_with(char::is_whitespace));
+            line_start = false;
+        }
+        out
+    }
+
+    /// The name of the style for a paragraph, adding it to the document if needed.
+    fn style(&mut self, context: &Context<'a, 'input>, node: Node<'_, '_>) -> Style {
+        let mut style = self.doc.styles[0].clone();
+        self.apply(&mut style, &context.props);
+        if let Some(region) = context.region {
+            let row = match self
+                .specified(region, 0)
+                .get("displayAlign")
+                .map(String::as_str)
+            {
+                Some("after") => 0,
+                Some("center") => 1,
+                _ => 2,
+            };
+            style.alignment = row * 3 + (style.alignment - 1) % 3 + 1;
+            style.margin_l = 0;
+            style.margin_r = 0;
+            style.margin_v = 0;
+        }
+
+        if let Some(existing) = self.doc.styles.iter().find(|existing| {
+            **existing
+                == Style {
+                    name: existing.name.clone(),
+                    ..style.clone()
+                }
+        }) {
+            return existing.clone();
+        }
+        let name = node
+            .attribute("style")
+            .or_else(|| context.region.and_then(id))
+            .unwrap_or("TTML");
+        style.name = name.to_owned();
+        let mut suffix = 1;
+        while self.doc.style(&style.name).is_some() {
+            suffix += 1;
+            style.name = format!("{name} {suffix}");
+        }
+        self.doc.styles.push(style.clone());
+        style
+    }
+
+    /// Set an event's margins for its region, returning a `\pos` if it needs one.
+    fn position(&self, region: Node<'_, '_>, style: &Style, event: &mut Event) -> TagBlock {
+        let props = self.specified(region, 0);
+        let pair = |key| {
+            let mut parts = props.get(key)?.split_whitespace();
+            Some((
+                self.length(parts.next()?, 0)?,
+                self.length(parts.next()?, 1)?,
+            ))
+        };
+        let (x, y) = pair("origin").unwrap_or((0.0, 0.0));
+        let (width, height) = pair("extent").unwrap_or((self.extent.0 - x, self.extent.1 - y));
+        let (left, right) = (x * self.scale.0, (x + width) * self.scale.0);
+        let (top, bottom) = (y * self.scale.1, (y + height) * self.scale.1);
+        let play_res = (self.extent.0 * self.scale.0, self.extent.1 * self.scale.1);
+
+        event.margin_l = to_px(left);
+        event.margin_r = to_px(play_res.0 - right);
+        match (style.alignment - 1) / 3 {
+            0 => event.margin_v = to_px(play_res.1 - bottom),
+            2 => event.margin_v = to_px(top),
+            _ => {
+                let x = match (style.alignment - 1) % 3 {
+                    0 => left,
+                    1 => (left + right) / 2.0,
+                    _ => right,
+                };
+                return TagBlock::new().pos(x, (top + bottom) / 2.0);
+            }
+        }
+        TagBlock::new()
+    }
+
+    /// Styling set on an element, after the styles it references.
+    fn specified(&self, node: Node<'_, 'input>, depth: usize) -> Props<'input> {
+        let mut props = Props::new();
+        // Guards against styles that reference each other.
+        if depth > 16 {
+            return props;
+        }
+        for id in node
+            .attribute("style")
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            if let Some(style) = self.styles.get(id) {
+                props.append(&mut self.specified(*style, depth + 1));
+            }
+        }
+        if node.has_tag_name("region") {
+            for style in children(node, "style") {
+                props.append(&mut self.specified(style, depth + 1));
+            }
+        }
+        for attribute in node.attributes() {
+            if is_styling(attribute.namespace()) {
+                props.insert(attribute.name(), attribute.value().to_owned());
+            }
+        }
+        props
+    }
+
+    /// Computed styling of an element, from its parent's.
+    fn compute(&self, parent: &Props<'input>, node: Node<'_, 'input>) -> Props<'input> {
+        let mut props: Props<'input> = parent
+            .iter()
+            .filter(|(key, _)| !NOT_INHERITED.contains(key))
+            .map(|(key, value)| (*key, value.clone()))
+            .collect();
+        for (key, value) in self.specified(node, 0) {
+            if key == "fontSize" {
+                if let Some(size) = self.font_size(&value, parent) {
+                    props.insert(key, size.to_string());
+                }
+            } else {
+                props.insert(key, value);
+            }
+        }
+        props
+    }
+
+    /// Resolve a font size to script pixels. Two value sizes use the height.
+    fn font_size(&self, value: &str, parent: &Props<'_>) -> Option<f64> {
+        let parent = parent
+            .get("fontSize")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(self.cell.1 * self.scale.1);
+        let value = value.split_whitespace().last()?;
+        let size = if let Some(percent) = value.strip_suffix('%') {
+            parent * percent.parse::<f64>().ok()? / 100.0
+        } else if let Some(em) = value.strip_suffix("em") {
+            parent * em.parse::<f64>().ok()?
+        } else {
+            self.length(value, 1)? * self.scale.1
+        };
+        (size > 0.0).then_some(size)
+    }
+
+    /// A length in TTML pixels. Percentages are of the root container.
+    fn length(&self, value: &str, axis: usize) -> Option<f64> {
+        let (extent, cell) = if axis == 0 {
+            (self.extent.0, self.cell.0)
+        } else {
+            (self.extent.1, self.cell.1)
+        };
+        let number = |value: &str| value.parse::<f64>().ok().filter(|value| value.is_finite());
+        if let Some(px) = value.strip_suffix("px") {
+            number(px)
+        } else if let Some(percent) = value.strip_suffix('%') {
+            Some(number(percent)? / 100.0 * extent)
+        } else if let Some(cells) = value.strip_suffix('c') {
+            Some(number(cells)? * cell)
+        } else if let Some(width) = value.strip_suffix("rw") {
+            Some(number(width)? / 100.0 * self.extent.0)
+        } else if let Some(height) = value.strip_suffix("rh") {
+            Some(number(height)? / 100.0 * self.extent.1)
+        } else {
+            None
+        }
+    }
+
+    /// Apply computed styling to a style.
+    fn apply(&self, style: &mut Style, props: &Props<'_>) {
+        for (key, value) in props {
+            let value = value.trim();
+            match *key {
+                "color" => {
+                    if let Some(color) = color(value) {
+                        style.primary_color = color;
+                    }
+                }
+                "backgroundColor" => {
+                    // An opaque box, which is drawn with the outline color.
+                    if let Some(color) = color(value).filter(|color| color.alpha != 255) {
+                        style.border_style = 3;
+                        style.outline_color = color;
+                        style.back_color = color;
+                    }
+                }
+                "fontFamily" => {
+                    if let Some(family) = value.split(',').next() {
+                        match family.trim().trim_matches(['"', '\'']) {
+                            "default" | "sansSerif" | "proportionalSansSerif" => {}
+                            "monospace" | "monospaceSansSerif" | "monospaceSerif" => {
+                                style.font_name = "Courier New".to_owned();
+                            }
+                            "serif" | "proportionalSerif" => {
+                                style.font_name = "Times New Roman".to_owned();
+                            }
+                            family => style.font_name = family.to_owned(),
+                        }
+                    }
+                }
+                "fontSize" => {
+                    if let Ok(size) = value.parse() {
+                        style.font_size = size;
+                    }
+                }
+                "fontWeight" => style.bold = i32::from(value == "bold"),
+                "fontStyle" => style.italic = matches!(value, "italic" | "oblique"),
+                "textDecoration" => {
+                    for decoration in value.split_whitespace() {
+                        match decoration {
+                            "none" => {
+                                style.underline = false;
+                                style.strike_out = false;
+                            }
+                            "underline" => style.underline = true,
+                            "noUnderline" => style.underline = false,
+                            "lineThrough" => style.strike_out = true,
+                            "noLineThrough" => style.strike_out = false,
+                            _ => {}
+                        }
+                    }
+                }
+                "textAlign" => {
+                    let column = match value {
+                        "left" | "start" => 1,
+                        "right" | "end" => 3,
+                        _ => 2,
+                    };
+                    style.alignment = (style.alignment - 1) / 3 * 3 + column;
+                }
+                "textOutline" => {
+                    if value == "none" {
+                        style.outline = 0.0;
+                        continue;
+                    }
+                    let mut thickness = None;
+                    for part in value.split_whitespace() {
+                        if let Some(length) = self.length(part, 1) {
+                            thickness.get_or_insert(length * self.scale.1);
+                        } else if let Some(color) = color(part) {
+                            if style.border_style != 3 {
+                                style.outline_color = color;
+                            }
+                        }
+                    }
+                    if let Some(thickness) = thickness {
+                        style.outline = thickness;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The finished document.
+    ///
+    /// Unbounded events end with the last bounded one, or are shown for a few seconds if they start
+    /// after it.
+    fn finish(mut self) -> ScriptDocument {
+        let last = self
+            .events
+            .iter()
+            .map(|(_, end, _)| *end)
+            .filter(|end| end.is_finite())
+            .fold(0.0, f64::max);
+        for (begin, end, mut event) in self.events {
+            let end = if end.is_finite() {
+                end
+            } else if last > begin {
+                last
+            } else {
+                begin + UNBOUNDED_DURATION
+            };
+            // Times too large to be represented are clamped, which empties events starting then.
+            let (start, end) = (millis(begin), millis(end));
+            if start >= end {
+                continue;
+            }
+            event.start = Duration::milliseconds(start);
+            event.end = Duration::milliseconds(end);
+            self.doc.events.push(event);
+        }
+        self.doc
+    }
+}
+
+/// Seconds rounded to whole milliseconds, saturating at the limits of `i64`.
+const fn millis(seconds: f64) -> i64 {
+    round_to_i64(seconds * 1000.0)
+}
+
+/// Tags changing the formatting of a span from `from` to `to`, resetting to the event's style
+/// where they match it.
+fn overrides(from: &Style, to: &Style, event: &Style) -> TagBlock {
+    let mut block = TagBlock::new();
+    for (index, old, new, base) in [
+        (1, from.primary_color, to.primary_color, event.primary_color),
+        (3, from.outline_color, to.outline_color, event.outline_color),
+    ] {
+        if old == new {
+            continue;
+        }
+        block = if new == base {
+            block
+                .tag(Tag::Color { index, color: None })
+                .tag(Tag::Alpha {
+                    index: Some(index),
+                    alpha: None,
+                })
+        } else {
+            block.color_n(index, new).alpha_n(index, new.alpha)
+        };
+    }
+    if from.bold != to.bold {
+        block = match to.bold == event.bold {
+            true => block.tag(Tag::Bold(None)),
+            false => block.bold(to.bold != 0),
+        };
+    }
+    for (old, new, base, tag, set) in [
+        (
+            from.italic,
+            to.italic,
+            event.italic,
+            Tag::Italic(None),
+            TagBlock::italic as fn(_, _) -> _,
+        ),
+        (
+            from.underline,
+            to.underline,
+            event.underline,
+            Tag::Underline(None),
+            TagBlock::underline,
+        ),
+        (
+            from.strike_out,
+            to.strike_out,
+            event.strike_out,
+            Tag::StrikeOut(None),
+            TagBlock::strike_out,
+        ),
+    ] {
+        if old != new {
+            block = if new == base {
+                block.tag(tag)
+            } else {
+                set(block, new)
+            };
+        }
+    }
+    if from.font_name != to.font_name {
+        block = match to.font_name == event.font_name {
+            true => block.tag(Tag::FontName(None)),
+            false => block.font_name(&to.font_name),
+        };
+    }
+    if from.font_size != to.font_size {
+        block = match to.font_size == event.font_size {
+            true => block.tag(Tag::FontSize(None)),
+            false => block.font_size(to.font_size),
+        };
+    }
+    block
+}
+
+/// Child elements with a local name.
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+/// The `xml:id` of an element, or `id` as used by some DFXP.
+fn id<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    node.attribute((XML_NAMESPACE, "id"))
+        .or_else(|| node.attribute("id"))
+}
+
+/// Whether an attribute namespace is the TTML styling namespace, in any TTML version.
+fn is_styling(namespace: Option<&str>) -> bool {
+    namespace
+        .is_some_and(|namespace| namespace.ends_with("#styling") || namespace.ends_with("#style"))
+}
+
+/// A `ttp:` parameter attribute of the root.
+fn parameter<'a>(root: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    root.attributes()
+        .find(|attribute| {
+            attribute.name() == name
+                && attribute
+                    .namespace()
+                    .is_some_and(|namespace| namespace.ends_with("#parameter"))
+        })
+        .map(|attribute| attribute.value())
+}
+
+/// Parse a TTML color, where `rgba()` has an alpha from 0 to 255.
+fn color(value: &str) -> Option<AssColor> {
+    if let Some(args) = value
+        .strip_prefix("rgba(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let channels: Vec<u8> = args
+            .split(',')
+            .map(|channel| channel.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        if let [r, g, b, alpha] = channels[..] {
+            return Some(AssColor::rgba(r, g, b, 255 - alpha));
+        }
+    }
+    AssColor::from_css(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wrap a body in a `<tt>` root with the usual namespaces.
+    fn document(attributes: &str, body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<tt xmlns="http://www.w3.org/ns/ttml" xmlns:tts="http://www.w3.org/ns/ttml#styling"
+    xmlns:ttp="http://www.w3.org/ns/ttml#parameter" {attributes}>{body}</tt>"#
+        )
+    }
+
+    /// The events of a document as (ms, ms, text).
+    fn events(attributes: &str, body: &str) -> Vec<(i128, i128, String)> {
+        parse(&document(attributes, body), &ImportOptions::default())
+            .unwrap()
+            .events
+            .into_iter()
+            .map(|event| {
+                (
+                    event.start.whole_milliseconds(),
+                    event.end.whole_milliseconds(),
+                    event.text,
+                )
+            })
+            .collect()
+    }
+
+    /// Owned (ms, ms, text) for comparing with [`events`].
+    fn cue(start: i128, end: i128, text: &str) -> (i128, i128, String) {
+        (start, end, text.to_owned())
+    }
+
+    #[test]
+    fn times() {
+        let body = r#"<body><div begin="1s">
+            <p begin="00:00:01.5" end="00:00:02:15">Clock</p>
+            <p begin="30f" dur="2h">Frames</p>
+            <p begin="100t" end="150t">Ticks</p>
+            <p begin="bad" end="1s">Invalid</p>
+        </div></body>"#;
+        assert_eq!(
+            events(r#"ttp:frameRate="25" ttp:tickRate="100""#, body),
+            [
+                cue(2500, 3600, "Clock"),
+                cue(2200, 7_202_200, "Frames"),
+                cue(2000, 2500, "Ticks"),
+                cue(1000, 2000, "Invalid"),
+            ]
+        );
+        // 30000/1001 frames per second.
+        assert_eq!(
+            events(
+                r#"ttp:frameRate="30" ttp:frameRateMultiplier="1000 1001""#,
+                r#"<body><p begin="00:00:00:00" end="00:00:00:30">NTSC</p></body>"#
+            ),
+            [cue(0, 1001, "NTSC")]
+        );
+    }
+
+    #[test]
+    fn seq() {
+        let body = r#"<body><div timeContainer="seq" begin="1s">
+            <p dur="2s">a</p>
+            <p begin="1s" end="3s">b</p>
+            <p>c</p>
+        </div><div begin="10s" end="12s" timeContainer="seq">
+            ignored
+            <p>d</p>
+            <p>e</p>
+        </div></body>"#;
+        assert_eq!(
+            events("", body),
+            [
+                cue(1000, 3000, "a"),
+                cue(4000, 6000, "b"),
+                cue(6000, 12000, "c"),
+                cue(10000, 12000, "d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unbounded() {
+        let body = r#"<body>
+            <p begin="1s" end="4s">Bounded</p>
+            <p begin="2s">Until last</p>
+            <p begin="10s">After</p>
+        </body>"#;
+        assert_eq!(
+            events("", body),
+            [
+                cue(1000, 4000, "Bounded"),
+                cue(2000, 4000, "Until last"),
+                cue(10000, 15000, "After"),
+            ]
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        // Times past the range of a `Duration` are clamped, leaving an empty event that is dropped.
+        let body = r#"<body>
+            <p begin="99999999999999999999h">Overflow</p>
+            <p begin="0s" end="99999999999999999999h">Forever</p>
+        </body>"#;
+        assert_eq!(events("", body), [cue(0, i128::from(i64::MAX), "Forever")]);
+    }
+
+    #[test]
+    fn timed_spans() {
+        // Lines not shown yet still take their space, so earlier lines don't move.
+        let body = r#"<body><p begin="0s" end="3s">
+            <span begin="0s">Roll</span> <span begin="1s">up</span><br/>
+            <span begin="2s">&lt;next&gt; {line}</span>
+        </p></body>"#;
+        assert_eq!(
+            events("", body),
+            [
+                cue(0, 1000, r"Roll\N"),
+                cue(1000, 2000, r"Roll up\N"),
+                cue(2000, 3000, r"Roll up\N<next> \{line\}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn styling() {
+        let text = document(
+            r#"tts:extent="1920px 1080px""#,
+            r#"<head><styling>
+                <style xml:id="s1" tts:color="yellow" tts:fontSize="54px" tts:fontWeight="bold"/>
+            </styling><layout>
+                <region xml:id="top" tts:origin="10% 10%" tts:extent="80% 20%"
+                    tts:displayAlign="before" tts:textAlign="center"/>
+            </layout></head>
+            <body><p begin="0s" end="1s" style="s1" region="top">A
+                <span tts:fontStyle="italic" tts:color="rgba(255,0,0,128)">b</span> c</p>
+            </body>"#,
+        );
+        let doc = parse(&text, &ImportOptions::default()).unwrap();
+        let style = doc.style("s1").unwrap();
+        assert_eq!(style.primary_color, AssColor::rgb(255, 255, 0));
+        assert_eq!(style.bold, 1);
+        assert_eq!(style.alignment, 8);
+
+        let event = &doc.events[0];
+        assert_eq!(event.style, "s1");
+        assert_eq!(event.text, r"A {\c&H0000FF&\1a&H7F&\i1}b {\c\1a\i}c");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("<tt><p>", &ImportOptions::default()).is_err());
+        assert!(parse("<html/>", &ImportOptions::default()).is_err());
+    }
+}
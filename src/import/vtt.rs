@@ -0,0 +1,808 @@
+//! WebVTT (`.vtt`) import
+//!
+//! Cue settings are translated to ASS positioning. Without `line` percentages or `position`,
+//! alignment and margins are used, so the renderer still handles collisions. Otherwise the cue
+//! gets a `\pos`. `vertical` cues use a vertical `@` font rotated in to place, which is only an
+//! approximation of WebVTT vertical layout.
+//!
+//! `::cue` rules in `STYLE` blocks change the default style. Rules for `::cue(.class)` and
+//! `::cue(v[voice="Name"])` create new styles based on it, named after the class or voice, which
+//! are used for cues and spans with that class or voice. Voices also become the event's actor.
+use time::Duration;
+
+use super::{to_px, ImportOptions};
+use crate::{
+    color::AssColor,
+    error::Result,
+    library::Library,
+    script::{Event, ScriptDocument, Style},
+    tags::{escape_text, Tag, TagBlock},
+    timing::AssTime,
+    track::Track,
+};
+
+/// Convert WebVTT text to a script.
+pub fn parse(text: &str, options: &ImportOptions) -> ScriptDocument {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+    let mut rules = Vec::new();
+    let mut cues = Vec::new();
+    for (index, block) in blocks(&text).into_iter().enumerate() {
+        let first = block[0].trim_start();
+        if index == 0 && first.starts_with("WEBVTT") {
+            continue;
+        }
+        if first.starts_with("NOTE") || first.starts_with("REGION") {
+            continue;
+        }
+        if first.starts_with("STYLE") && !first.contains("-->") {
+            rules.extend(parse_css(&block[1..].join("\n")));
+            continue;
+        }
+        if let Some(cue) = parse_cue(&block) {
+            cues.push(cue);
+        }
+    }
+
+    let mut doc = options.document();
+    for (selector, declarations) in &rules {
+        if *selector == Selector::Cue {
+            apply_css(&mut doc.styles[0], declarations);
+        }
+    }
+    let base = doc.styles[0].clone();
+    let mut converter = Converter {
+        base,
+        rules,
+        styles: Vec::new(),
+        play_res: options.play_res,
+    };
+    for cue in cues {
+        let event = converter.event(&cue);
+        doc.events.push(event);
+    }
+    doc.styles.extend(converter.styles);
+    doc
+}
+
+impl Track {
+    /// Import WebVTT text in to a new track. See [`parse`](crate::import::vtt::parse).
+    pub fn from_vtt(library: &Library, text: &str, options: &ImportOptions) -> Result<Track> {
+        parse(text, options).to_track(library)
+    }
+}
+
+/// Split text in to blocks of lines separated by blank lines.
+fn blocks(text: &str) -> Vec<Vec<&str>> {
+    let mut blocks = vec![Vec::new()];
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            blocks.push(Vec::new());
+        } else if let Some(block) = blocks.last_mut() {
+            block.push(line);
+        }
+    }
+    blocks.retain(|block| !block.is_empty());
+    blocks
+}
+
+/// A parsed cue.
+#[derive(Debug)]
+struct Cue {
+    #[allow(clippy::missing_docs_in_private_items)]
+    start: Duration,
+    #[allow(clippy::missing_docs_in_private_items)]
+    end: Duration,
+    /// Cue settings after the timing.
+    settings: Settings,
+    /// Payload lines joined with `\n`.
+    payload: String,
+}
+
+/// Cue settings. Percentages are from 0 to 100.
+#[derive(Debug, Default, PartialEq)]
+struct Settings {
+    /// `vertical`, true for `rl` and false for `lr`.
+    vertical: Option<bool>,
+    /// `line` as a line number.
+    line_number: Option<i32>,
+    /// `line` as a percentage, with its alignment.
+    line_percent: Option<(f64, LineAlign)>,
+    /// `position`, with its alignment if given.
+    position: Option<(f64, Option<LineAlign>)>,
+    /// `size`
+    size: Option<f64>,
+    /// `align`, as ASS horizontal alignment 1 to 3.
+    align: i32,
+}
+
+/// Alignment of a cue box relative to a `line` or `position`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum LineAlign {
+    #[allow(clippy::missing_docs_in_private_items)]
+    Start,
+    #[allow(clippy::missing_docs_in_private_items)]
+    Center,
+    #[allow(clippy::missing_docs_in_private_items)]
+    End,
+}
+
+impl LineAlign {
+    /// Parse `start`, `center` or `end`, or `line-left` and `line-right` for `position`.
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "start" | "line-left" => Some(Self::Start),
+            "center" | "middle" => Some(Self::Center),
+            "end" | "line-right" => Some(Self::End),
+            _ => None,
+        }
+    }
+
+    /// Offset of the ASS alignment numbering, 0 for left or bottom through 2 for right or top.
+    const fn index(self) -> i32 {
+        match self {
+            Self::Start => 0,
+            Self::Center => 1,
+            Self::End => 2,
+        }
+    }
+}
+
+/// Parse a cue block, which may start with an identifier.
+fn parse_cue(block: &[&str]) -> Option<Cue> {
+    let timing_line = block.iter().position(|line| line.contains("-->"))?;
+    if timing_line > 1 {
+        return None;
+    }
+    let (start, rest) = block[timing_line].split_once("-->")?;
+    let mut rest = rest.split_whitespace();
+    let end = rest.next()?;
+
+    let mut settings = Settings {
+        align: 2,
+        ..Settings::default()
+    };
+    for setting in rest {
+        let Some((key, value)) = setting.split_once(':') else {
+            continue;
+        };
+        let (value, alignment) = match value.split_once(',') {
+            Some((value, alignment)) => (value, Some(alignment)),
+            None => (value, None),
+        };
+        match key {
+            "vertical" => settings.vertical = Some(value == "rl"),
+            "line" => {
+                if let Some(percent) = percentage(value) {
+                    let align = alignment.and_then(LineAlign::parse);
+                    settings.line_percent = Some((percent, align.unwrap_or(LineAlign::Start)));
+                } else if let Ok(number) = value.parse::<f64>() {
+                    settings.line_number = Some(to_px(number));
+                }
+            }
+            "position" => {
+                if let Some(percent) = percentage(value) {
+                    settings.position = Some((percent, alignment.and_then(LineAlign::parse)));
+                }
+            }
+            "size" => settings.size = percentage(value),
+            "align" => {
+                settings.align = match value {
+                    "start" | "left" => 1,
+                    "end" | "right" => 3,
+                    _ => 2,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    Some(Cue {
+        start: AssTime::parse_vtt(start)?.to_duration(),
+        end: AssTime::parse_vtt(end)?.to_duration(),
+        settings,
+        payload: block[timing_line + 1..].join("\n"),
+    })
+}
+
+/// Parse a percentage such as `40%`.
+fn percentage(value: &str) -> Option<f64> {
+    value
+        .strip_suffix('%')?
+        .parse()
+        .ok()
+        .filter(|percent: &f64| (0.0..=100.0).contains(percent))
+}
+
+/// A `::cue` selector.
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    /// `::cue`, every cue.
+    Cue,
+    /// `::cue(.class)` or `::cue(c.class)`.
+    Class(String),
+    /// `::cue(v[voice="Name"])`.
+    Voice(String),
+}
+
+/// CSS declarations as property and value.
+type Declarations = Vec<(String, String)>;
+
+/// Parse the rules of a `STYLE` block, ignoring selectors that can't be mapped to a style.
+fn parse_css(css: &str) -> Vec<(Selector, Declarations)> {
+    let mut css = css.to_owned();
+    while let Some(start) = css.find("/*") {
+        let end = css[start..]
+            .find("*/")
+            .map_or(css.len(), |end| start + end + 2);
+        css.replace_range(start..end, "");
+    }
+
+    let mut rules = Vec::new();
+    for rule in css.split('}') {
+        let Some((selectors, body)) = rule.split_once('{') else {
+            continue;
+        };
+        let declarations: Declarations = body
+            .split(';')
+            .filter_map(|declaration| declaration.split_once(':'))
+            .map(|(property, value)| {
+                (
+                    property.trim().to_ascii_lowercase(),
+                    value.trim().to_owned(),
+                )
+            })
+            .collect();
+        for selector in selectors.split(',').filter_map(parse_selector) {
+            rules.push((selector, declarations.clone()));
+        }
+    }
+    rules
+}
+
+/// Parse a single selector.
+fn parse_selector(selector: &str) -> Option<Selector> {
+    let selector = selector.trim();
+    if selector == "::cue" {
+        return Some(Selector::Cue);
+    }
+    let inner = selector.strip_prefix("::cue(")?.strip_suffix(')')?.trim();
+    if let Some(class) = inner.strip_prefix("c.").or_else(|| inner.strip_prefix('.')) {
+        return Some(Selector::Class(class.to_owned()));
+    }
+    let voice = inner.strip_prefix("v[voice=")?.strip_suffix(']')?;
+    Some(Selector::Voice(voice.trim_matches(['"', '\'']).to_owned()))
+}
+
+/// Apply CSS declarations to a style.
+fn apply_css(style: &mut Style, declarations: &Declarations) {
+    for (property, value) in declarations {
+        let value = value.trim_end_matches("!important").trim();
+        match property.as_str() {
+            "color" => {
+                if let Some(color) = AssColor::from_css(value) {
+                    style.primary_color = color;
+                }
+            }
+            "background-color" | "background" => {
+                if let Some(color) = AssColor::from_css(value) {
+                    // An opaque box, which is drawn with the outline color.
+                    style.border_style = 3;
+                    style.outline_color = color;
+                    style.back_color = color;
+                }
+            }
+            "font-family" => {
+                if let Some(family) = value.split(',').next() {
+                    style.font_name = family.trim().trim_matches(['"', '\'']).to_owned();
+                }
+            }
+            "font-size" => {
+                let size = if let Some(px) = value.strip_suffix("px") {
+                    px.trim().parse().ok()
+                } else if let Some(em) = value.strip_suffix("em") {
+                    em.trim().parse::<f64>().ok().map(|em| em * style.font_size)
+                } else if let Some(percent) = value.strip_suffix('%') {
+                    percent
+                        .trim()
+                        .parse::<f64>()
+                        .ok()
+                        .map(|p| p / 100.0 * style.font_size)
+                } else {
+                    None
+                };
+                if let Some(size) = size.filter(|size: &f64| *size > 0.0) {
+                    style.font_size = size;
+                }
+            }
+            "font-weight" => {
+                style.bold = match value {
+                    "bold" | "bolder" => 1,
+                    "normal" | "lighter" => 0,
+                    weight => weight.parse().unwrap_or(style.bold),
+                };
+            }
+            "font-style" => style.italic = matches!(value, "italic" | "oblique"),
+            "text-decoration" | "text-decoration-line" => {
+                style.underline = value.contains("underline");
+                style.strike_out = value.contains("line-through");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// State for converting cues to events.
+#[derive(Debug)]
+struct Converter {
+    /// The default style, with `::cue` rules applied.
+    base: Style,
+    /// Every CSS rule.
+    rules: Vec<(Selector, Declarations)>,
+    /// Styles created for classes and voices.
+    styles: Vec<Style>,
+    /// Script resolution.
+    play_res: (i32, i32),
+}
+
+/// An open span in a cue payload.
+#[derive(Debug)]
+enum Span {
+    /// `<i>`, `<b>` or `<u>`, by the tag name.
+    Format(char),
+    /// `<c>` or `<v>`, with the style it replaced.
+    Style(String),
+    /// Any other span, such as `<lang>` or `<ruby>`.
+    Other,
+}
+
+impl Converter {
+    /// The style for a class or voice, created on first use. `None` if no rules match.
+    fn style_for(&mut self, selectors: &[Selector]) -> Option<String> {
+        let name = selectors
+            .iter()
+            .map(|selector| match selector {
+                Selector::Class(name) | Selector::Voice(name) => name.as_str(),
+                Selector::Cue => "",
+            })
+            .collect::<Vec<_>>()
+            .join(".");
+        if self.styles.iter().any(|style| style.name == name) {
+            return Some(name);
+        }
+        let mut style = Style {
+            name: name.clone(),
+            ..self.base.clone()
+        };
+        let mut matched = false;
+        for (selector, declarations) in &self.rules {
+            if selectors.contains(selector) {
+                apply_css(&mut style, declarations);
+                matched = true;
+            }
+        }
+        matched.then(|| {
+            self.styles.push(style);
+            name
+        })
+    }
+
+    /// Convert a cue to an event.
+    fn event(&mut self, cue: &Cue) -> Event {
+        let mut event = Event {
+            start: cue.start,
+            end: cue.end,
+            style: self.base.name.clone(),
+            ..Event::default()
+        };
+
+        // A voice or class around the whole cue becomes the event's style.
+        let mut payload = cue.payload.as_str();
+        if let Some((selectors, voice, rest)) = leading_span(payload) {
+            if let Some(voice) = voice {
+                event.name = voice;
+            }
+            if let Some(style) = self.style_for(&selectors) {
+                event.style = style;
+            }
+            payload = rest;
+        }
+
+        let block = self.positioning(cue, &mut event);
+        event.text = format!("{block}{}", self.convert_payload(payload, &event.style));
+        event
+    }
+
+    /// Positioning tags for the cue settings, also setting the event's margins.
+    fn positioning(&self, cue: &Cue, event: &mut Event) -> TagBlock {
+        let settings = &cue.settings;
+        let (width, height) = (f64::from(self.play_res.0), f64::from(self.play_res.1));
+        let style = &self.base;
+        let line_height = style.font_size;
+        let mut block = TagBlock::new();
+
+        if let Some(vertical_rl) = settings.vertical {
+            let font = format!("@{}", style.font_name);
+            block = block.font_name(&font).rotate_z(270.0);
+            return block.align(if vertical_rl { 9 } else { 7 });
+        }
+
+        let horizontal = settings.align;
+        if settings.position.is_none() && settings.line_percent.is_none() {
+            // Margins and alignment only.
+            let mut vertical_row = 0;
+            if let Some(line) = settings.line_number {
+                let (row, lines) = if line >= 0 { (2, line) } else { (0, -line - 1) };
+                vertical_row = row;
+                event.margin_v = style.margin_v + to_px(f64::from(lines) * line_height);
+            }
+            if let Some(size) = settings.size.filter(|size| *size < 100.0) {
+                let spare = to_px((100.0 - size) / 100.0 * width);
+                let (left, right) = match horizontal {
+                    1 => (0, spare),
+                    3 => (spare, 0),
+                    _ => (spare / 2, spare - spare / 2),
+                };
+                event.margin_l = style.margin_l + left;
+                event.margin_r = style.margin_r + right;
+            }
+            if vertical_row != 0 || horizontal != 2 {
+                block = block.align(vertical_row * 3 + horizontal);
+            }
+            return block;
+        }
+
+        let (x, x_index) = match settings.position {
+            Some((percent, align)) => {
+                let align = align.unwrap_or(match horizontal {
+                    1 => LineAlign::Start,
+                    3 => LineAlign::End,
+                    _ => LineAlign::Center,
+                });
+                (percent / 100.0 * width, align.index())
+            }
+            None => match horizontal {
+                1 => (f64::from(style.margin_l), 0),
+                3 => (width - f64::from(style.margin_r), 2),
+                _ => (width / 2.0, 1),
+            },
+        };
+        let (y, row) = match (settings.line_percent, settings.line_number) {
+            // Start is the top of the box, which is the top ASS row.
+            (Some((percent, align)), _) => (percent / 100.0 * height, 2 - align.index()),
+            (None, Some(line)) if line >= 0 => {
+                (f64::from(style.margin_v) + f64::from(line) * line_height, 2)
+            }
+            (None, Some(line)) => (
+                height - f64::from(style.margin_v) - f64::from(-line - 1) * line_height,
+                0,
+            ),
+            (None, None) => (height - f64::from(style.margin_v), 0),
+        };
+        block.align(row * 3 + x_index + 1).pos(x, y)
+    }
+
+    /// Convert the payload markup of a cue to event text.
+    fn convert_payload(&mut self, payload: &str, event_style: &str) -> String {
+        let mut out = String::with_capacity(payload.len());
+        let mut plain = String::new();
+        let mut spans: Vec<Span> = Vec::new();
+        let mut style = event_style.to_owned();
+        let mut rest = payload;
+
+        while let Some(c) = rest.chars().next() {
+            let tag = (c == '<')
+                .then(|| rest.find('>'))
+                .flatten()
+                .map(|end| (&rest[1..end], end));
+            let Some((tag, end)) = tag else {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            };
+            rest = &rest[end + 1..];
+            out.push_str(&escape_text(&decode_entities(&plain)));
+            plain.clear();
+
+            let block = match tag.strip_prefix('/') {
+                Some(name) => self.close_span(name.trim(), &mut spans, &mut style),
+                None => self.open_span(tag, &mut spans, &mut style),
+            };
+            out.push_str(&block.to_string());
+        }
+        out.push_str(&escape_text(&decode_entities(&plain)));
+        out
+    }
+
+    /// Start a span, returning the tags it needs.
+    fn open_span(&mut self, tag: &str, spans: &mut Vec<Span>, style: &mut String) -> TagBlock {
+        let (selectors, _) = span_selectors(tag);
+        let block = TagBlock::new();
+        match tag.chars().next() {
+            Some(format @ ('i' | 'b' | 'u')) if tag.len() == 1 => {
+                spans.push(Span::Format(format));
+                format_tag(block, format, true)
+            }
+            Some('c' | 'v') if !selectors.is_empty() => {
+                let Some(new_style) = self.style_for(&selectors) else {
+                    spans.push(Span::Other);
+                    return block;
+                };
+                spans.push(Span::Style(std::mem::replace(style, new_style.clone())));
+                reapply_formats(block.reset(Some(&new_style)), spans)
+            }
+            // Timestamps inside cues are karaoke style timing, which isn't kept.
+            Some(c) if c.is_ascii_digit() => block,
+            _ => {
+                spans.push(Span::Other);
+                block
+            }
+        }
+    }
+
+    /// End the most recent span, returning the tags that undo it.
+    fn close_span(&self, name: &str, spans: &mut Vec<Span>, style: &mut String) -> TagBlock {
+        let block = TagBlock::new();
+        let Some(span) = spans.pop() else {
+            return block;
+        };
+        match span {
+            Span::Format(format) => {
+                let still_open = spans
+                    .iter()
+                    .any(|span| matches!(span, Span::Format(f) if *f == format));
+                if still_open || !name.starts_with(format) {
+                    block
+                } else {
+                    format_tag(block, format, false)
+                }
+            }
+            Span::Style(previous) => {
+                let block = if previous == *style {
+                    block
+                } else {
+                    let reset = if previous == self.base.name {
+                        block.tag(Tag::Reset(None))
+                    } else {
+                        block.reset(Some(&previous))
+                    };
+                    reapply_formats(reset, spans)
+                };
+                *style = previous;
+                block
+            }
+            Span::Other => block,
+        }
+    }
+}
+
+/// A `<c>` or `<v>` span wrapping the start of a cue.
+///
+/// Returns the span's selectors, the voice name, and the payload after the tag.
+fn leading_span(payload: &str) -> Option<(Vec<Selector>, Option<String>, &str)> {
+    let rest = payload.strip_prefix('<')?;
+    if !rest.starts_with(['c', 'v']) {
+        return None;
+    }
+    let end = rest.find('>')?;
+    let (selectors, voice) = span_selectors(&rest[..end]);
+    // Only whole cue spans, which aren't closed before the end.
+    let after = &rest[end + 1..];
+    let closing = if rest.starts_with('v') {
+        "</v>"
+    } else {
+        "</c>"
+    };
+    if let Some(close) = after.find(closing) {
+        if !after[close + closing.len()..].trim().is_empty() {
+            return None;
+        }
+        return Some((selectors, voice, &after[..close]));
+    }
+    Some((selectors, voice, after))
+}
+
+/// The selectors matching a `<c.class>` or `<v.class Name>` start tag, and the voice name.
+fn span_selectors(tag: &str) -> (Vec<Selector>, Option<String>) {
+    let (head, annotation) = match tag.split_once(char::is_whitespace) {
+        Some((head, annotation)) => (head, Some(annotation.trim())),
+        None => (tag, None),
+    };
+    let mut parts = head.split('.');
+    let name = parts.next().unwrap_or_default();
+    let mut selectors: Vec<Selector> = parts
+        .filter(|class| !class.is_empty())
+        .map(|class| Selector::Class(class.to_owned()))
+        .collect();
+    let voice = (name == "v")
+        .then(|| annotation.filter(|voice| !voice.is_empty()))
+        .flatten()
+        .map(str::to_owned);
+    if let Some(voice) = &voice {
+        selectors.push(Selector::Voice(voice.clone()));
+    }
+    if name != "c" && name != "v" {
+        selectors.clear();
+    }
+    (selectors, voice)
+}
+
+/// Add `\i`, `\b` or `\u`.
+fn format_tag(block: TagBlock, format: char, enable: bool) -> TagBlock {
+    match format {
+        'i' => block.italic(enable),
+        'b' => block.bold(enable),
+        _ => block.underline(enable),
+    }
+}
+
+/// Turn formatting spans that are still open back on after a `\r`.
+fn reapply_formats(mut block: TagBlock, spans: &[Span]) -> TagBlock {
+    for format in ['i', 'b', 'u'] {
+        if spans
+            .iter()
+            .any(|span| matches!(span, Span::Format(f) if *f == format))
+        {
+            block = format_tag(block, format, true);
+        }
+    }
+    block
+}
+
+/// Decode the HTML character references WebVTT allows.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_owned();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').and_then(|end| {
+            let name = &rest[1..end];
+            let c = match name {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                "lrm" => '\u{200e}',
+                "rlm" => '\u{200f}',
+                _ => {
+                    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                    };
+                    char::from_u32(code?)?
+                }
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse at 1000x500 with a 50 pixel default font.
+    fn import(text: &str) -> ScriptDocument {
+        let style = Style {
+            font_size: 50.0,
+            ..Style::default()
+        };
+        parse(
+            text,
+            &ImportOptions::default().style(style).play_res(1000, 500),
+        )
+    }
+
+    #[test]
+    fn cues() {
+        let doc = import(
+            "\u{feff}WEBVTT - Title\n\n\
+             NOTE a comment\n\
+             spanning lines\n\n\
+             REGION\nid:r\n\n\
+             intro\n\
+             00:01.000 --> 00:02.500\n\
+             Hello &amp; <b>welcome</b> {x}\n\
+             second line\n\n\
+             01:00:00.000 --> 01:00:01.000\n\
+             Hour\n\n\
+             a\nb\n00:03.000 --> 00:04.000\nIdentifier too long\n\n\
+             00:05.000 --> bad\nBad timing\n",
+        );
+        let cues: Vec<_> = doc
+            .events
+            .iter()
+            .map(|event| {
+                let millis = |time| AssTime::from(time).as_millis();
+                (millis(event.start), millis(event.end), event.text.as_str())
+            })
+            .collect();
+        assert_eq!(
+            cues,
+            [
+                (1000, 2500, r"Hello & {\b1}welcome{\b0} \{x\}\Nsecond line"),
+                (3_600_000, 3_601_000, "Hour"),
+            ]
+        );
+    }
+
+    #[test]
+    fn settings() {
+        let text = |settings: &str| {
+            let doc = import(&format!(
+                "WEBVTT\n\n00:00.000 --> 00:01.000 {settings}\nText\n"
+            ));
+            let event = doc.events.into_iter().next().unwrap();
+            (event.text, event.margin_l, event.margin_r, event.margin_v)
+        };
+        assert_eq!(text(""), ("Text".to_owned(), 0, 0, 0));
+        assert_eq!(text("align:start"), (r"{\an1}Text".to_owned(), 0, 0, 0));
+        // Line 0 is the top row, and each line below is a font size further down.
+        assert_eq!(text("line:1"), (r"{\an8}Text".to_owned(), 0, 0, 60));
+        assert_eq!(text("line:-1"), ("Text".to_owned(), 0, 0, 10));
+        assert_eq!(text("size:50%"), ("Text".to_owned(), 260, 260, 0));
+        assert_eq!(
+            text("line:10% position:25%,line-left align:end"),
+            (r"{\an7\pos(250,50)}Text".to_owned(), 0, 0, 0)
+        );
+        assert_eq!(
+            text("vertical:rl"),
+            (r"{\fn@Arial\frz270\an9}Text".to_owned(), 0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn styles_and_voices() {
+        let doc = import(
+            "WEBVTT\n\n\
+             STYLE\n\
+             ::cue { color: yellow; font-family: \"Noto Sans\", sans-serif }\n\
+             /* comment */ ::cue(.loud) { font-weight: bold; font-size: 2em }\n\
+             ::cue(v[voice=\"Ann\"]) { font-style: italic }\n\n\
+             00:00.000 --> 00:01.000\n\
+             <v Ann>Whole cue</v>\n\n\
+             00:01.000 --> 00:02.000\n\
+             <i>a <c.loud>b</c> c</i> <lang en>d</lang>\n",
+        );
+        let base = &doc.styles[0];
+        assert_eq!(base.primary_color, AssColor::rgb(255, 255, 0));
+        assert_eq!(base.font_name, "Noto Sans");
+
+        let ann = doc.style("Ann").unwrap();
+        assert!(ann.italic);
+        assert_eq!(doc.events[0].style, "Ann");
+        assert_eq!(doc.events[0].name, "Ann");
+        assert_eq!(doc.events[0].text, "Whole cue");
+
+        let loud = doc.style("loud").unwrap();
+        assert_eq!((loud.bold, loud.font_size), (1, 100.0));
+        assert_eq!(doc.events[1].text, r"{\i1}a {\rloud\i1}b{\r\i1} c{\i0} d");
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            decode_entities("&lt;&gt;&quot;&#65;&#x42;&unknown; & &nbsp"),
+            "<>\"AB&unknown; & &nbsp"
+        );
+    }
+}
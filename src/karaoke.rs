@@ -0,0 +1,272 @@
+//! Karaoke syllables
+//!
+//! Karaoke events time each syllable with a `\k`, `\K`, `\kf` or `\ko` tag before it, giving its
+//! duration in centiseconds. [`KaraokeLine::parse`] splits event text in to [`Syllable`]s with
+//! absolute start and end times, and [`KaraokeLine::to_text`] rebuilds the text from them, so
+//! syllables can be retimed without handling tags.
+use std::fmt::Write;
+
+use crate::{
+    script::Event,
+    tags::{find_block, Item, KaraokeKind, Tag},
+    timing::AssTime,
+};
+
+/// A karaoke syllable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Syllable {
+    /// The text up to the next syllable, with escapes and any override blocks in it as written.
+    pub text: String,
+    #[allow(missing_docs)]
+    pub start: AssTime,
+    #[allow(missing_docs)]
+    pub end: AssTime,
+    /// How the syllable is highlighted.
+    pub kind: KaraokeKind,
+    /// Other tags from the override block the karaoke tag was in, as written.
+    pub tags: String,
+}
+
+impl Syllable {
+    #[allow(missing_docs)]
+    pub fn duration(&self) -> AssTime {
+        self.end - self.start
+    }
+
+    /// The text without override blocks.
+    pub fn plain_text(&self) -> String {
+        crate::tags::parse(&self.text)
+            .items
+            .into_iter()
+            .filter_map(|item| match item.value {
+                Item::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Event text split in to karaoke syllables.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct KaraokeLine {
+    /// Text and override blocks before the first syllable, as written.
+    pub prefix: String,
+    /// The syllables in order.
+    pub syllables: Vec<Syllable>,
+}
+
+impl KaraokeLine {
+    /// Split the text of an event starting at `start` in to syllables.
+    ///
+    /// Each karaoke tag starts a syllable when the previous one ends, unless a `\kt` before it
+    /// sets its start in centiseconds from the start of the event. Other tags in the same
+    /// override block are kept in [`Syllable::tags`], and the rest of the text up to the next
+    /// karaoke tag is the syllable's text.
+    pub fn parse(text: &str, start: AssTime) -> Self {
+        let mut line = Self::default();
+        // Time from the start of the event to the start of the next syllable.
+        let mut time = AssTime::ZERO;
+        let mut pos = 0;
+        while pos < text.len() {
+            let (open, close) = match find_block(text, pos)
+                .and_then(|open| Some((open, open + text[open..].find('}')?)))
+            {
+                Some(block) => block,
+                None => (text.len(), text.len()),
+            };
+            line.push_text(&text[pos..open]);
+            if open == text.len() {
+                break;
+            }
+
+            let block = &text[open + 1..close];
+            let mut started = false;
+            // Tags before the first karaoke tag of a block go with its syllable.
+            let mut pending = String::new();
+            let mut piece_start = 0;
+            for item in crate::tags::parse(&text[open..=close]).items {
+                // Spans are of the block with its opening brace.
+                let span = item.span.start - 1..item.span.end - 1;
+                let (kind, duration) = match item.value {
+                    Item::Tag(Tag::Karaoke { kind, duration }) => (Some(kind), duration),
+                    Item::Tag(Tag::KaraokeTime(centis)) => {
+                        time = AssTime::from_millis(i64::from(centis) * 10);
+                        (None, 0)
+                    }
+                    _ => continue,
+                };
+                line.push_tags(started, &mut pending, &block[piece_start..span.start]);
+                piece_start = span.end;
+                let Some(kind) = kind else {
+                    continue;
+                };
+
+                let syllable_start = start + time;
+                time += AssTime::from_millis(i64::from(duration) * 10);
+                line.syllables.push(Syllable {
+                    text: String::new(),
+                    start: syllable_start,
+                    end: start + time,
+                    kind,
+                    tags: std::mem::take(&mut pending),
+                });
+                started = true;
+            }
+
+            if started {
+                line.push_tags(true, &mut pending, &block[piece_start..]);
+            } else {
+                line.push_text(&text[open..=close]);
+            }
+            pos = close + 1;
+        }
+        line
+    }
+
+    /// Rebuild event text for an event starting at `start`.
+    ///
+    /// Durations are rounded to centiseconds, and a `\kt` is added before syllables that don't
+    /// start when the previous one ends. `\K` is written as `\kf`, which is the same tag.
+    pub fn to_text(&self, start: AssTime) -> String {
+        let mut out = self.prefix.clone();
+        let mut time = AssTime::ZERO;
+        for syllable in &self.syllables {
+            let offset = (syllable.start - start).round_to_centis();
+            out.push('{');
+            if offset != time {
+                let _ = write!(out, "{}", Tag::KaraokeTime(centis(offset)));
+                time = offset;
+            }
+            let duration = centis(syllable.duration().round_to_centis());
+            time += AssTime::from_millis(i64::from(duration) * 10);
+            let _ = write!(
+                out,
+                "{}{}}}{}",
+                Tag::Karaoke {
+                    kind: syllable.kind,
+                    duration,
+                },
+                syllable.tags,
+                syllable.text
+            );
+        }
+        out
+    }
+
+    /// Add text outside of a karaoke block to the current syllable.
+    fn push_text(&mut self, text: &str) {
+        match self.syllables.last_mut() {
+            Some(syllable) => syllable.text.push_str(text),
+            None => self.prefix.push_str(text),
+        }
+    }
+
+    /// Add tags from a karaoke block to the syllable started in it, or keep them for the next.
+    fn push_tags(&mut self, started: bool, pending: &mut String, tags: &str) {
+        match self.syllables.last_mut() {
+            Some(syllable) if started => syllable.tags.push_str(tags),
+            _ => pending.push_str(tags),
+        }
+    }
+}
+
+impl Event {
+    /// Split the event's text in to karaoke syllables. See [`KaraokeLine::parse`].
+    pub fn karaoke(&self) -> KaraokeLine {
+        KaraokeLine::parse(&self.text, AssTime::from(self.start))
+    }
+
+    /// Replace the event's text with karaoke syllables. See [`KaraokeLine::to_text`].
+    pub fn set_karaoke(&mut self, line: &KaraokeLine) {
+        self.text = line.to_text(AssTime::from(self.start));
+    }
+}
+
+/// Whole centiseconds of a time rounded to centiseconds, saturating at the limits of a tag.
+fn centis(time: AssTime) -> i32 {
+    let centis = time.as_millis() / 10;
+    i32::try_from(centis).unwrap_or(if centis < 0 { i32::MIN } else { i32::MAX })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (start, end, kind, tags, text) of each syllable, with times in milliseconds.
+    fn syllables(line: &KaraokeLine) -> Vec<(i64, i64, KaraokeKind, &str, &str)> {
+        line.syllables
+            .iter()
+            .map(|syllable| {
+                (
+                    syllable.start.as_millis(),
+                    syllable.end.as_millis(),
+                    syllable.kind,
+                    syllable.tags.as_str(),
+                    syllable.text.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        let text = r"{\an8}Intro {\be1\k20\1c&HFF&}ka{\K30}ra{\i1}o{\kt100\ko10\i0}ke\{x\}";
+        let line = KaraokeLine::parse(text, AssTime::from_millis(1000));
+        assert_eq!(line.prefix, r"{\an8}Intro ");
+        assert_eq!(
+            syllables(&line),
+            [
+                (1000, 1200, KaraokeKind::Fill, r"\be1\1c&HFF&", "ka"),
+                (1200, 1500, KaraokeKind::Sweep, "", r"ra{\i1}o"),
+                (2000, 2100, KaraokeKind::Outline, r"\i0", r"ke\{x\}"),
+            ]
+        );
+        assert_eq!(line.syllables[1].plain_text(), "rao");
+        assert_eq!(line.syllables[1].duration(), AssTime::from_millis(300));
+    }
+
+    #[test]
+    fn round_trip() {
+        let start = AssTime::from_millis(5000);
+        for text in [
+            "",
+            "No karaoke {\\b1}here",
+            r"{\k10}a{\kf20}b{\ko30\bord2}c",
+            r"pre{\k10}a{\kt50\k5}b{\k0}",
+        ] {
+            let line = KaraokeLine::parse(text, start);
+            assert_eq!(line.to_text(start), text);
+            assert_eq!(KaraokeLine::parse(&line.to_text(start), start), line);
+        }
+        // `\K` is the same tag as `\kf`.
+        let line = KaraokeLine::parse(r"{\K10}a", start);
+        assert_eq!(line.to_text(start), r"{\kf10}a");
+    }
+
+    #[test]
+    fn retiming() {
+        let mut event = Event {
+            start: time::Duration::seconds(2),
+            text: r"{\k10}a{\k10}b{\k10}c".to_owned(),
+            ..Event::default()
+        };
+        let mut line = event.karaoke();
+        // Stretch the second syllable and leave a gap before the third.
+        line.syllables[1].end += AssTime::from_millis(154);
+        line.syllables[2].start = AssTime::from_millis(2500);
+        line.syllables[2].end = AssTime::from_millis(2600);
+        event.set_karaoke(&line);
+        assert_eq!(event.text, r"{\k10}a{\k25}b{\kt50\k10}c");
+    }
+
+    #[test]
+    fn overflow() {
+        let line = KaraokeLine::parse(r"{\k2147483647}a{\k2147483647}b", AssTime::ZERO);
+        assert_eq!(line.syllables[1].end.as_millis(), 42_949_672_940);
+        assert_eq!(
+            line.to_text(AssTime::ZERO),
+            r"{\k2147483647}a{\k2147483647}b"
+        );
+        assert_eq!(centis(AssTime::from_millis(i64::MIN)), i32::MIN);
+    }
+}
@@ -14,10 +14,34 @@
 )]
 #![doc = include_str!("../README.md")]
 
+pub mod color;
+pub mod drawing;
+#[cfg(feature = "encoding")]
+pub mod encoding;
+pub mod error;
+mod export;
+pub mod fonts;
+pub mod frame;
+pub mod import;
+pub mod karaoke;
 pub mod library;
+pub mod pool;
 pub mod render;
+mod resample;
+pub mod script;
+pub mod tags;
+pub mod timing;
 pub mod track;
+pub mod yuv;
 
+pub use color::AssColor;
+pub use drawing::DrawingPath;
+pub use error::{Error, Result};
+pub use frame::RgbaFrame;
 pub use library::Library;
-pub use render::{Renderer, RendererConfig};
+pub use pool::RendererPool;
+pub use render::{Renderer, RendererConfig, RendererSettings};
+pub use script::ScriptDocument;
+pub use timing::{AssTime, RetimeOptions};
 pub use track::Track;
+pub use yuv::{ColorSpace, YuvFrameMut};
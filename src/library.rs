@@ -5,57 +5,239 @@ use core::{ptr::NonNull, slice};
 
 use libass_sys::ASS_Library;
 use std::{
+    cell::{Cell, RefCell},
     ffi::{c_char, c_int, c_void, CStr},
+    fmt,
     marker::PhantomData,
-    mem::ManuallyDrop,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
-use crate::track::Track;
+use crate::{
+    error::{Error, Result},
+    render::Renderer,
+    track::{Track, FEATURE_COUNT},
+};
 
 /// Libass Library instance
-#[derive(Debug, PartialEq)]
-#[allow(clippy::missing_docs_in_private_items)]
+///
+/// This is a reference counted handle, so cloning it is cheap and every clone refers to the same
+/// Libass library. Each `Track` and `Renderer` keeps a clone alive, so they do not borrow the
+/// `Library` and may outlive the handle they were created from. The underlying library is
+/// released once the last handle referencing it is dropped.
+///
+/// # Threading
+///
+/// Libass has no internal synchronization, but the library handle is only read by tracks and
+/// renderers, with the exception of embedded fonts being extracted in to it while a track is
+/// parsed. So every call that mutates library state takes an internal write lock, and every call
+/// that reads it (creating tracks and renderers, configuring renderer fonts, rendering) takes a
+/// read lock. This makes `Library` both `Send` and `Sync`, and a track can be parsed on one thread
+/// while a different track is rendered on another.
+#[derive(Debug, Clone)]
 pub struct Library {
+    /// Shared owner of the Libass library handle.
+    inner: Arc<LibraryHandle>,
+}
+
+/// Owner of the raw Libass library pointer.
+///
+/// Dropping this is what finalizes the library, so it must only happen once every `Track` and
+/// `Renderer` is gone. That is guarenteed by them holding a `Library`.
+#[derive(Debug)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct LibraryHandle {
     lib: NonNull<ASS_Library>,
     phan: PhantomData<ASS_Library>,
+    /// Guards the library state. See the threading section on `Library`.
+    state: RwLock<LibraryState>,
 }
 
+/// Closure type that Libass messages are forwarded to.
+type MessageCallback = Box<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+/// State owned on behalf of Libass, guarded by the library lock.
+#[derive(Default)]
+pub(crate) struct LibraryState {
+    /// The registered message callback.
+    ///
+    /// Double boxed so that Libass can be given a thin pointer to it, which stays valid until
+    /// the callback is replaced, cleared, or the library is dropped.
+    message_cb: Option<Box<MessageCallback>>,
+}
+
+impl fmt::Debug for LibraryState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LibraryState")
+            .field("message_cb", &self.message_cb.is_some())
+            .finish()
+    }
+}
+
+// Safety:
+// The library handle isn't tied to the thread that created it, and all access to the library
+// state goes through the `state` lock.
+unsafe impl Send for LibraryHandle {}
+unsafe impl Sync for LibraryHandle {}
+
 impl Library {
     /// Construct a new Libass library instance
     ///
-    /// Returns None if allocation in library fails.
-    pub fn new() -> Option<Self> {
+    /// Returns `Error::Alloc` if allocation in library fails.
+    pub fn new() -> Result<Self> {
         // Safety: There is no global state this function acesses.
-        let new = NonNull::new(unsafe { libass_sys::ass_library_init() })?;
+        let new = NonNull::new(unsafe { libass_sys::ass_library_init() }).ok_or(Error::Alloc {
+            function: "ass_library_init",
+        })?;
+        // Our handler acts like Libass' default one until a callback is set, and also lets
+        // messages be captured.
+        // Safety: No callback data is given, and the handler never reads a null one.
+        unsafe {
+            libass_sys::ass_set_message_cb(
+                new.as_ptr(),
+                Some(message_handler),
+                core::ptr::null_mut(),
+            )
+        }
 
-        Some(Self {
-            lib: new,
-            phan: PhantomData,
+        Ok(Self {
+            inner: Arc::new(LibraryHandle {
+                lib: new,
+                phan: PhantomData,
+                state: RwLock::new(LibraryState::default()),
+            }),
         })
     }
 
+    /// Raw pointer to the Libass library handle.
+    pub(crate) fn as_ptr(&self) -> *mut ASS_Library {
+        self.inner.lib.as_ptr()
+    }
+
+    /// Lock the library state for reading.
+    ///
+    /// Must be held while calling any Libass function that reads the library state, such as
+    /// creating a track or renderer, setting renderer fonts, or rendering.
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, LibraryState> {
+        // Nothing in the state can be left inconsistent by a panic, so poisoning is ignored.
+        self.inner
+            .state
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lock the library state for writing.
+    ///
+    /// Must be held while calling any Libass function that modifies the library state, including
+    /// parsing track data, which may extract embedded fonts in to the library.
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, LibraryState> {
+        self.inner
+            .state
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
     /// Set callback for logging.
     ///
-    /// May reference count this closure later, but making it static is easiest for now.
-    /// Since it shouldn't change often.
+    /// The library owns the callback. Setting a new one drops the previous callback, and it is
+    /// also dropped by `Library::clear_message_cb` or once the library is released.
+    ///
+    /// The callback must be `Send` and `Sync` because Libass calls it from whichever thread is
+    /// parsing a track or rendering a frame, and tracks and renderers of the same library may be
+    /// used on different threads at the same time.
     pub fn set_message_cb<T>(&self, callback: T)
     where
         T: Fn(LogLevel, &str) + Send + Sync,
         T: 'static,
     {
-        let mut leaked_cb = ManuallyDrop::new(callback);
-        let mut cb: &mut dyn Fn(LogLevel, &str) = &mut leaked_cb as &mut T as _;
-        let cb = &mut cb;
+        // Messages are only emitted while the lock is held, so no message can be in flight while
+        // the callback is swapped.
+        let mut state = self.write();
+        self.install_message_cb(&mut state, Some(Box::new(Box::new(callback))));
+    }
 
-        // Safety: It is leaked and also static so it should last as long
-        // as needed.
-        unsafe {
-            libass_sys::ass_set_message_cb(
-                self.lib.as_ptr(),
-                Some(message_handler),
-                cb as *mut _ as *mut c_void,
-            )
+    /// Register a callback with Libass and store it in the state, dropping the previous one.
+    ///
+    /// `None` goes back to reporting messages to stderr the way Libass does by default.
+    fn install_message_cb(&self, state: &mut LibraryState, callback: Option<Box<MessageCallback>>) {
+        // Libass ignores a null callback and would keep the old data pointer, so the handler is
+        // always registered, with null data when there is no callback.
+        let data = callback
+            .as_deref()
+            .map_or(core::ptr::null_mut(), |callback| {
+                (callback as *const MessageCallback).cast_mut()
+            });
+        // Safety:
+        // The callback is owned by the library state and is only dropped after Libass has been
+        // given a different pointer, or after the library is finalized.
+        unsafe { libass_sys::ass_set_message_cb(self.as_ptr(), Some(message_handler), data.cast()) }
+        state.message_cb = callback;
+    }
+
+    /// Run `f`, collecting the Libass messages it causes on this thread.
+    ///
+    /// Libass calls the message handler on the thread that caused the message, so messages from
+    /// other threads aren't collected. They are still passed on to the callback set with
+    /// `Library::set_message_cb`, which is left in place.
+    pub(crate) fn capture_messages<R>(&self, f: impl FnOnce() -> R) -> (R, Vec<String>) {
+        /// Puts back the capture `f` was nested in, even if `f` panics.
+        struct Restore(Option<Option<Vec<String>>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                if let Some(outer) = self.0.take() {
+                    CAPTURED.with(|captured| *captured.borrow_mut() = outer);
+                }
+            }
         }
+
+        let mut restore = Restore(Some(
+            CAPTURED.with(|captured| captured.replace(Some(Vec::new()))),
+        ));
+        let result = f();
+        let outer = restore.0.take().unwrap_or_default();
+        let messages = CAPTURED.with(|captured| captured.replace(outer));
+        (result, messages.unwrap_or_default())
+    }
+
+    /// Forward Libass messages to the `log` crate, with the target `libass`.
+    ///
+    /// Replaces any callback set with `Library::set_message_cb`. See the `From<LogLevel>` impl
+    /// for `log::Level` for how levels are mapped.
+    #[cfg(feature = "log")]
+    pub fn forward_to_log(&self) {
+        self.set_message_cb(
+            |level, message| log::log!(target: "libass", level.into(), "{}", message.trim_end()),
+        )
+    }
+
+    /// Forward Libass messages to `tracing` as events, with the target `libass`.
+    ///
+    /// Replaces any callback set with `Library::set_message_cb`. See the `From<LogLevel>` impl
+    /// for `tracing::Level` for how levels are mapped.
+    #[cfg(feature = "tracing")]
+    pub fn forward_to_tracing(&self) {
+        use tracing::{event, Level};
+
+        self.set_message_cb(|level, message| {
+            let message = message.trim_end();
+            // The level of an event must be a constant.
+            match level.into() {
+                Level::ERROR => event!(target: "libass", Level::ERROR, "{}", message),
+                Level::WARN => event!(target: "libass", Level::WARN, "{}", message),
+                Level::INFO => event!(target: "libass", Level::INFO, "{}", message),
+                Level::DEBUG => event!(target: "libass", Level::DEBUG, "{}", message),
+                Level::TRACE => event!(target: "libass", Level::TRACE, "{}", message),
+            }
+        })
+    }
+
+    /// Remove the logging callback, dropping it.
+    ///
+    /// Messages with a level below `LogLevel::Application` go back to being reported to stderr,
+    /// as Libass does when no callback has been set.
+    pub fn clear_message_cb(&self) {
+        let mut state = self.write();
+        self.install_message_cb(&mut state, None);
     }
 
     /// Get the avaliable font providers
@@ -73,7 +255,7 @@ impl Library {
         // references provided. Also checked that the pointers aren't leaked anywhere.
         unsafe {
             libass_sys::ass_get_available_font_providers(
-                self.lib.as_ptr(),
+                self.as_ptr(),
                 (&mut out_ptr as *mut Option<NonNull<i32>>).cast(),
                 &mut count as _,
             )
@@ -97,8 +279,9 @@ impl Library {
 
     /// Whether fonts should be extracted from the track data.
     pub fn extract_fonts(&self, extract: bool) {
+        let _lock = self.write();
         // Safety: This is basically just a setter on the library handle.
-        unsafe { libass_sys::ass_set_extract_fonts(self.lib.as_ptr(), extract.into()) }
+        unsafe { libass_sys::ass_set_extract_fonts(self.as_ptr(), extract.into()) }
     }
 
     /// Set additional font directory for lookup.
@@ -107,9 +290,10 @@ impl Library {
     ///
     /// Libass copies the name so lifetime is managed for us.
     pub fn set_font_dir(&self, dir: &CStr) {
+        let _lock = self.write();
         // Safety:
         // Libass copies the string provided and doesn't leak the pointer at all.
-        unsafe { libass_sys::ass_set_fonts_dir(self.lib.as_ptr(), dir.as_ptr()) }
+        unsafe { libass_sys::ass_set_fonts_dir(self.as_ptr(), dir.as_ptr()) }
     }
 
     /// Load font in to library instance
@@ -118,18 +302,19 @@ impl Library {
     /// Internally Libass copies the string and the
     /// data so it manages the lifetimes.
     #[allow(dead_code, unused_variables, unreachable_code)]
-    fn add_font<T>(&self, name: T, data: &[()]) -> ()
+    fn add_font<T>(&self, name: T, data: &[()])
     where
         T: AsRef<CStr>,
     {
         /// Cute trick to reduce compile times.
         fn inner_font(lib: &Library, name: &CStr, data: &[()]) {
+            let _lock = lib.write();
             // Safety:
             // It copies the name and doesn't leak the pointer anywhere
             // Data is also memcpy'd to the library through the handle.
             unsafe {
                 libass_sys::ass_add_font(
-                    lib.lib.as_ptr(),
+                    lib.as_ptr(),
                     name.as_ptr(),
                     data.as_ptr() as *const i8,
                     data.len().try_into().unwrap(),
@@ -142,14 +327,19 @@ impl Library {
 
     /// Clear all fonts associated with the Library instance
     ///
-    /// Must take ownership of the Library because all Track
-    /// and Render instance must be released before this
-    /// method can be called.
-    pub fn clear_fonts(self) -> Self {
+    /// All Track and Render instances must be released before this method can be called. If any
+    /// other handle to this library (a clone, `Track`, or `Renderer`) is still alive the fonts are
+    /// not cleared and `Error::LibraryInUse` is returned.
+    pub fn clear_fonts(&mut self) -> Result<()> {
+        if Arc::get_mut(&mut self.inner).is_none() {
+            return Err(Error::LibraryInUse);
+        }
+
         // Safety:
         // It frees memory in the library that was allocated within the library.
-        unsafe { libass_sys::ass_clear_fonts(self.lib.as_ptr()) }
-        self
+        // This is the only handle left, so no track or renderer can reference the fonts.
+        unsafe { libass_sys::ass_clear_fonts(self.as_ptr()) }
+        Ok(())
     }
 
     /// Register style overrides for this library instance.
@@ -158,28 +348,102 @@ impl Library {
     #[allow(dead_code, unreachable_code, unused_variables)]
     fn style_overrides(&self, overrides: &()) {
         todo!("Make custom style override type");
+        let _lock = self.write();
         // Safety
         // It copies the overrides so it doesn't outlive the owner.
-        unsafe {
-            libass_sys::ass_set_style_overrides(self.lib.as_ptr(), overrides as *const () as _)
-        }
+        unsafe { libass_sys::ass_set_style_overrides(self.as_ptr(), overrides as *const () as _) }
     }
 
     /// Allocate new `Track` for a new subtitle stream.
-    pub fn new_track(&self) -> Option<Track> {
-        let new = NonNull::new(unsafe { libass_sys::ass_new_track(self.lib.as_ptr()) })?;
-        Some(Track {
+    ///
+    /// The track keeps this library alive.
+    pub fn new_track(&self) -> Result<Track> {
+        let new = {
+            let _lock = self.read();
+            NonNull::new(unsafe { libass_sys::ass_new_track(self.as_ptr()) }).ok_or(
+                Error::Alloc {
+                    function: "ass_new_track",
+                },
+            )?
+        };
+        Ok(Track {
             track: new,
-            lib: self,
+            lib: self.clone(),
             phantom: PhantomData,
+            features: Cell::new([None; FEATURE_COUNT]),
         })
     }
+
+    /// Parse a whole subtitle file in memory in to a new `Track`.
+    ///
+    /// With a `codepage`, Libass converts the data from it to UTF-8 with iconv, which only works if
+    /// Libass was built with iconv. Otherwise the data must be UTF-8.
+    ///
+    /// Returns `Error::LoadFailed` if Libass couldn't convert or parse the data.
+    pub fn read_memory(&self, data: &[u8], codepage: Option<&CStr>) -> Result<Track> {
+        let new = {
+            // Embedded fonts may be extracted in to the library.
+            let _lock = self.write();
+            // Safety:
+            // Libass copies the data before parsing it, and only reads the codepage.
+            let track = unsafe {
+                libass_sys::ass_read_memory(
+                    self.as_ptr(),
+                    data.as_ptr().cast_mut().cast(),
+                    data.len(),
+                    codepage.map_or(std::ptr::null_mut(), |codepage| {
+                        codepage.as_ptr().cast_mut()
+                    }),
+                )
+            };
+            NonNull::new(track).ok_or(Error::LoadFailed {
+                function: "ass_read_memory",
+            })?
+        };
+        Ok(Track {
+            track: new,
+            lib: self.clone(),
+            phantom: PhantomData,
+            features: Cell::new([None; FEATURE_COUNT]),
+        })
+    }
+
+    /// Allocate a new `Renderer`.
+    ///
+    /// The renderer keeps this library alive. Before rendering it must be configured with at
+    /// least a frame size, storage size, and fonts, see `Renderer::apply_config`.
+    pub fn new_renderer(&self) -> Result<Renderer> {
+        let new = {
+            let _lock = self.read();
+            // Safety: Only reads the library to set up the renderer.
+            NonNull::new(unsafe { libass_sys::ass_renderer_init(self.as_ptr()) }).ok_or(
+                Error::Alloc {
+                    function: "ass_renderer_init",
+                },
+            )?
+        };
+        Ok(Renderer {
+            renderer: new,
+            data: PhantomData,
+            parent: self.clone(),
+            settings: RefCell::default(),
+        })
+    }
+}
+
+impl PartialEq for Library {
+    /// Handles are equal if they refer to the same Libass library.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
 }
 
-impl Drop for Library {
+impl Drop for LibraryHandle {
     fn drop(&mut self) {
         // Safety:
         // :ferrisclueless:
+        // Every track and renderer holds a reference to this handle so they are all gone by now.
+        // The message callback in the state is dropped after this, once Libass can't call it.
         unsafe { libass_sys::ass_library_done(self.lib.as_ptr()) }
     }
 }
@@ -222,42 +486,105 @@ impl From<i32> for LogLevel {
     }
 }
 
+#[cfg(feature = "log")]
+impl From<LogLevel> for log::Level {
+    /// Libass' own levels are finer than `log`'s, so some are merged.
+    ///
+    /// * `Fatal` and `Error` are `Error`
+    /// * `Warn` is `Warn`
+    /// * `Info` is `Info`
+    /// * `Application` and `Verbose` are `Debug`. Libass doesn't print these to stderr by default,
+    ///   so they are treated as diagnostics rather than information.
+    /// * `Debug` is `Trace`
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Fatal | LogLevel::Error => log::Level::Error,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Application | LogLevel::Verbose => log::Level::Debug,
+            LogLevel::Debug => log::Level::Trace,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<LogLevel> for tracing::Level {
+    /// Mapped the same way as for `log::Level`.
+    ///
+    /// * `Fatal` and `Error` are `ERROR`
+    /// * `Warn` is `WARN`
+    /// * `Info` is `INFO`
+    /// * `Application` and `Verbose` are `DEBUG`
+    /// * `Debug` is `TRACE`
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Fatal | LogLevel::Error => tracing::Level::ERROR,
+            LogLevel::Warn => tracing::Level::WARN,
+            LogLevel::Info => tracing::Level::INFO,
+            LogLevel::Application | LogLevel::Verbose => tracing::Level::DEBUG,
+            LogLevel::Debug => tracing::Level::TRACE,
+        }
+    }
+}
+
+extern "C" {
+    /// The C library's `vsnprintf`, taking the argument list exactly as Libass passes it on.
+    fn vsnprintf(
+        buf: *mut c_char,
+        len: usize,
+        fmt: *const c_char,
+        args: libass_sys::va_list,
+    ) -> c_int;
+}
+
+thread_local! {
+    /// Messages collected by `Library::capture_messages` on this thread, while it runs.
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Longest message kept, in bytes. Longer messages are cut short.
+const MESSAGE_LEN: usize = 1024;
+
 /// Handler for the libass logging
-/// TODO: Figure out something to do with the variadic argument
+///
+/// Messages are formatted before being passed on, as Libass only gives the format string and its
+/// arguments.
 extern "C" fn message_handler(
     level: c_int,
     fmt: *const c_char,
-    _: libass_sys::va_list,
+    args: libass_sys::va_list,
     data: *mut c_void,
 ) {
-    let mess = {
-        if fmt.is_null() {
-            // Safety:
-            // the string has a static lifetime and is valid UTF-8 (empty).
-            unsafe {
-                CStr::from_bytes_with_nul_unchecked(b"\0")
-                    .to_str()
-                    .unwrap_unchecked()
-            }
-        } else {
-            // Safety:
-            // I know that it will atleast live through 'a
-            // But I have no checked every log callsite so
-            // let's hope for the best that fmt is always valid.
-            unsafe { CStr::from_ptr(fmt).to_str().unwrap_or("") }
-        }
-    };
+    let mut buf = [0u8; MESSAGE_LEN];
+    if !fmt.is_null() {
+        // Safety:
+        // Libass passes a valid format string with matching arguments, and the argument list is
+        // only used this once. `vsnprintf` writes at most `buf.len()` bytes, including the nul.
+        unsafe { vsnprintf(buf.as_mut_ptr().cast(), buf.len(), fmt, args) };
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let mess = String::from_utf8_lossy(&buf[..len]);
+    let mess = &*mess;
     let log_lev = level.into();
 
+    // The slot may already be gone if the thread is exiting, and the message is then not captured.
+    let _ = CAPTURED.try_with(|captured| {
+        if let Ok(Some(captured)) = captured.try_borrow_mut().as_deref_mut() {
+            captured.push(mess.to_owned());
+        }
+    });
+
     // Safety:
-    // I believe this is correct because it has Send+Sync bounds, so it should be safe to call
-    // concurrently, though I do not believe Libass does? It may though. Libass keeps a pointer to
-    // the closure within itself, but it doesn't modify the data at all as it is a c_void.
-    let data = unsafe { data.cast::<&dyn Fn(LogLevel, &str)>().as_ref() };
-
-    if let Some(data_ref) = data {
-        let closure = data_ref;
-        closure(log_lev, mess)
+    // The pointer is null or to the callback owned by the library state, which outlives its
+    // registration with Libass. The closure is `Send + Sync`, so calling it from several threads at once is
+    // fine.
+    let callback = unsafe { data.cast_const().cast::<MessageCallback>().as_ref() };
+
+    match callback {
+        Some(callback) => callback(log_lev, mess),
+        // Without a callback, do what Libass' own handler does.
+        None if log_lev < LogLevel::Application => eprintln!("[ass] {mess}"),
+        None => {}
     }
 }
 
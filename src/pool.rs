@@ -0,0 +1,125 @@
+//! Parallel rendering with a pool of renderers
+//!
+use std::{num::NonZeroUsize, thread};
+
+use time::Duration;
+
+use crate::{error::Result, frame::RgbaFrame, Library, Renderer, RendererConfig, Track};
+
+/// A set of identically configured `Renderer`s used to render many timestamps in parallel.
+///
+/// Each renderer runs on its own thread. Since Libass writes to a track while rendering it, every
+/// worker renders from its own copy of the track, which is made once per call to
+/// `RendererPool::render_many` rather than once per frame.
+#[derive(Debug)]
+pub struct RendererPool {
+    /// One renderer per worker.
+    renderers: Vec<Renderer>,
+    /// The configuration every renderer was created with.
+    config: RendererConfig,
+}
+
+impl RendererPool {
+    /// Create a pool of `workers` renderers, each configured from `config`.
+    ///
+    /// Returns an error if a renderer could not be allocated, or if the font paths in `config` are
+    /// invalid.
+    pub fn new(library: &Library, config: &RendererConfig, workers: NonZeroUsize) -> Result<Self> {
+        let first = library.new_renderer()?;
+        first.apply_config(config)?;
+        // Duplicates don't build the Fontconfig cache again.
+        let mut renderers = vec![first];
+        for _ in 1..workers.get() {
+            let copy = renderers[0].duplicate()?;
+            renderers.push(copy);
+        }
+
+        Ok(Self {
+            renderers,
+            config: config.clone(),
+        })
+    }
+
+    /// Number of renderers in the pool.
+    pub const fn workers(&self) -> usize {
+        self.renderers.len()
+    }
+
+    /// The configuration the renderers were created with.
+    pub const fn config(&self) -> &RendererConfig {
+        &self.config
+    }
+
+    /// Render `track` at every timestamp, returning one frame per timestamp in the same order.
+    ///
+    /// The timestamps are split in to contiguous runs, one per worker, so neighbouring frames are
+    /// rendered by the same renderer and benefit from its caches. Frames are the size of the
+    /// configured frame size, and timestamps that cannot be represented by Libass produce an
+    /// empty frame.
+    ///
+    /// Returns `Error::Alloc` if the track could not be copied for a worker.
+    pub fn render_many(
+        &mut self,
+        track: &Track,
+        timestamps: &[Duration],
+    ) -> Result<Vec<RgbaFrame>> {
+        if timestamps.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let chunk_len = timestamps.len().div_ceil(self.renderers.len());
+        let width = self.config.frame_width.try_into().unwrap_or(0);
+        let height = self.config.frame_height.try_into().unwrap_or(0);
+        let mut chunks = timestamps.chunks(chunk_len);
+        let mut renderers = self.renderers.iter_mut();
+
+        // The calling thread works on the first chunk with the original track, the others get
+        // copies.
+        let (Some(first_chunk), Some(first_renderer)) = (chunks.next(), renderers.next()) else {
+            return Ok(Vec::new());
+        };
+        let jobs = chunks
+            .zip(renderers)
+            .map(|(chunk, renderer)| Ok((chunk, renderer, track.try_clone()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let frames = thread::scope(|scope| {
+            let handles = jobs
+                .into_iter()
+                .map(|(chunk, renderer, copy)| {
+                    scope.spawn(move || render_chunk(renderer, &copy, chunk, width, height))
+                })
+                .collect::<Vec<_>>();
+
+            let mut frames = render_chunk(first_renderer, track, first_chunk, width, height);
+            for handle in handles {
+                match handle.join() {
+                    Ok(chunk_frames) => frames.extend(chunk_frames),
+                    Err(panic) => std::panic::resume_unwind(panic),
+                }
+            }
+            frames
+        });
+
+        Ok(frames)
+    }
+}
+
+/// Render each timestamp of a chunk in to a new frame.
+fn render_chunk(
+    renderer: &Renderer,
+    track: &Track,
+    timestamps: &[Duration],
+    width: usize,
+    height: usize,
+) -> Vec<RgbaFrame> {
+    timestamps
+        .iter()
+        .map(|timestamp| {
+            let mut frame = RgbaFrame::new(width, height);
+            // Timestamps out of range are left empty.
+            let _ = renderer.render_rgba(track, timestamp, &mut frame);
+            frame
+        })
+        .collect()
+}
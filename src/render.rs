@@ -1,31 +1,127 @@
 //! Renderer module
 //!
 use std::{
-    ffi::{CString, OsString},
+    cell::RefCell,
+    ffi::{CStr, CString},
     marker::PhantomData,
-    mem::ManuallyDrop,
     path::PathBuf,
     ptr::NonNull,
 };
 use time::Duration;
 
 use libass_sys;
-use thiserror::Error;
 
-use crate::{library::FontProvider, Library, Track};
+use crate::{
+    error::{Error, Result},
+    frame::RgbaFrame,
+    library::FontProvider,
+    Library, Track,
+};
 
 /// Handle to a Libass rendering instance.
 ///
-/// Constructed from a `Library` handle. See `Library::new_renderer`.
-#[derive(Debug, PartialEq, Clone)]
+/// Constructed from a `Library` handle. See `Library::new_renderer`. The renderer keeps the library
+/// alive, so it has no lifetime.
+///
+/// # Threading
+///
+/// A renderer is `Send`, so it can be moved to a worker thread. It is not `Sync`, as rendering
+/// mutates the glyph and bitmap caches inside the renderer without any synchronization.
+///
+/// The renderer is not `Clone`, as both copies would free the same Libass renderer. Use
+/// `Renderer::duplicate` to create a new renderer with the same settings instead.
+#[derive(Debug, PartialEq)]
 #[allow(clippy::missing_docs_in_private_items)]
-pub struct Renderer<'lib> {
+pub struct Renderer {
     pub(crate) renderer: NonNull<libass_sys::ASS_Renderer>,
     pub(crate) data: PhantomData<libass_sys::ASS_Renderer>,
-    pub(crate) parent: &'lib Library,
+    pub(crate) parent: Library,
+    /// Every setting applied to the renderer, since Libass has no getters.
+    pub(crate) settings: RefCell<RendererSettings>,
 }
 
-impl<'lib> Renderer<'lib> {
+// Safety:
+// Libass renderers have no thread affinity. Their access to the shared library goes through the
+// library lock.
+unsafe impl Send for Renderer {}
+
+impl Renderer {
+    /// The library this renderer was created from.
+    pub const fn library(&self) -> &Library {
+        &self.parent
+    }
+
+    /// Create a new renderer from the same library with all of the settings of this one.
+    ///
+    /// Only the configuration is copied, caches are not shared.
+    ///
+    /// Returns `Error::Alloc` if the renderer could not be allocated.
+    pub fn duplicate(&self) -> Result<Renderer> {
+        let copy = self.parent.new_renderer()?;
+        // The Fontconfig cache was already built when the fonts of this renderer were set.
+        copy.replay_settings(&self.settings.borrow(), false)?;
+        Ok(copy)
+    }
+
+    /// The settings currently applied to this renderer.
+    ///
+    /// This reflects every setter called on the renderer, including through
+    /// `Renderer::apply_config`.
+    pub fn settings(&self) -> RendererSettings {
+        self.settings.borrow().clone()
+    }
+
+    /// Apply every setting in `settings` to the renderer.
+    ///
+    /// Combined with `Renderer::settings` this can be used to restore a saved configuration.
+    pub fn apply_settings(&self, settings: &RendererSettings) -> Result<()> {
+        self.replay_settings(settings, true)
+    }
+
+    /// Apply every setting in `settings`, only building the Fontconfig cache if `update_fonts`
+    /// and the font settings ask for it.
+    fn replay_settings(&self, settings: &RendererSettings, update_fonts: bool) -> Result<()> {
+        let RendererSettings {
+            frame_size: (frame_width, frame_height),
+            storage_size: (storage_width, storage_height),
+            shaper,
+            margins:
+                Margins {
+                    top,
+                    bottom,
+                    left,
+                    right,
+                },
+            use_margins,
+            pixel_aspect,
+            font_scale,
+            hinting,
+            line_spacing,
+            line_position,
+            ref fonts,
+            selective_style_override_flags,
+            cache_limits: (glyph_max, bitmap_cache),
+        } = *settings;
+
+        self.set_frame_size(frame_width, frame_height);
+        self.set_storage_size(storage_width, storage_height);
+        self.set_shaper(shaper);
+        self.set_margins(top, bottom, left, right);
+        self.use_margins(use_margins);
+        self.set_pixel_aspect(pixel_aspect);
+        self.set_font_scale(font_scale);
+        self.set_font_hinting(hinting);
+        self.set_line_spacing(line_spacing);
+        self.set_line_position(line_position);
+        self.set_selective_style_override_flags(selective_style_override_flags);
+        self.set_cache_limits(glyph_max, bitmap_cache);
+        // Font lookup is expensive to initialize, so only do it if it was done before.
+        if let Some(fonts) = fonts {
+            self.apply_fonts(fonts.clone(), update_fonts && fonts.update)?;
+        }
+        Ok(())
+    }
+
     /// Set the frame size in pixels, including margins.
     ///
     /// he renderer will never return images that are outside of the frame area. The value set with
@@ -44,6 +140,7 @@ impl<'lib> Renderer<'lib> {
                 height.try_into().unwrap_or(0),
             )
         }
+        self.settings.borrow_mut().frame_size = (width, height);
     }
 
     /// Set the source image size in pixels.
@@ -65,6 +162,7 @@ impl<'lib> Renderer<'lib> {
                 height.try_into().unwrap_or(0),
             )
         }
+        self.settings.borrow_mut().storage_size = (width, height);
     }
 
     /// Set shaping level. This is merely a hint, the renderer will use whatever is available if
@@ -73,6 +171,7 @@ impl<'lib> Renderer<'lib> {
         // Safety:
         // Just a setter
         unsafe { libass_sys::ass_set_shaper(self.renderer.as_ptr(), level as _) }
+        self.settings.borrow_mut().shaper = level;
     }
 
     /// Set frame margins.
@@ -109,12 +208,19 @@ impl<'lib> Renderer<'lib> {
                 right_margin,
             )
         }
+        self.settings.borrow_mut().margins = Margins {
+            top: top_margin,
+            bottom: bottom_margin,
+            left: left_margin,
+            right: right_margin,
+        };
     }
 
     /// Whether margins should be used for placing regular events.
     pub fn use_margins(&self, r#use: bool) {
         // Safety: setter
         unsafe { libass_sys::ass_set_use_margins(self.renderer.as_ptr(), r#use as _) }
+        self.settings.borrow_mut().use_margins = r#use;
     }
 
     /// Set pixel aspect ratio correction. This is the ratio of pixel width to pixel height.
@@ -136,12 +242,14 @@ impl<'lib> Renderer<'lib> {
     pub fn set_pixel_aspect(&self, aspect_ratio: f64) {
         // Safety: setter
         unsafe { libass_sys::ass_set_pixel_aspect(self.renderer.as_ptr(), aspect_ratio) }
+        self.settings.borrow_mut().pixel_aspect = aspect_ratio;
     }
 
     /// Set a fixed font scaling factor.
     pub fn set_font_scale(&self, scale: f64) {
         // Safety: setter
         unsafe { libass_sys::ass_set_font_scale(self.renderer.as_ptr(), scale) }
+        self.settings.borrow_mut().font_scale = scale;
     }
 
     /// Set font hinting method
@@ -157,6 +265,7 @@ impl<'lib> Renderer<'lib> {
     pub fn set_font_hinting(&self, method: FontHinting) {
         // Safety: setter
         unsafe { libass_sys::ass_set_hinting(self.renderer.as_ptr(), method as _) }
+        self.settings.borrow_mut().hinting = method;
     }
 
     /// Set line spacing. Will not be scaled with frame size.
@@ -164,6 +273,7 @@ impl<'lib> Renderer<'lib> {
     /// This spacing is in pixels.
     pub fn set_line_spacing(&self, spacing: f64) {
         unsafe { libass_sys::ass_set_line_spacing(self.renderer.as_ptr(), spacing) }
+        self.settings.borrow_mut().line_spacing = spacing;
     }
 
     /// Set vertical line position in percent.
@@ -175,10 +285,10 @@ impl<'lib> Renderer<'lib> {
     ///
     /// 100 = on top
     pub fn set_line_position(&self, position: f64) {
+        let position = position.clamp(0.0, 100.0);
         // Safety: setter
-        unsafe {
-            libass_sys::ass_set_line_position(self.renderer.as_ptr(), position.clamp(0.0, 100.0))
-        }
+        unsafe { libass_sys::ass_set_line_position(self.renderer.as_ptr(), position) }
+        self.settings.borrow_mut().line_position = position;
     }
 
     /// Set font lookup defaults.
@@ -186,61 +296,75 @@ impl<'lib> Renderer<'lib> {
     /// # Arguments
     ///
     /// * `default_font` - Path to default font to use. Must be supplied if all system
-    /// fontproviders are disabled or unavailable.
+    ///   fontproviders are disabled or unavailable.
     ///
     /// * `default_family` - Fallback font family
     ///
     /// * `font_provider` - Which font provider to use If the requested fontprovider does not exist
-    /// or fails to initialize, the behavior is the same as when `FontProvider::None` is passed.
+    ///   or fails to initialize, the behavior is the same as when `FontProvider::None` is passed.
     ///
     /// * `fontconfig_config` - Path to Fontconfig configuration file. Only relevant if fontconfig
-    /// is used. The encoding must match the one accepted by fontconfig.
+    ///   is used. The encoding must match the one accepted by fontconfig.
     ///
     /// * `update` - Whether Fontconfig cache should be built/updated now. Only relevant if
-    /// Fontconfig is used.
-    ///
-    /// Currently unsound to have the PathBuf's allocated with anything except for the system
-    /// allocater.
+    ///   Fontconfig is used.
     pub fn set_fonts(
         &self,
         default_font: Option<PathBuf>,
         default_family: Option<PathBuf>,
         font_provider: FontProvider,
-        mut fontconfig_config: Option<PathBuf>,
+        fontconfig_config: Option<PathBuf>,
         update: bool,
-    ) -> Result<(), PathErr> {
-        // Need to leak everything.
-        // Also it's unsound for anything to be allocated with anything except the system
-        // allocator so fun.
-        let font_out = match default_font {
-            Some(path) => path_to_ptr(path)?,
-            None => core::ptr::null(),
-        };
-        let family_out = match default_family {
-            Some(path) => path_to_ptr(path)?,
-            None => core::ptr::null(),
+    ) -> Result<()> {
+        let fonts = FontSettings {
+            default_font,
+            default_family,
+            font_provider,
+            fontconfig_config,
+            update,
         };
-        if font_provider == FontProvider::Fontconfig {
-            fontconfig_config = None;
-        }
+        self.apply_fonts(fonts, update)
+    }
 
-        let config_out = match fontconfig_config {
-            Some(path) => path_to_ptr(path)?,
-            None => core::ptr::null(),
+    /// Pass `fonts` to Libass and record them, building the Fontconfig cache only if `update`.
+    fn apply_fonts(&self, fonts: FontSettings, update: bool) -> Result<()> {
+        let font = fonts
+            .default_font
+            .clone()
+            .map(path_to_c_string)
+            .transpose()?;
+        let family = fonts
+            .default_family
+            .clone()
+            .map(path_to_c_string)
+            .transpose()?;
+        let config = match fonts.font_provider {
+            FontProvider::Fontconfig => None,
+            _ => fonts
+                .fontconfig_config
+                .clone()
+                .map(path_to_c_string)
+                .transpose()?,
         };
+        let as_ptr =
+            |path: &Option<CString>| path.as_deref().map_or(core::ptr::null(), CStr::as_ptr);
 
+        // Font lookup is initialized from the fonts stored in the library.
+        let _lock = self.parent.read();
         // Safety:
-        // ferrisclueless
+        // Libass copies the default font and family, and only reads the config while setting up
+        // the font provider, so the strings only need to outlive the call.
         unsafe {
             libass_sys::ass_set_fonts(
                 self.renderer.as_ptr(),
-                font_out,
-                family_out,
-                font_provider as _,
-                config_out,
+                as_ptr(&font),
+                as_ptr(&family),
+                fonts.font_provider as _,
+                as_ptr(&config),
                 update as _,
             )
         }
+        self.settings.borrow_mut().fonts = Some(fonts);
         Ok(())
     }
 
@@ -259,6 +383,7 @@ impl<'lib> Renderer<'lib> {
         unsafe {
             libass_sys::ass_set_selective_style_override_enabled(self.renderer.as_ptr(), flags.bits)
         }
+        self.settings.borrow_mut().selective_style_override_flags = flags;
     }
 
     /// Set style for selective style override.
@@ -288,22 +413,64 @@ impl<'lib> Renderer<'lib> {
                 bitmap_cache.try_into().unwrap_or(i32::MAX),
             )
         }
+        self.settings.borrow_mut().cache_limits = (glyph_max, bitmap_cache);
+    }
+
+    /// Configure the renderer with the required parameters from a `RendererConfig`.
+    pub fn apply_config(&self, config: &RendererConfig) -> Result<()> {
+        self.set_frame_size(
+            config.frame_width.try_into().unwrap_or(0),
+            config.frame_height.try_into().unwrap_or(0),
+        );
+        self.set_storage_size(
+            config.storage_width.try_into().unwrap_or(0),
+            config.storage_height.try_into().unwrap_or(0),
+        );
+        self.set_fonts(
+            config.default_font.clone(),
+            config.default_font_family.clone(),
+            config.default_font_provider,
+            config.fontconfig_path.clone(),
+            config.update_fontconfig,
+        )
+    }
+
+    /// Render the subtitles of `track` that are visible at `timestamp` on to `frame`.
+    ///
+    /// Images are composited on top of what is already in the frame. The frame should be the size
+    /// set with `Renderer::set_frame_size`, anything outside of it is clipped.
+    ///
+    /// Returns how the output differs from the previous frame this renderer drew, or
+    /// `Error::TimestampOutOfRange` if the timestamp cannot be represented by Libass.
+    pub fn render_rgba(
+        &self,
+        track: &Track,
+        timestamp: &Duration,
+        frame: &mut RgbaFrame,
+    ) -> Result<ChangeDetection> {
+        let mut change = Some(ChangeDetection::Identical);
+        let images = self.render_frame(track, timestamp, &mut change)?;
+
+        // Safety:
+        // The images were just returned and the renderer is not used again until they have been
+        // composited.
+        unsafe { frame.composite(images) };
+        Ok(change.unwrap_or(ChangeDetection::DifferentContent))
     }
 
-    /// Render a frame, producing a list of images.
-    /// TODO: wut is detect change
-    /// TODO: Linked list things
-    /// TODO: How does this need to be called?
-    /// It provides a linked list of images, but what is that linked list? Is it for the entire
-    /// track after the timestamp provided, or must you call the function again for the next
-    /// timestamp?
-    #[allow(dead_code, unused_variables)]
-    fn render_frame(
+    /// Render a frame, producing a linked list of images.
+    ///
+    /// The list is null if nothing is visible at `timestamp`. It is only valid until the renderer
+    /// is used again or dropped. Returns `Error::TimestampOutOfRange` if the timestamp cannot be
+    /// represented by Libass.
+    ///
+    /// TODO: Safe wrapper for the image list.
+    pub(crate) fn render_frame(
         &self,
         track: &Track,
         timestamp: &Duration,
         detect_change: &mut Option<ChangeDetection>,
-    ) -> Option<*const ()> {
+    ) -> Result<*const libass_sys::ASS_Image> {
         let mut out_value = 0;
         let out_ptr = match detect_change {
             Some(val) => {
@@ -313,22 +480,22 @@ impl<'lib> Renderer<'lib> {
             None => core::ptr::null_mut(),
         };
 
-        let image_out = NonNull::new(unsafe {
-            libass_sys::ass_render_frame(
-                self.renderer.as_ptr(),
-                track.track.as_ptr(),
-                // Not really the proper error handling but oh well.
-                timestamp.whole_milliseconds().try_into().ok()?,
-                out_ptr,
-            )
-        });
+        let now = timestamp
+            .whole_milliseconds()
+            .try_into()
+            .map_err(|_| Error::TimestampOutOfRange)?;
+
+        let _lock = self.parent.read();
+        let image_out = unsafe {
+            libass_sys::ass_render_frame(self.renderer.as_ptr(), track.track.as_ptr(), now, out_ptr)
+        };
 
         *detect_change = detect_change.map(|_| out_value.try_into().expect("Libass has changed and can return invalid values from the detect_change out ptr in ass_render_frame"));
-        image_out.map(|inner| inner.as_ptr().cast_const().cast())
+        Ok(image_out.cast_const())
     }
 }
 
-impl Drop for Renderer<'_> {
+impl Drop for Renderer {
     fn drop(&mut self) {
         // Safety:
         // :ferrisclueless:
@@ -388,32 +555,98 @@ bitflags::bitflags! {
     }
 }
 
-/// Errors for leaking paths to create pointers.
-#[derive(Error, Debug, PartialEq)]
-pub enum PathErr {
-    /// Returned if there is a zero value in the middle of the path.
-    #[error("{0}")]
-    NullInPath(#[from] std::ffi::NulError),
-    /// Returned if it's not UTF-8
-    /// Because I don't want to deal with non-UTF-8 for now.
-    #[error("Invalid UTF-8 found in OsString \"{0:?}\"")]
-    NotUtf8(OsString),
-}
-
-/// Leaks a path an returns a pointer to it.
+/// Convert a path to a C string for Libass.
 /// Must be UTF-8 (at least for now) because heck dealing with that.
-fn path_to_ptr(path: PathBuf) -> Result<*const i8, PathErr> {
+fn path_to_c_string(path: PathBuf) -> Result<CString> {
     match path.into_os_string().into_string() {
-        Ok(utf) => {
-            let to_leak = CString::new(utf)?;
-            let out = to_leak.as_ptr();
-            let _ = ManuallyDrop::new(to_leak);
-            Ok(out)
+        Ok(utf) => CString::new(utf).map_err(|err| Error::PathContainsNul {
+            position: err.nul_position(),
+            path: String::from_utf8_lossy(&err.into_vec()).into_owned(),
+        }),
+        Err(path) => Err(Error::PathNotUtf8 { path }),
+    }
+}
+
+/// The current settings of a `Renderer`, see `Renderer::settings`.
+///
+/// Libass has no getters, so the renderer records every setting as it is applied. Settings that
+/// have never been set hold Libass' defaults.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct RendererSettings {
+    /// Width and height. See `Renderer::set_frame_size`
+    pub frame_size: (u32, u32),
+    /// Width and height. See `Renderer::set_storage_size`
+    pub storage_size: (u32, u32),
+    /// See `Renderer::set_shaper`
+    pub shaper: ShapingLevel,
+    /// See `Renderer::set_margins`
+    pub margins: Margins,
+    /// See `Renderer::use_margins`
+    pub use_margins: bool,
+    /// See `Renderer::set_pixel_aspect`
+    pub pixel_aspect: f64,
+    /// See `Renderer::set_font_scale`
+    pub font_scale: f64,
+    /// See `Renderer::set_font_hinting`
+    pub hinting: FontHinting,
+    /// See `Renderer::set_line_spacing`
+    pub line_spacing: f64,
+    /// See `Renderer::set_line_position`
+    pub line_position: f64,
+    /// None if font lookup has not been configured. See `Renderer::set_fonts`
+    pub fonts: Option<FontSettings>,
+    /// See `Renderer::set_selective_style_override_flags`
+    pub selective_style_override_flags: OverrideBits,
+    /// Glyph and bitmap cache limits, where zero is Libass' default. See
+    /// `Renderer::set_cache_limits`
+    pub cache_limits: (u32, u32),
+}
+
+impl Default for RendererSettings {
+    /// The settings of a new renderer, as set up by `ass_renderer_init`.
+    fn default() -> Self {
+        Self {
+            frame_size: (0, 0),
+            storage_size: (0, 0),
+            shaper: ShapingLevel::Complex,
+            margins: Margins::default(),
+            use_margins: false,
+            // Zero derives the pixel aspect from the frame and storage sizes.
+            pixel_aspect: 0.0,
+            font_scale: 1.0,
+            hinting: FontHinting::default(),
+            line_spacing: 0.0,
+            line_position: 0.0,
+            fonts: None,
+            selective_style_override_flags: OverrideBits::SELECTIVE_FONT_SCALE,
+            // Zero is Libass' own limits.
+            cache_limits: (0, 0),
         }
-        Err(osstr) => Err(PathErr::NotUtf8(osstr)),
     }
 }
 
+/// Arguments last passed to `Renderer::set_fonts`.
+#[derive(Debug, Clone, PartialEq)]
+#[allow(missing_docs)]
+pub struct FontSettings {
+    pub default_font: Option<PathBuf>,
+    pub default_family: Option<PathBuf>,
+    pub font_provider: FontProvider,
+    pub fontconfig_config: Option<PathBuf>,
+    pub update: bool,
+}
+
+/// Frame margins in pixels. See `Renderer::set_margins`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub struct Margins {
+    pub top: i32,
+    pub bottom: i32,
+    pub left: i32,
+    pub right: i32,
+}
+
 /// The configuration parameters that are required to get a working `Renderer`.
 ///
 /// There are other parameters that can be configured as well, but they are optionally configured.
@@ -507,20 +740,18 @@ pub enum ChangeDetection {
     DifferentContent = 2,
 }
 
-/// Conversion from i32 to value failed.
-#[derive(Error, Debug)]
-#[error("Failed to convert from int to {0}. Invalid value of {1} found instead.")]
-pub struct FromIntError(String, i32);
-
 impl TryFrom<i32> for ChangeDetection {
-    type Error = FromIntError;
+    type Error = Error;
     fn try_from(value: i32) -> Result<Self, Self::Error> {
         use ChangeDetection::*;
         match value {
             0 => Ok(Identical),
             1 => Ok(DifferentPositions),
             2 => Ok(DifferentContent),
-            val => Err(FromIntError("ChangeDetection".to_string(), val)),
+            value => Err(Error::InvalidValue {
+                type_name: "ChangeDetection",
+                value,
+            }),
         }
     }
 }
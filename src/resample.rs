@@ -0,0 +1,359 @@
+//! Script resolution resampling
+//!
+use std::ffi::{CStr, CString};
+
+use crate::{
+    drawing::{DrawingPath, Point},
+    error::{Error, Result},
+    import::to_px,
+    script::ScriptDocument,
+    tags::{replace_spans, ClipShape, Item, Tag},
+    track::{replace_c_str, Track},
+};
+
+/// Scale factors from one resolution to another.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Scale {
+    /// Horizontal positions.
+    x: f64,
+    /// Vertical positions and sizes.
+    y: f64,
+    /// Horizontal scales, for changes of aspect ratio.
+    aspect: f64,
+}
+
+impl Scale {
+    /// The scale from one resolution to another, checking both are positive.
+    fn new(from: (i32, i32), to: (i32, i32)) -> Result<Self> {
+        for (width, height) in [from, to] {
+            if width <= 0 || height <= 0 {
+                return Err(Error::InvalidResolution { width, height });
+            }
+        }
+        let x = f64::from(to.0) / f64::from(from.0);
+        let y = f64::from(to.1) / f64::from(from.1);
+        Ok(Self {
+            x,
+            y,
+            aspect: x / y,
+        })
+    }
+
+    /// Scale a horizontal position.
+    fn x(self, value: f64) -> f64 {
+        round(value * self.x)
+    }
+
+    /// Scale a vertical position or a size.
+    fn y(self, value: f64) -> f64 {
+        round(value * self.y)
+    }
+
+    /// Scale a point.
+    fn point(self, (x, y): (f64, f64)) -> (f64, f64) {
+        (self.x(x), self.y(y))
+    }
+}
+
+impl Track {
+    /// Convert the script from one resolution to another, and set `PlayResX` and `PlayResY`.
+    ///
+    /// Positions, sizes, and drawings in a script are in script pixels, so typesetting made for
+    /// one resolution is misplaced on video of another. Like Aegisub's Resample Resolution,
+    /// styles, event margins, and override tags are rescaled: horizontal positions by the change
+    /// in width, and vertical positions and sizes such as font sizes, borders, shadows and blur by
+    /// the change in height. If the aspect ratio changes, horizontal scales are adjusted so text
+    /// is stretched with the rest of the script.
+    ///
+    /// Returns `Error::InvalidResolution` if either resolution isn't positive, or `Error::Alloc`
+    /// if new event text could not be allocated.
+    pub fn resample(&mut self, from: (i32, i32), to: (i32, i32)) -> Result<()> {
+        let scale = Scale::new(from, to)?;
+        // Safety:
+        // The track is valid and borrowed mutably, so nothing else can access it. Its arrays hold
+        // `n_styles` and `n_events` initialised elements, and strings are null or valid C strings
+        // allocated with the C allocator.
+        unsafe {
+            let raw = self.track.as_ptr();
+            (*raw).PlayResX = to.0;
+            (*raw).PlayResY = to.1;
+
+            if !(*raw).styles.is_null() {
+                let count = usize::try_from((*raw).n_styles).unwrap_or(0);
+                for style in std::slice::from_raw_parts_mut((*raw).styles, count) {
+                    style.FontSize = scale.y(style.FontSize);
+                    style.ScaleX *= scale.aspect;
+                    style.Spacing = scale.x(style.Spacing);
+                    style.Outline = scale.y(style.Outline);
+                    style.Shadow = scale.y(style.Shadow);
+                    style.Blur = scale.y(style.Blur);
+                    style.MarginL = scale_margin(style.MarginL, scale.x);
+                    style.MarginR = scale_margin(style.MarginR, scale.x);
+                    style.MarginV = scale_margin(style.MarginV, scale.y);
+                }
+            }
+
+            if !(*raw).events.is_null() {
+                let count = usize::try_from((*raw).n_events).unwrap_or(0);
+                for event in std::slice::from_raw_parts_mut((*raw).events, count) {
+                    event.MarginL = scale_margin(event.MarginL, scale.x);
+                    event.MarginR = scale_margin(event.MarginR, scale.x);
+                    event.MarginV = scale_margin(event.MarginV, scale.y);
+                    if event.Text.is_null() {
+                        continue;
+                    }
+                    // Tags are ASCII, so text that isn't UTF-8 is left alone rather than mangled.
+                    let Ok(text) = CStr::from_ptr(event.Text).to_str() else {
+                        continue;
+                    };
+                    let scaled = scale_text(text, scale).and_then(|text| CString::new(text).ok());
+                    if let Some(scaled) = scaled {
+                        replace_c_str(&mut event.Text, scaled.as_ptr())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ScriptDocument {
+    /// Convert the script from one resolution to another, the same as [`Track::resample`].
+    ///
+    /// Returns `Error::InvalidResolution` if either resolution isn't positive.
+    pub fn resample(&mut self, from: (i32, i32), to: (i32, i32)) -> Result<()> {
+        let scale = Scale::new(from, to)?;
+        self.info.set("PlayResX", to.0.to_string());
+        self.info.set("PlayResY", to.1.to_string());
+
+        for style in &mut self.styles {
+            style.font_size = scale.y(style.font_size);
+            style.scale_x = round(style.scale_x * scale.aspect);
+            style.spacing = scale.x(style.spacing);
+            style.outline = scale.y(style.outline);
+            style.shadow = scale.y(style.shadow);
+            style.margin_l = scale_margin(style.margin_l, scale.x);
+            style.margin_r = scale_margin(style.margin_r, scale.x);
+            style.margin_v = scale_margin(style.margin_v, scale.y);
+        }
+
+        for event in &mut self.events {
+            event.margin_l = scale_margin(event.margin_l, scale.x);
+            event.margin_r = scale_margin(event.margin_r, scale.x);
+            event.margin_v = scale_margin(event.margin_v, scale.y);
+            if let Some(text) = scale_text(&event.text, scale) {
+                event.text = text;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scale the tags and drawings of event text, or `None` if it has nothing to scale.
+///
+/// Only the changed tags and drawings are rewritten, so the rest of the text is kept as written.
+fn scale_text(text: &str, scale: Scale) -> Option<String> {
+    let mut edits: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+    let mut drawing = false;
+    for item in crate::tags::parse(text).items {
+        match item.value {
+            Item::Text(commands) if drawing => {
+                edits.push((item.span, scale_drawing(&commands, scale)));
+            }
+            Item::Tag(Tag::Drawing(drawing_scale)) => drawing = drawing_scale > 0,
+            Item::Tag(Tag::Transform { tags, .. }) => {
+                // Only the animated tags are replaced, keeping any that couldn't be parsed.
+                for tag in tags {
+                    if let Some(scaled) = scale_tag(&tag.value, scale) {
+                        edits.push((tag.span, scaled.to_string()));
+                    }
+                }
+            }
+            Item::Tag(tag) => {
+                if let Some(scaled) = scale_tag(&tag, scale) {
+                    edits.push((item.span, scaled.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    (!edits.is_empty()).then(|| replace_spans(text, edits))
+}
+
+/// Scale a tag with a position or size, or `None` if it has neither.
+fn scale_tag(tag: &Tag, scale: Scale) -> Option<Tag> {
+    let size = |value: &Option<f64>| value.map(|value| scale.y(value));
+    Some(match tag {
+        Tag::Position { x, y } => Tag::Position {
+            x: scale.x(*x),
+            y: scale.y(*y),
+        },
+        Tag::Move { from, to, time } => Tag::Move {
+            from: scale.point(*from),
+            to: scale.point(*to),
+            time: *time,
+        },
+        Tag::Origin { x, y } => Tag::Origin {
+            x: scale.x(*x),
+            y: scale.y(*y),
+        },
+        Tag::Clip { inverse, shape } => Tag::Clip {
+            inverse: *inverse,
+            shape: match shape {
+                ClipShape::Rect { x1, y1, x2, y2 } => ClipShape::Rect {
+                    x1: scale.x(*x1),
+                    y1: scale.y(*y1),
+                    x2: scale.x(*x2),
+                    y2: scale.y(*y2),
+                },
+                ClipShape::Drawing {
+                    scale: drawing_scale,
+                    commands,
+                } => ClipShape::Drawing {
+                    scale: *drawing_scale,
+                    commands: scale_drawing(commands, scale),
+                },
+            },
+        },
+        Tag::FontSize(value) => Tag::FontSize(size(value)),
+        // A step is relative to the current size, which is already scaled.
+        Tag::FontSizeStep(_) => return None,
+        Tag::FontScaleX(Some(value)) if scale.aspect != 1.0 => {
+            Tag::FontScaleX(Some(round(value * scale.aspect)))
+        }
+        Tag::FontSpacing(value) => Tag::FontSpacing(value.map(|value| scale.x(value))),
+        Tag::Border(value) => Tag::Border(size(value)),
+        Tag::BorderX(value) => Tag::BorderX(size(value)),
+        Tag::BorderY(value) => Tag::BorderY(size(value)),
+        Tag::Shadow(value) => Tag::Shadow(size(value)),
+        Tag::ShadowX(value) => Tag::ShadowX(size(value)),
+        Tag::ShadowY(value) => Tag::ShadowY(size(value)),
+        Tag::Blur(value) => Tag::Blur(size(value)),
+        Tag::DrawingBaselineOffset(value) => Tag::DrawingBaselineOffset(scale.y(*value)),
+        _ => return None,
+    })
+}
+
+/// Scale the coordinates of drawing commands.
+///
+/// The commands are written again from a [`DrawingPath`], so anything Libass would skip is
+/// dropped.
+fn scale_drawing(commands: &str, scale: Scale) -> String {
+    DrawingPath::parse(commands)
+        .map_points(|point| Point::new(scale.x(point.x), scale.y(point.y)))
+        .to_string()
+}
+
+/// Scale a margin, rounding to whole pixels.
+fn scale_margin(margin: i32, factor: f64) -> i32 {
+    to_px(f64::from(margin) * factor)
+}
+
+/// Round a scaled value to three decimal places, so it is written without float noise.
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::{Event, Style};
+
+    /// Scale event text from 640x480 to 1920x1080, returning it unchanged if nothing scales.
+    fn scaled(text: &str) -> String {
+        let scale = Scale::new((640, 480), (1920, 1080)).unwrap();
+        scale_text(text, scale).unwrap_or_else(|| text.to_owned())
+    }
+
+    #[test]
+    fn document() {
+        let mut doc = ScriptDocument::new();
+        doc.styles.push(Style {
+            font_size: 20.0,
+            scale_x: 100.0,
+            spacing: 2.0,
+            outline: 2.0,
+            shadow: 1.0,
+            margin_l: 10,
+            margin_r: 11,
+            margin_v: 10,
+            ..Style::default()
+        });
+        doc.events.push(Event {
+            margin_l: 5,
+            margin_v: 3,
+            text: r"{\pos(320,240)}Text".to_owned(),
+            ..Event::default()
+        });
+        doc.resample((640, 480), (1920, 1080)).unwrap();
+
+        assert_eq!(doc.info.play_res(), (Some(1920), Some(1080)));
+        let style = &doc.styles[0];
+        assert_eq!(
+            (style.font_size, style.spacing, style.outline, style.shadow),
+            (45.0, 6.0, 4.5, 2.25)
+        );
+        // 4:3 to 16:9 stretches text horizontally.
+        assert_eq!(style.scale_x, 133.333);
+        assert_eq!(
+            (style.margin_l, style.margin_r, style.margin_v),
+            (30, 33, 23)
+        );
+        let event = &doc.events[0];
+        assert_eq!((event.margin_l, event.margin_r, event.margin_v), (15, 0, 7));
+        assert_eq!(event.text, r"{\pos(960,540)}Text");
+    }
+
+    #[test]
+    fn invalid_resolution() {
+        let mut doc = ScriptDocument::new();
+        assert!(matches!(
+            doc.resample((0, 480), (1920, 1080)),
+            Err(Error::InvalidResolution {
+                width: 0,
+                height: 480
+            })
+        ));
+        assert!(doc.resample((640, 480), (1920, -1)).is_err());
+    }
+
+    #[test]
+    fn positions() {
+        assert_eq!(
+            scaled(r"{\move(0,0,100,100,10,20)\org(64,48)\clip(1,2,3,4)}a"),
+            r"{\move(0,0,300,225,10,20)\org(192,108)\clip(3,4.5,9,9)}a"
+        );
+        assert_eq!(
+            scaled(r"{\iclip(2,m 0 0 l 10 10)}a"),
+            r"{\iclip(2,m 0 0 l 30 22.5)}a"
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(
+            scaled(r"{\fs20\bord2\xbord1\ybord3\shad1\xshad2\yshad-2\blur0.5\fsp1\fscx100}a"),
+            r"{\fs45\bord4.5\xbord2.25\ybord6.75\shad2.25\xshad4.5\yshad-4.5\blur1.125\fsp3\fscx133.333}a"
+        );
+        // Resets and tags that don't depend on the resolution are kept as written.
+        assert_eq!(scaled(r"{\fs\b1\frz10}a"), r"{\fs\b1\frz10}a");
+        assert_eq!(
+            scaled(r"{\t(0,100,\fs40\bord1\xyz)}a"),
+            r"{\t(0,100,\fs90\bord2.25\xyz)}a"
+        );
+    }
+
+    #[test]
+    fn font_size_steps() {
+        // Steps are relative to a size that is already scaled.
+        assert_eq!(scaled(r"{\fs+2\fs-1\fs20}a"), r"{\fs+2\fs-1\fs45}a");
+    }
+
+    #[test]
+    fn drawings() {
+        assert_eq!(
+            scaled(r"{\p1\pbo10}m 0 0 l 64  48 b 1 2 3 4 5 6{\p0} 64 48"),
+            r"{\p1\pbo22.5}m 0 0 l 192 108 b 3 4.5 9 9 15 13.5{\p0} 64 48"
+        );
+    }
+}
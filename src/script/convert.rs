@@ -0,0 +1,219 @@
+//! Conversion from Libass tracks
+//!
+use std::{
+    borrow::Cow,
+    ffi::{c_char, CStr},
+};
+
+use libass_sys::{ass_track__bindgen_ty_1 as track_type, ASS_YCbCrMatrix as matrix};
+use time::Duration;
+
+use super::{parse::legacy_to_numpad, Event, EventKind, ScriptDocument, ScriptInfo, Style};
+use crate::{color::AssColor, track::Track};
+
+impl ScriptDocument {
+    /// Copy the script loaded in to a track.
+    ///
+    /// Only what Libass keeps is available: Script Info properties Libass doesn't use, comments,
+    /// and unknown sections are lost. Embedded fonts are extracted in to the library rather than
+    /// kept in the track, so they are not included either. SSA tracks are converted to ASS.
+    pub fn from_track(track: &Track) -> Self {
+        // Safety:
+        // The track is valid, and is only read. Its arrays hold `n_styles` and `n_events`
+        // initialised elements, and strings are null or valid C strings.
+        unsafe {
+            let raw = &*track.track.as_ptr();
+            let styles = slice(raw.styles, raw.n_styles);
+            let events = slice(raw.events, raw.n_events);
+
+            let styles_out = styles
+                .iter()
+                .enumerate()
+                .filter(|(sid, style)| !(*sid == 0 && is_builtin_default(style)))
+                .map(|(_, style)| convert_style(style))
+                .collect();
+
+            let events_out = events
+                .iter()
+                .map(|event| {
+                    let style = usize::try_from(event.Style)
+                        .ok()
+                        .and_then(|sid| styles.get(sid))
+                        .map_or(Cow::Borrowed("Default"), |style| string(style.Name));
+                    convert_event(event, &style)
+                })
+                .collect();
+
+            Self {
+                info: convert_info(raw),
+                styles: styles_out,
+                events: events_out,
+                ..Self::default()
+            }
+        }
+    }
+}
+
+impl From<&Track> for ScriptDocument {
+    fn from(track: &Track) -> Self {
+        Self::from_track(track)
+    }
+}
+
+/// Borrow a Libass array.
+///
+/// # Safety
+///
+/// `data` must be null or point to at least `len` initialised elements.
+unsafe fn slice<'a, T>(data: *const T, len: i32) -> &'a [T] {
+    match usize::try_from(len) {
+        Ok(len) if !data.is_null() => std::slice::from_raw_parts(data, len),
+        _ => &[],
+    }
+}
+
+/// Copy a Libass string, treating null as empty.
+///
+/// # Safety
+///
+/// `ptr` must be null or a valid C string.
+unsafe fn string<'a>(ptr: *const c_char) -> Cow<'a, str> {
+    if ptr.is_null() {
+        Cow::Borrowed("")
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy()
+    }
+}
+
+/// Whether a style is the fallback style Libass adds as the first style of every new track.
+///
+/// # Safety
+///
+/// The style's strings must be null or valid C strings.
+unsafe fn is_builtin_default(style: &libass_sys::ASS_Style) -> bool {
+    string(style.Name) == "Default" && string(style.FontName) == "Arial" && style.FontSize == 18.0
+}
+
+/// Rebuild Script Info from the track header.
+///
+/// # Safety
+///
+/// The track's strings must be null or valid C strings.
+unsafe fn convert_info(raw: &libass_sys::ASS_Track) -> ScriptInfo {
+    let mut info = ScriptInfo::default();
+    if raw.track_type != track_type::TRACK_TYPE_UNKNOWN {
+        info.set("ScriptType", "v4.00+");
+    }
+    let positive = |value: i32| (value > 0).then(|| value.to_string());
+    let yes_no = |value: i32| if value != 0 { "yes" } else { "no" };
+
+    let properties = [
+        ("PlayResX", positive(raw.PlayResX)),
+        ("PlayResY", positive(raw.PlayResY)),
+        ("LayoutResX", positive(raw.LayoutResX)),
+        ("LayoutResY", positive(raw.LayoutResY)),
+        (
+            "Timer",
+            (raw.Timer != 0.0).then(|| format!("{:.4}", raw.Timer)),
+        ),
+        ("WrapStyle", Some(raw.WrapStyle.to_string())),
+        (
+            "ScaledBorderAndShadow",
+            Some(yes_no(raw.ScaledBorderAndShadow).to_owned()),
+        ),
+        ("Kerning", (raw.Kerning != 0).then(|| "yes".to_owned())),
+        (
+            "Language",
+            (!raw.Language.is_null()).then(|| string(raw.Language).into_owned()),
+        ),
+        (
+            "YCbCr Matrix",
+            matrix_name(raw.YCbCrMatrix).map(str::to_owned),
+        ),
+    ];
+    for (key, value) in properties {
+        if let Some(value) = value {
+            info.set(key, value);
+        }
+    }
+    info
+}
+
+/// The Script Info value of a YCbCr matrix, if it has one.
+const fn matrix_name(value: matrix::Type) -> Option<&'static str> {
+    Some(match value {
+        matrix::YCBCR_NONE => "None",
+        matrix::YCBCR_BT601_TV => "TV.601",
+        matrix::YCBCR_BT601_PC => "PC.601",
+        matrix::YCBCR_BT709_TV => "TV.709",
+        matrix::YCBCR_BT709_PC => "PC.709",
+        matrix::YCBCR_SMPTE240M_TV => "TV.240M",
+        matrix::YCBCR_SMPTE240M_PC => "PC.240M",
+        matrix::YCBCR_FCC_TV => "TV.FCC",
+        matrix::YCBCR_FCC_PC => "PC.FCC",
+        _ => return None,
+    })
+}
+
+/// Convert a Libass style, undoing the normalisation Libass applies while parsing.
+///
+/// # Safety
+///
+/// The style's strings must be null or valid C strings.
+unsafe fn convert_style(style: &libass_sys::ASS_Style) -> Style {
+    Style {
+        name: string(style.Name).into_owned(),
+        font_name: string(style.FontName).into_owned(),
+        font_size: style.FontSize,
+        primary_color: AssColor::from_libass(style.PrimaryColour),
+        secondary_color: AssColor::from_libass(style.SecondaryColour),
+        outline_color: AssColor::from_libass(style.OutlineColour),
+        back_color: AssColor::from_libass(style.BackColour),
+        bold: style.Bold,
+        italic: style.Italic != 0,
+        underline: style.Underline != 0,
+        strike_out: style.StrikeOut != 0,
+        scale_x: percent(style.ScaleX),
+        scale_y: percent(style.ScaleY),
+        spacing: style.Spacing,
+        angle: style.Angle,
+        border_style: style.BorderStyle,
+        outline: style.Outline,
+        shadow: style.Shadow,
+        // Libass stores alignment with the SSA numbering.
+        alignment: legacy_to_numpad(style.Alignment),
+        margin_l: style.MarginL,
+        margin_r: style.MarginR,
+        margin_v: style.MarginV,
+        encoding: style.Encoding,
+    }
+}
+
+/// Convert a scale Libass stores as a fraction back to a percentage.
+///
+/// The result is rounded to 6 decimal places, so values written with a few decimal places in the
+/// script come back exactly rather than with floating point error from the division.
+fn percent(fraction: f64) -> f64 {
+    (fraction * 100.0 * 1e6).round() / 1e6
+}
+
+/// Convert a Libass event, which are always dialogue.
+///
+/// # Safety
+///
+/// The event's strings must be null or valid C strings.
+unsafe fn convert_event(event: &libass_sys::ASS_Event, style: &str) -> Event {
+    Event {
+        kind: EventKind::Dialogue,
+        layer: event.Layer,
+        start: Duration::milliseconds(event.Start),
+        end: Duration::milliseconds(event.Start.saturating_add(event.Duration)),
+        style: style.to_owned(),
+        name: string(event.Name).into_owned(),
+        margin_l: event.MarginL,
+        margin_r: event.MarginR,
+        margin_v: event.MarginV,
+        effect: string(event.Effect).into_owned(),
+        text: string(event.Text).into_owned(),
+    }
+}
@@ -0,0 +1,333 @@
+//! Pure Rust ASS/SSA script documents
+//!
+//! A [`ScriptDocument`] is an owned, editable copy of a script that can be inspected and
+//! transformed without going through Libass. Parsing is as lenient as Libass is, malformed lines
+//! are skipped and unparsable numbers become 0, so any script Libass accepts can be loaded.
+//!
+//! SSA (`v4.00`) scripts are converted to ASS (`v4.00+`) while parsing, so the document always
+//! describes an ASS script.
+use time::Duration;
+
+use crate::{color::AssColor, error::Result, library::Library, track::Track};
+
+mod convert;
+mod parse;
+mod write;
+
+pub(crate) use parse::{legacy_to_numpad, parse_int};
+pub use write::WriteOptions;
+
+/// An owned ASS script.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScriptDocument {
+    /// The `[Script Info]` section.
+    pub info: ScriptInfo,
+    /// Styles from the `[V4+ Styles]` or `[V4 Styles]` section.
+    pub styles: Vec<Style>,
+    /// Dialogue and comment lines from the `[Events]` section.
+    pub events: Vec<Event>,
+    /// Fonts from the `[Fonts]` section.
+    pub fonts: Vec<Attachment>,
+    /// Images from the `[Graphics]` section.
+    pub graphics: Vec<Attachment>,
+    /// Any other section, such as `[Aegisub Project Garbage]`, in the order they appeared.
+    pub extra_sections: Vec<RawSection>,
+}
+
+impl ScriptDocument {
+    /// Create an empty ASS script.
+    pub fn new() -> Self {
+        let mut info = ScriptInfo::default();
+        info.set("ScriptType", "v4.00+");
+        Self {
+            info,
+            ..Self::default()
+        }
+    }
+
+    /// Parse the text of an `.ass` or `.ssa` file.
+    pub fn parse(text: &str) -> Self {
+        parse::parse(text)
+    }
+
+    /// Find a style by name.
+    ///
+    /// Like Libass, the last style with the name wins, and a leading `*` is ignored.
+    pub fn style(&self, name: &str) -> Option<&Style> {
+        let name = name.strip_prefix('*').unwrap_or(name);
+        self.styles.iter().rev().find(|style| style.name == name)
+    }
+
+    /// Load the document in to a new track.
+    ///
+    /// The script is fed to [`Track::process_slice`], then the track is finished off the same way
+    /// Libass finishes a file it has read, so rendering matches loading the script from disk.
+    pub fn to_track(&self, library: &Library) -> Result<Track> {
+        let track = library.new_track()?;
+        self.load_into(&track)?;
+        Ok(track)
+    }
+
+    /// Append the document to an existing track.
+    ///
+    /// Script Info and styles replace those already in the track, while events are added to the
+    /// existing ones.
+    pub fn load_into(&self, track: &Track) -> Result<()> {
+        track.process_slice(&self.to_string())?;
+        // Libass numbers the events of files it reads, so collisions are resolved in file order.
+        // Events processed as data are left unnumbered.
+        // Safety:
+        // The track is valid, and events are only accessed through the track which isn't `Sync`.
+        unsafe {
+            let raw = track.track.as_ptr();
+            let count = usize::try_from((*raw).n_events).unwrap_or(0);
+            if !(*raw).events.is_null() {
+                let events = std::slice::from_raw_parts_mut((*raw).events, count);
+                for (order, event) in (0..).zip(events) {
+                    event.ReadOrder = order;
+                }
+            }
+        }
+        track.force_process_styles();
+        Ok(())
+    }
+}
+
+/// The `[Script Info]` section, in file order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScriptInfo {
+    /// Properties and comments.
+    pub lines: Vec<InfoLine>,
+}
+
+/// A single line of the `[Script Info]` section.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InfoLine {
+    /// A `Key: Value` property.
+    Property {
+        /// The name before the colon.
+        key: String,
+        /// The value after the colon, with leading whitespace removed.
+        value: String,
+    },
+    /// A `;` comment, without the semicolon.
+    Comment(String),
+}
+
+impl ScriptInfo {
+    /// The value of a property, matched case insensitively like Libass.
+    ///
+    /// If a property is repeated the last value wins.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().rev().find_map(|line| match line {
+            InfoLine::Property { key: k, value } if k.eq_ignore_ascii_case(key) => {
+                Some(value.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    /// Set a property, replacing the existing value or adding it to the end.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        let value = value.into();
+        let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+            InfoLine::Property { key: k, value } if k.eq_ignore_ascii_case(key) => Some(value),
+            _ => None,
+        });
+        match existing {
+            Some(existing) => *existing = value,
+            None => self.lines.push(InfoLine::Property {
+                key: key.to_owned(),
+                value,
+            }),
+        }
+    }
+
+    /// Remove every occurrence of a property, returning the last value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let mut removed = None;
+        self.lines.retain(|line| match line {
+            InfoLine::Property { key: k, value } if k.eq_ignore_ascii_case(key) => {
+                removed = Some(value.clone());
+                false
+            }
+            _ => true,
+        });
+        removed
+    }
+
+    /// The `PlayResX` and `PlayResY` properties, when set.
+    pub fn play_res(&self) -> (Option<i32>, Option<i32>) {
+        let get = |key| self.get(key).map(parse::parse_int);
+        (get("PlayResX"), get("PlayResY"))
+    }
+}
+
+/// A style from the `[V4+ Styles]` section.
+///
+/// Field names follow the V4+ `Format` line. Sizes, spacing and margins are in script pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    /// Name used by events to reference the style.
+    pub name: String,
+    /// Font family name.
+    pub font_name: String,
+    /// Font size.
+    pub font_size: f64,
+    /// Fill color.
+    pub primary_color: AssColor,
+    /// Color karaoke fills from.
+    pub secondary_color: AssColor,
+    /// Border color. SSA scripts use `BackColour`, like Libass.
+    pub outline_color: AssColor,
+    /// Shadow color.
+    pub back_color: AssColor,
+    /// `-1` or `1` for bold, `0` for regular, and anything else is a font weight.
+    pub bold: i32,
+    #[allow(missing_docs)]
+    pub italic: bool,
+    #[allow(missing_docs)]
+    pub underline: bool,
+    #[allow(missing_docs)]
+    pub strike_out: bool,
+    /// Horizontal scale in percent.
+    pub scale_x: f64,
+    /// Vertical scale in percent.
+    pub scale_y: f64,
+    /// Extra space between letters.
+    pub spacing: f64,
+    /// Rotation around the z axis in degrees.
+    pub angle: f64,
+    /// `1` for an outline and shadow, `3` for an opaque box.
+    pub border_style: i32,
+    /// Border width.
+    pub outline: f64,
+    /// Shadow depth.
+    pub shadow: f64,
+    /// Numpad style alignment, 1 to 9.
+    pub alignment: i32,
+    #[allow(missing_docs)]
+    pub margin_l: i32,
+    #[allow(missing_docs)]
+    pub margin_r: i32,
+    #[allow(missing_docs)]
+    pub margin_v: i32,
+    /// Font charset, as a Windows `CHARSET` value.
+    pub encoding: i32,
+}
+
+impl Style {
+    /// The font weight, where 400 is regular and 700 is bold.
+    pub const fn weight(&self) -> i32 {
+        match self.bold {
+            0 => 400,
+            -1 | 1 => 700,
+            weight => weight,
+        }
+    }
+}
+
+impl Default for Style {
+    /// The same default style Aegisub creates.
+    fn default() -> Self {
+        Self {
+            name: "Default".to_owned(),
+            font_name: "Arial".to_owned(),
+            font_size: 48.0,
+            primary_color: AssColor::WHITE,
+            secondary_color: AssColor::rgb(255, 0, 0),
+            outline_color: AssColor::BLACK,
+            back_color: AssColor::BLACK,
+            bold: 0,
+            italic: false,
+            underline: false,
+            strike_out: false,
+            scale_x: 100.0,
+            scale_y: 100.0,
+            spacing: 0.0,
+            angle: 0.0,
+            border_style: 1,
+            outline: 2.0,
+            shadow: 2.0,
+            alignment: 2,
+            margin_l: 10,
+            margin_r: 10,
+            margin_v: 10,
+            encoding: 1,
+        }
+    }
+}
+
+/// What kind of line an event is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    /// A line that is displayed.
+    Dialogue,
+    /// A line that is kept in the script but not displayed.
+    Comment,
+    /// Any other line type, such as `Picture` or `Command`, which renderers ignore.
+    Other(String),
+}
+
+/// A line from the `[Events]` section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    #[allow(missing_docs)]
+    pub kind: EventKind,
+    /// Events on higher layers are drawn on top. Always 0 for SSA scripts.
+    pub layer: i32,
+    #[allow(missing_docs)]
+    pub start: Duration,
+    #[allow(missing_docs)]
+    pub end: Duration,
+    /// Name of the style, see [`ScriptDocument::style`].
+    pub style: String,
+    /// Actor name.
+    pub name: String,
+    /// Overrides the style margin when not 0.
+    pub margin_l: i32,
+    /// Overrides the style margin when not 0.
+    pub margin_r: i32,
+    /// Overrides the style margin when not 0.
+    pub margin_v: i32,
+    /// Legacy scrolling effects such as `Banner;...`.
+    pub effect: String,
+    /// Text, including override blocks.
+    pub text: String,
+}
+
+impl Default for Event {
+    fn default() -> Self {
+        Self {
+            kind: EventKind::Dialogue,
+            layer: 0,
+            start: Duration::ZERO,
+            end: Duration::ZERO,
+            style: "Default".to_owned(),
+            name: String::new(),
+            margin_l: 0,
+            margin_r: 0,
+            margin_v: 0,
+            effect: String::new(),
+            text: String::new(),
+        }
+    }
+}
+
+/// A file embedded in the `[Fonts]` or `[Graphics]` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// File name, as given on the `fontname:` or `filename:` line.
+    pub name: String,
+    /// Decoded file contents.
+    pub data: Vec<u8>,
+}
+
+/// A section that isn't otherwise understood, kept as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawSection {
+    /// Name without the brackets.
+    pub name: String,
+    /// Lines after the header, excluding blank lines.
+    pub lines: Vec<String>,
+}
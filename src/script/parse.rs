@@ -0,0 +1,509 @@
+//! ASS/SSA text parser
+//!
+
+use super::{Attachment, Event, EventKind, InfoLine, RawSection, ScriptDocument, Style};
+use crate::{color::AssColor, timing::AssTime};
+
+/// Default `Format` of the `[V4+ Styles]` section.
+const ASS_STYLE_FORMAT: &str = "Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, \
+    OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, \
+    Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding";
+
+/// Default `Format` of the `[V4 Styles]` section.
+const SSA_STYLE_FORMAT: &str = "Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, \
+    TertiaryColour, BackColour, Bold, Italic, BorderStyle, Outline, Shadow, Alignment, MarginL, \
+    MarginR, MarginV, AlphaLevel, Encoding";
+
+/// Default `Format` of the `[Events]` section.
+const EVENT_FORMAT: &str =
+    "Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
+/// The section lines are currently being read from.
+#[derive(Debug)]
+enum Section {
+    /// Before the first header.
+    None,
+    /// `[Script Info]`
+    Info,
+    /// `[V4+ Styles]` or `[V4 Styles]`
+    Styles,
+    /// `[Events]`
+    Events,
+    /// `[Fonts]`
+    Fonts,
+    /// `[Graphics]`
+    Graphics,
+    /// Anything else, stored at this index of `extra_sections`.
+    Raw(usize),
+}
+
+/// Parser state.
+#[derive(Debug)]
+struct Parser {
+    /// Document being built.
+    doc: ScriptDocument,
+    /// Current section.
+    section: Section,
+    /// Whether the script is SSA rather than ASS, which changes how alignment is numbered.
+    ssa: bool,
+    /// Lower case field names of the current style `Format` line.
+    style_format: Vec<String>,
+    /// Lower case field names of the current event `Format` line.
+    event_format: Vec<String>,
+    /// Encoded lines of the attachment being read, and which list it goes in to.
+    attachment: Option<(Attachment, String, bool)>,
+}
+
+/// Parse the text of a script.
+pub(super) fn parse(text: &str) -> ScriptDocument {
+    let mut parser = Parser {
+        doc: ScriptDocument::default(),
+        section: Section::None,
+        ssa: false,
+        style_format: split_format(ASS_STYLE_FORMAT),
+        event_format: split_format(EVENT_FORMAT),
+        attachment: None,
+    };
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    for line in text.lines() {
+        parser.line(line.trim_end_matches('\r'));
+    }
+    parser.finish_attachment();
+    parser.doc
+}
+
+impl Parser {
+    /// Process a single line.
+    fn line(&mut self, line: &str) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return;
+        }
+        if let Some(name) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            self.header(name);
+            return;
+        }
+        match self.section {
+            Section::None => {}
+            Section::Info => self.info_line(trimmed),
+            Section::Styles => self.style_line(trimmed),
+            Section::Events => self.event_line(line.trim_start()),
+            Section::Fonts => self.attachment_line(trimmed, "fontname:", true),
+            Section::Graphics => self.attachment_line(trimmed, "filename:", false),
+            Section::Raw(index) => self.doc.extra_sections[index].lines.push(line.to_owned()),
+        }
+    }
+
+    /// Start a new section.
+    fn header(&mut self, name: &str) {
+        self.finish_attachment();
+        self.section = match name.to_ascii_lowercase().as_str() {
+            "script info" => Section::Info,
+            "v4+ styles" => {
+                self.ssa = false;
+                self.style_format = split_format(ASS_STYLE_FORMAT);
+                Section::Styles
+            }
+            "v4 styles" => {
+                self.ssa = true;
+                self.style_format = split_format(SSA_STYLE_FORMAT);
+                Section::Styles
+            }
+            "events" => Section::Events,
+            "fonts" => Section::Fonts,
+            "graphics" => Section::Graphics,
+            _ => {
+                self.doc.extra_sections.push(RawSection {
+                    name: name.to_owned(),
+                    lines: Vec::new(),
+                });
+                Section::Raw(self.doc.extra_sections.len() - 1)
+            }
+        };
+    }
+
+    /// Process a line of `[Script Info]`.
+    fn info_line(&mut self, line: &str) {
+        if let Some(comment) = line.strip_prefix(';') {
+            self.doc
+                .info
+                .lines
+                .push(InfoLine::Comment(comment.to_owned()));
+            return;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            return;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if key.eq_ignore_ascii_case("ScriptType") {
+            // Converted to ASS, so the script type is too.
+            self.ssa = !value.eq_ignore_ascii_case("v4.00+");
+            self.doc.info.lines.push(InfoLine::Property {
+                key: key.to_owned(),
+                value: "v4.00+".to_owned(),
+            });
+            return;
+        }
+        self.doc.info.lines.push(InfoLine::Property {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        });
+    }
+
+    /// Process a line of `[V4+ Styles]` or `[V4 Styles]`.
+    fn style_line(&mut self, line: &str) {
+        if let Some(format) = strip_key(line, "Format") {
+            self.style_format = split_format(format);
+        } else if let Some(values) = strip_key(line, "Style") {
+            let style = self.style(values);
+            self.doc.styles.push(style);
+        }
+    }
+
+    /// Parse the values of a `Style:` line according to the current format.
+    fn style(&self, values: &str) -> Style {
+        let mut style = Style::default();
+        let count = self.style_format.len().max(1);
+        for (field, value) in self.style_format.iter().zip(values.splitn(count, ',')) {
+            let value = value.trim();
+            match field.as_str() {
+                "name" => style.name = value.trim_start_matches('*').to_owned(),
+                "fontname" => style.font_name = value.to_owned(),
+                "fontsize" => style.font_size = parse_float(value),
+                "primarycolour" => style.primary_color = AssColor::parse_style(value),
+                "secondarycolour" => style.secondary_color = AssColor::parse_style(value),
+                "outlinecolour" | "tertiarycolour" => {
+                    style.outline_color = AssColor::parse_style(value);
+                }
+                "backcolour" => style.back_color = AssColor::parse_style(value),
+                "bold" => style.bold = parse_int(value),
+                "italic" => style.italic = parse_int(value) != 0,
+                "underline" => style.underline = parse_int(value) != 0,
+                "strikeout" => style.strike_out = parse_int(value) != 0,
+                "scalex" => style.scale_x = parse_float(value),
+                "scaley" => style.scale_y = parse_float(value),
+                "spacing" => style.spacing = parse_float(value),
+                "angle" => style.angle = parse_float(value),
+                "borderstyle" => style.border_style = parse_int(value),
+                "outline" => style.outline = parse_float(value),
+                "shadow" => style.shadow = parse_float(value),
+                "alignment" => {
+                    let alignment = parse_int(value);
+                    style.alignment = if self.ssa {
+                        legacy_to_numpad(alignment)
+                    } else {
+                        alignment
+                    };
+                }
+                "marginl" => style.margin_l = parse_int(value),
+                "marginr" => style.margin_r = parse_int(value),
+                "marginv" => style.margin_v = parse_int(value),
+                "encoding" => style.encoding = parse_int(value),
+                // AlphaLevel is unused by every renderer.
+                _ => {}
+            }
+        }
+        if self.ssa {
+            // SSA draws outlines with the back color, and Libass ignores TertiaryColour.
+            style.outline_color = style.back_color;
+        }
+        style
+    }
+
+    /// Process a line of `[Events]`.
+    fn event_line(&mut self, line: &str) {
+        let Some((kind, values)) = line.split_once(':') else {
+            return;
+        };
+        let kind = match kind.trim() {
+            "Format" => {
+                self.event_format = split_format(values);
+                return;
+            }
+            "Dialogue" => EventKind::Dialogue,
+            "Comment" => EventKind::Comment,
+            other if other.starts_with(';') => return,
+            other => EventKind::Other(other.to_owned()),
+        };
+        let event = self.event(kind, values.trim_start());
+        self.doc.events.push(event);
+    }
+
+    /// Parse the values of an event line according to the current format.
+    ///
+    /// The last field gets the rest of the line, commas included.
+    fn event(&self, kind: EventKind, values: &str) -> Event {
+        let mut event = Event {
+            kind,
+            ..Event::default()
+        };
+        let count = self.event_format.len().max(1);
+        for (field, value) in self.event_format.iter().zip(values.splitn(count, ',')) {
+            if field == "text" {
+                event.text = value.to_owned();
+                continue;
+            }
+            let value = value.trim();
+            match field.as_str() {
+                "layer" => event.layer = parse_int(value),
+                "start" => event.start = AssTime::parse_ass(value).to_duration(),
+                "end" => event.end = AssTime::parse_ass(value).to_duration(),
+                "style" => event.style = value.to_owned(),
+                "name" | "actor" => event.name = value.to_owned(),
+                "marginl" => event.margin_l = parse_int(value),
+                "marginr" => event.margin_r = parse_int(value),
+                "marginv" | "margint" => event.margin_v = parse_int(value),
+                "effect" => event.effect = value.to_owned(),
+                // Marked is unused, and MarginB is not supported by Libass.
+                _ => {}
+            }
+        }
+        event
+    }
+
+    /// Process a line of `[Fonts]` or `[Graphics]`.
+    fn attachment_line(&mut self, line: &str, start: &str, font: bool) {
+        if let Some(name) = line.strip_prefix(start) {
+            self.finish_attachment();
+            let attachment = Attachment {
+                name: name.trim().to_owned(),
+                data: Vec::new(),
+            };
+            self.attachment = Some((attachment, String::new(), font));
+        } else if let Some((_, encoded, _)) = &mut self.attachment {
+            encoded.push_str(line);
+        }
+    }
+
+    /// Decode the attachment being read and add it to the document.
+    fn finish_attachment(&mut self) {
+        if let Some((mut attachment, encoded, font)) = self.attachment.take() {
+            attachment.data = uudecode(&encoded);
+            if font {
+                self.doc.fonts.push(attachment);
+            } else {
+                self.doc.graphics.push(attachment);
+            }
+        }
+    }
+}
+
+/// Strip `key:` from the start of a line, case insensitively.
+fn strip_key<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let (name, rest) = line.split_once(':')?;
+    name.trim().eq_ignore_ascii_case(key).then_some(rest)
+}
+
+/// Split a `Format` line in to lower case field names.
+fn split_format(format: &str) -> Vec<String> {
+    format
+        .split(',')
+        .map(|field| field.trim().to_ascii_lowercase())
+        .collect()
+}
+
+/// Convert SSA alignment, where 1-3 is bottom, 5-7 top, and 9-11 middle, to numpad alignment.
+pub(crate) const fn legacy_to_numpad(alignment: i32) -> i32 {
+    let horizontal = match alignment & 3 {
+        0 => 2,
+        h => h,
+    };
+    if alignment & 4 != 0 {
+        horizontal + 6
+    } else if alignment & 8 != 0 {
+        horizontal + 3
+    } else {
+        horizontal
+    }
+}
+
+/// Parse an integer like `atoi`, from leading digits and saturating on overflow.
+pub(crate) fn parse_int(value: &str) -> i32 {
+    let value = value.trim_start();
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let magnitude = digits
+        .chars()
+        .map_while(|c| c.to_digit(10))
+        .fold(0i64, |acc, digit| {
+            (acc * 10 + i64::from(digit)).min(i64::from(u32::MAX))
+        });
+    let signed = if negative { -magnitude } else { magnitude };
+    i32::try_from(signed).unwrap_or(if negative { i32::MIN } else { i32::MAX })
+}
+
+/// Parse a float like `strtod`, from the longest valid prefix.
+pub(super) fn parse_float(value: &str) -> f64 {
+    let value = value.trim_start();
+    let bytes = value.as_bytes();
+    let digits = |mut i: usize| {
+        while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+            i += 1;
+        }
+        i
+    };
+    let mut end = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+    end = digits(end);
+    if bytes.get(end) == Some(&b'.') {
+        end = digits(end + 1);
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+        let exponent_end = digits(end + 1 + sign);
+        if exponent_end > end + 1 + sign {
+            end = exponent_end;
+        }
+    }
+    value[..end].parse().unwrap_or(0.0)
+}
+
+/// Decode the uuencoding variant used for embedded files.
+///
+/// Every 4 characters encode 3 bytes, 6 bits at a time offset by 33. A trailing group of 2 or 3
+/// characters encodes 1 or 2 bytes.
+fn uudecode(encoded: &str) -> Vec<u8> {
+    let bytes = encoded.as_bytes();
+    let mut data = Vec::with_capacity(bytes.len() / 4 * 3 + 2);
+    for group in bytes.chunks(4) {
+        let value = group.iter().enumerate().fold(0u32, |acc, (i, c)| {
+            acc | (u32::from(c.wrapping_sub(33)) & 0x3f) << (18 - 6 * i)
+        });
+        let [_, a, b, c] = value.to_be_bytes();
+        let decoded = [a, b, c];
+        data.extend_from_slice(&decoded[..group.len().saturating_sub(1)]);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn ass_script() {
+        let doc = parse(
+            "\u{feff}[Script Info]\r\n\
+             ; A comment\r\n\
+             Title: Test\r\n\
+             ScriptType: v4.00+\r\n\
+             PlayResX: 1280\r\n\
+             \r\n\
+             [V4+ Styles]\r\n\
+             Format: Name, Fontname, Fontsize, PrimaryColour, Bold, Italic, Alignment\r\n\
+             Style: *Sign,Open Sans,36.5,&H8000FFFF,-1,1,7\r\n\
+             \r\n\
+             [Events]\r\n\
+             Format: Layer, Start, End, Style, Text\r\n\
+             Dialogue: 2,0:00:01.50,0:00:03.00,Sign,{\\an8}Hello, world\r\n\
+             Comment: 0,0:00:04.00,0:00:05.00,Sign,Note\r\n",
+        );
+
+        assert_eq!(
+            doc.info.lines[0],
+            InfoLine::Comment(" A comment".to_owned())
+        );
+        assert_eq!(doc.info.get("title"), Some("Test"));
+        assert_eq!(doc.info.play_res(), (Some(1280), None));
+
+        let style = &doc.styles[0];
+        assert_eq!(style.name, "Sign");
+        assert_eq!(style.font_name, "Open Sans");
+        assert_eq!(style.font_size, 36.5);
+        assert_eq!(style.primary_color, AssColor::rgba(255, 255, 0, 0x80));
+        assert_eq!(style.bold, -1);
+        assert!(style.italic);
+        assert_eq!(style.alignment, 7);
+        // Fields missing from the format keep their defaults.
+        assert_eq!(style.margin_l, Style::default().margin_l);
+
+        let event = &doc.events[0];
+        assert_eq!(event.kind, EventKind::Dialogue);
+        assert_eq!(event.layer, 2);
+        assert_eq!(event.start, Duration::milliseconds(1500));
+        assert_eq!(event.end, Duration::seconds(3));
+        assert_eq!(event.text, "{\\an8}Hello, world");
+        assert_eq!(doc.events[1].kind, EventKind::Comment);
+    }
+
+    #[test]
+    fn ssa_script() {
+        let doc = parse(
+            "[Script Info]\n\
+             ScriptType: v4.00\n\
+             [V4 Styles]\n\
+             Style: Default,Arial,20,16777215,65535,255,0,0,0,1,2,0,6,10,10,10,0,0\n\
+             [Events]\n\
+             Format: Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: Marked=0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Top\n",
+        );
+        assert_eq!(doc.info.get("ScriptType"), Some("v4.00+"));
+        let style = &doc.styles[0];
+        // SSA alignment 6 is top center, and outlines use the back color.
+        assert_eq!(style.alignment, 8);
+        assert_eq!(style.outline_color, style.back_color);
+        assert_eq!(doc.events[0].layer, 0);
+        assert_eq!(doc.events[0].text, "Top");
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let doc = parse(
+            "Before any section\n\
+             [Script Info]\n\
+             No colon here\n\
+             [Events]\n\
+             Dialogue: x,bad,0:00:02.00,Default,,y,0,0,,Text\n\
+             ; comment\n\
+             Picture: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,image.png\n\
+             Nonsense\n",
+        );
+        assert!(doc.info.lines.is_empty());
+        assert_eq!(doc.events.len(), 2);
+        assert_eq!(doc.events[0].layer, 0);
+        assert_eq!(doc.events[0].start, Duration::ZERO);
+        assert_eq!(doc.events[0].end, Duration::seconds(2));
+        assert_eq!(doc.events[1].kind, EventKind::Other("Picture".to_owned()));
+    }
+
+    #[test]
+    fn extra_sections() {
+        let doc = parse("[Aegisub Project Garbage]\nVideo File: a.mkv\n\n[Events]\n");
+        assert_eq!(
+            doc.extra_sections,
+            [RawSection {
+                name: "Aegisub Project Garbage".to_owned(),
+                lines: vec!["Video File: a.mkv".to_owned()],
+            }]
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_int(" 42px"), 42);
+        assert_eq!(parse_int("-7"), -7);
+        assert_eq!(parse_int("+3"), 3);
+        assert_eq!(parse_int("abc"), 0);
+        assert_eq!(parse_int("99999999999"), i32::MAX);
+        assert_eq!(parse_int("-99999999999"), i32::MIN);
+        assert_eq!(parse_float("1.5e2x"), 150.0);
+        assert_eq!(parse_float("-.5"), -0.5);
+        assert_eq!(parse_float("2e"), 2.0);
+        assert_eq!(parse_float("x"), 0.0);
+    }
+
+    #[test]
+    fn legacy_alignment() {
+        assert_eq!(legacy_to_numpad(1), 1);
+        assert_eq!(legacy_to_numpad(2), 2);
+        assert_eq!(legacy_to_numpad(5), 7);
+        assert_eq!(legacy_to_numpad(10), 5);
+        assert_eq!(legacy_to_numpad(0), 2);
+    }
+}
@@ -0,0 +1,339 @@
+//! ASS text writer
+//!
+use std::{
+    fmt::{self, Write},
+    io,
+};
+
+use super::{Attachment, Event, EventKind, InfoLine, ScriptDocument, Style};
+use crate::{timing::AssTime, track::Track};
+
+/// `Format` line written for styles.
+const STYLE_FORMAT: &str = "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, \
+    OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, \
+    Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding";
+
+/// `Format` line written for events.
+const EVENT_FORMAT: &str =
+    "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
+/// Length of each line of encoded attachment data.
+const ENCODED_LINE_LENGTH: usize = 80;
+
+/// Options for writing a [`ScriptDocument`] as ASS text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WriteOptions {
+    /// Write the `[Fonts]` section.
+    pub embed_fonts: bool,
+    /// Write the `[Graphics]` section.
+    pub embed_graphics: bool,
+}
+
+impl Default for WriteOptions {
+    /// Write everything in the document.
+    fn default() -> Self {
+        Self {
+            embed_fonts: true,
+            embed_graphics: true,
+        }
+    }
+}
+
+impl WriteOptions {
+    /// Set whether the `[Fonts]` section is written.
+    pub const fn embed_fonts(mut self, embed: bool) -> Self {
+        self.embed_fonts = embed;
+        self
+    }
+
+    /// Set whether the `[Graphics]` section is written.
+    pub const fn embed_graphics(mut self, embed: bool) -> Self {
+        self.embed_graphics = embed;
+        self
+    }
+}
+
+impl ScriptDocument {
+    /// Write the document as an ASS script.
+    ///
+    /// Styles and events are always written with the standard V4+ `Format` lines, followed by
+    /// any unknown sections. Parsing the output gives back an equal document, except for
+    /// attachments left out by `options` and timestamps, which are rounded to centiseconds.
+    pub fn write<W: io::Write>(&self, mut writer: W, options: &WriteOptions) -> io::Result<()> {
+        writer.write_all(self.to_ass_string(options).as_bytes())
+    }
+
+    /// Write the document as an ASS script in to a string. See [`ScriptDocument::write`].
+    pub fn to_ass_string(&self, options: &WriteOptions) -> String {
+        let mut out = String::new();
+        // Writing to a string can't fail.
+        let _ = write_document(&mut out, self, options);
+        out
+    }
+}
+
+impl Track {
+    /// Write the script loaded in to the track as an ASS script.
+    ///
+    /// See [`ScriptDocument::from_track`] for what Libass keeps of a script.
+    pub fn write_ass<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        ScriptDocument::from_track(self).write(writer, &WriteOptions::default())
+    }
+}
+
+impl fmt::Display for ScriptDocument {
+    /// Formats the document as an ASS script, with the default [`WriteOptions`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_document(f, self, &WriteOptions::default())
+    }
+}
+
+/// Write a whole document.
+fn write_document(f: &mut impl Write, doc: &ScriptDocument, options: &WriteOptions) -> fmt::Result {
+    f.write_str("[Script Info]\n")?;
+    for line in &doc.info.lines {
+        match line {
+            InfoLine::Property { key, value } => writeln!(f, "{key}: {value}")?,
+            InfoLine::Comment(comment) => writeln!(f, ";{comment}")?,
+        }
+    }
+
+    write!(f, "\n[V4+ Styles]\n{STYLE_FORMAT}\n")?;
+    for style in &doc.styles {
+        write_style(f, style)?;
+    }
+
+    if options.embed_fonts {
+        write_attachments(f, "Fonts", "fontname", &doc.fonts)?;
+    }
+    if options.embed_graphics {
+        write_attachments(f, "Graphics", "filename", &doc.graphics)?;
+    }
+
+    write!(f, "\n[Events]\n{EVENT_FORMAT}\n")?;
+    for event in &doc.events {
+        write_event(f, event)?;
+    }
+
+    for section in &doc.extra_sections {
+        write!(f, "\n[{}]\n", section.name)?;
+        for line in &section.lines {
+            writeln!(f, "{line}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a `Style:` line.
+fn write_style(f: &mut impl Write, style: &Style) -> fmt::Result {
+    writeln!(
+        f,
+        "Style: {},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        style.name,
+        style.font_name,
+        style.font_size,
+        style.primary_color,
+        style.secondary_color,
+        style.outline_color,
+        style.back_color,
+        style.bold,
+        flag(style.italic),
+        flag(style.underline),
+        flag(style.strike_out),
+        style.scale_x,
+        style.scale_y,
+        style.spacing,
+        style.angle,
+        style.border_style,
+        style.outline,
+        style.shadow,
+        style.alignment,
+        style.margin_l,
+        style.margin_r,
+        style.margin_v,
+        style.encoding,
+    )
+}
+
+/// Write a `Dialogue:`, `Comment:` or other event line.
+fn write_event(f: &mut impl Write, event: &Event) -> fmt::Result {
+    let kind = match &event.kind {
+        EventKind::Dialogue => "Dialogue",
+        EventKind::Comment => "Comment",
+        EventKind::Other(kind) => kind,
+    };
+    write!(f, "{kind}: {},", event.layer)?;
+    f.write_str(&AssTime::from(event.start).to_ass_string())?;
+    f.write_char(',')?;
+    f.write_str(&AssTime::from(event.end).to_ass_string())?;
+    writeln!(
+        f,
+        ",{},{},{},{},{},{},{}",
+        event.style,
+        event.name,
+        event.margin_l,
+        event.margin_r,
+        event.margin_v,
+        event.effect,
+        event.text,
+    )
+}
+
+/// Write a `[Fonts]` or `[Graphics]` section, if there are any attachments.
+fn write_attachments(
+    f: &mut impl Write,
+    section: &str,
+    key: &str,
+    attachments: &[Attachment],
+) -> fmt::Result {
+    if attachments.is_empty() {
+        return Ok(());
+    }
+    write!(f, "\n[{section}]\n")?;
+    for attachment in attachments {
+        writeln!(f, "{key}: {}", attachment.name)?;
+        let encoded = uuencode(&attachment.data);
+        for line in encoded.as_bytes().chunks(ENCODED_LINE_LENGTH) {
+            // Encoded data is ASCII.
+            f.write_str(std::str::from_utf8(line).map_err(|_| fmt::Error)?)?;
+            f.write_char('\n')?;
+        }
+    }
+    Ok(())
+}
+
+/// Boolean style fields are written as `-1` and `0`.
+const fn flag(value: bool) -> i32 {
+    if value {
+        -1
+    } else {
+        0
+    }
+}
+
+/// Encode with the uuencoding variant used for embedded files, the inverse of `uudecode`.
+fn uuencode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let mut bytes = [0; 4];
+        bytes[1..=group.len()].copy_from_slice(group);
+        let value = u32::from_be_bytes(bytes);
+        for i in 0..=group.len() {
+            let sextet = (value >> (18 - 6 * i)) & 0x3f;
+            encoded.push(char::from_u32(sextet + 33).unwrap_or('!'));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+    use crate::{color::AssColor, script::RawSection};
+
+    /// A document using every part of the format.
+    fn document() -> ScriptDocument {
+        let mut doc = ScriptDocument::new();
+        doc.info
+            .lines
+            .push(InfoLine::Comment(" Made by hand".to_owned()));
+        doc.info.set("PlayResX", "1920");
+        doc.info.set("PlayResY", "1080");
+        doc.styles.push(Style {
+            name: "Sign".to_owned(),
+            font_name: "Noto Sans".to_owned(),
+            font_size: 52.5,
+            primary_color: AssColor::rgba(1, 2, 3, 4),
+            bold: 700,
+            italic: true,
+            alignment: 9,
+            margin_l: 25,
+            ..Style::default()
+        });
+        doc.events.push(Event {
+            layer: 1,
+            start: Duration::milliseconds(1230),
+            end: Duration::milliseconds(4560),
+            style: "Sign".to_owned(),
+            name: "Actor".to_owned(),
+            margin_v: 40,
+            text: "{\\pos(10,20)}Commas, and {braces}\\Nnew line".to_owned(),
+            ..Event::default()
+        });
+        doc.events.push(Event {
+            kind: EventKind::Comment,
+            effect: "Banner;10".to_owned(),
+            ..Event::default()
+        });
+        doc.fonts.push(Attachment {
+            name: "font_0.ttf".to_owned(),
+            data: (0..=255).collect(),
+        });
+        doc.graphics.push(Attachment {
+            name: "logo.png".to_owned(),
+            data: vec![1, 2],
+        });
+        doc.extra_sections.push(RawSection {
+            name: "Aegisub Extradata".to_owned(),
+            lines: vec!["Data: 1,abc".to_owned()],
+        });
+        doc
+    }
+
+    #[test]
+    fn round_trip() {
+        let doc = document();
+        assert_eq!(ScriptDocument::parse(&doc.to_string()), doc);
+    }
+
+    #[test]
+    fn times_round_to_centiseconds() {
+        let mut doc = document();
+        doc.events[0].start = Duration::milliseconds(1234);
+        doc.events[0].end = Duration::milliseconds(4565);
+        let parsed = ScriptDocument::parse(&doc.to_string());
+        assert_eq!(parsed.events[0].start, Duration::milliseconds(1230));
+        assert_eq!(parsed.events[0].end, Duration::milliseconds(4570));
+    }
+
+    #[test]
+    fn leave_out_attachments() {
+        let doc = document();
+        let options = WriteOptions::default()
+            .embed_fonts(false)
+            .embed_graphics(false);
+        let text = doc.to_ass_string(&options);
+        assert!(!text.contains("[Fonts]") && !text.contains("[Graphics]"));
+        let parsed = ScriptDocument::parse(&text);
+        assert!(parsed.fonts.is_empty() && parsed.graphics.is_empty());
+        assert_eq!(parsed.events, doc.events);
+    }
+
+    #[test]
+    fn event_line() {
+        let mut out = String::new();
+        write_event(&mut out, &document().events[0]).unwrap();
+        assert_eq!(
+            out,
+            "Dialogue: 1,0:00:01.23,0:00:04.56,Sign,Actor,0,0,40,,\
+             {\\pos(10,20)}Commas, and {braces}\\Nnew line\n"
+        );
+    }
+
+    #[test]
+    fn uuencoding() {
+        for len in 0..8 {
+            let mut doc = ScriptDocument::new();
+            doc.fonts.push(Attachment {
+                name: "a.ttf".to_owned(),
+                data: (0..len).map(|i| i * 37).collect(),
+            });
+            assert_eq!(ScriptDocument::parse(&doc.to_string()).fonts, doc.fonts);
+        }
+        // Each group of 3 bytes is 4 characters, and a partial group one more than its length.
+        assert_eq!(uuencode(&[0, 0, 0, 0]), "!!!!!!");
+    }
+}
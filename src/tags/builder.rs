@@ -0,0 +1,517 @@
+//! Override tag builder
+//!
+use std::fmt::{self, Write};
+
+use super::{ClipShape, KaraokeKind, Spanned, Tag};
+use crate::color::AssColor;
+
+/// Builds an override block for generated event text.
+///
+/// Formatting the block with `Display` gives valid override syntax in braces, or nothing if no
+/// tags were added. Use [`TagBlock::apply_to`] to put it in front of text.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TagBlock {
+    /// Tags in the order they were added.
+    tags: Vec<Tag>,
+}
+
+impl TagBlock {
+    /// Create an empty block.
+    pub const fn new() -> Self {
+        Self { tags: Vec::new() }
+    }
+
+    /// Add any tag.
+    ///
+    /// Strings in tags are written as is, so they must not contain `\`, `{` or `}`. The builder
+    /// methods taking strings remove those characters.
+    pub fn tag(mut self, tag: Tag) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    /// The tags added so far.
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    /// Whether no tags have been added.
+    pub const fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    /// Event text of this block followed by `text`, escaped with [`escape_text`].
+    pub fn apply_to(&self, text: &str) -> String {
+        format!("{self}{}", escape_text(text))
+    }
+
+    /// `\pos`, position the line's anchor point.
+    pub fn pos(self, x: f64, y: f64) -> Self {
+        self.tag(Tag::Position { x, y })
+    }
+
+    /// `\move`, move the anchor point over the whole event, or from `t1` to `t2` milliseconds.
+    pub fn move_to(self, from: (f64, f64), to: (f64, f64), time: Option<(i32, i32)>) -> Self {
+        self.tag(Tag::Move { from, to, time })
+    }
+
+    /// `\org`, the origin for rotations.
+    pub fn origin(self, x: f64, y: f64) -> Self {
+        self.tag(Tag::Origin { x, y })
+    }
+
+    /// `\fad`, fade in and out over durations in milliseconds.
+    pub fn fade(self, fade_in: i32, fade_out: i32) -> Self {
+        self.tag(Tag::Fad { fade_in, fade_out })
+    }
+
+    /// `\an`, numpad alignment from 1 to 9.
+    pub fn align(self, alignment: i32) -> Self {
+        self.tag(Tag::Alignment(alignment))
+    }
+
+    /// `\c`, the fill color. Its alpha is ignored, see [`TagBlock::alpha`].
+    pub fn color(self, color: AssColor) -> Self {
+        self.color_n(1, color)
+    }
+
+    /// `\1c` to `\4c`, for the fill, karaoke, outline and shadow colors.
+    ///
+    /// `index` is clamped to 1 to 4.
+    pub fn color_n(self, index: u8, color: AssColor) -> Self {
+        self.tag(Tag::Color {
+            index: index.clamp(1, 4),
+            color: Some(AssColor { alpha: 0, ..color }),
+        })
+    }
+
+    /// `\alpha`, transparency of every color, where 0 is opaque.
+    pub fn alpha(self, alpha: u8) -> Self {
+        self.tag(Tag::Alpha {
+            index: None,
+            alpha: Some(alpha),
+        })
+    }
+
+    /// `\1a` to `\4a`, transparency of a single color.
+    ///
+    /// `index` is clamped to 1 to 4.
+    pub fn alpha_n(self, index: u8, alpha: u8) -> Self {
+        self.tag(Tag::Alpha {
+            index: Some(index.clamp(1, 4)),
+            alpha: Some(alpha),
+        })
+    }
+
+    /// `\fs`
+    pub fn font_size(self, size: f64) -> Self {
+        self.tag(Tag::FontSize(Some(size)))
+    }
+
+    /// `\fn`
+    pub fn font_name(self, name: &str) -> Self {
+        self.tag(Tag::FontName(Some(sanitize(name))))
+    }
+
+    /// `\b`
+    pub fn bold(self, bold: bool) -> Self {
+        self.tag(Tag::Bold(Some(bold.into())))
+    }
+
+    /// `\i`
+    pub fn italic(self, italic: bool) -> Self {
+        self.tag(Tag::Italic(Some(italic)))
+    }
+
+    /// `\u`
+    pub fn underline(self, underline: bool) -> Self {
+        self.tag(Tag::Underline(Some(underline)))
+    }
+
+    /// `\s`
+    pub fn strike_out(self, strike_out: bool) -> Self {
+        self.tag(Tag::StrikeOut(Some(strike_out)))
+    }
+
+    /// `\bord`
+    pub fn border(self, width: f64) -> Self {
+        self.tag(Tag::Border(Some(width)))
+    }
+
+    /// `\shad`
+    pub fn shadow(self, depth: f64) -> Self {
+        self.tag(Tag::Shadow(Some(depth)))
+    }
+
+    /// `\blur`
+    pub fn blur(self, strength: f64) -> Self {
+        self.tag(Tag::Blur(Some(strength)))
+    }
+
+    /// `\be`
+    pub fn blur_edges(self, strength: f64) -> Self {
+        self.tag(Tag::BlurEdges(Some(strength)))
+    }
+
+    /// `\fscx` and `\fscy`, in percent.
+    pub fn scale(self, x: f64, y: f64) -> Self {
+        self.tag(Tag::FontScaleX(Some(x)))
+            .tag(Tag::FontScaleY(Some(y)))
+    }
+
+    /// `\frx`
+    pub fn rotate_x(self, degrees: f64) -> Self {
+        self.tag(Tag::RotateX(Some(degrees)))
+    }
+
+    /// `\fry`
+    pub fn rotate_y(self, degrees: f64) -> Self {
+        self.tag(Tag::RotateY(Some(degrees)))
+    }
+
+    /// `\frz`
+    pub fn rotate_z(self, degrees: f64) -> Self {
+        self.tag(Tag::RotateZ(Some(degrees)))
+    }
+
+    /// `\t`, animate towards the tags in `block`.
+    ///
+    /// Tags that can't be animated are ignored by renderers.
+    pub fn transform(self, time: Option<(i32, i32)>, accel: Option<f64>, block: Self) -> Self {
+        let tags = block
+            .tags
+            .into_iter()
+            .map(|value| Spanned { value, span: 0..0 })
+            .collect();
+        self.tag(Tag::Transform { time, accel, tags })
+    }
+
+    /// `\clip` to a rectangle, or `\iclip` if `inverse`.
+    pub fn clip(self, inverse: bool, x1: f64, y1: f64, x2: f64, y2: f64) -> Self {
+        self.tag(Tag::Clip {
+            inverse,
+            shape: ClipShape::Rect { x1, y1, x2, y2 },
+        })
+    }
+
+    /// `\k`, `\kf` or `\ko` with a duration in centiseconds.
+    pub fn karaoke(self, kind: KaraokeKind, duration: i32) -> Self {
+        self.tag(Tag::Karaoke { kind, duration })
+    }
+
+    /// `\p`, start drawing mode at a scale, or return to text with 0.
+    pub fn drawing(self, scale: i32) -> Self {
+        self.tag(Tag::Drawing(scale))
+    }
+
+    /// `\q`, wrap style.
+    pub fn wrap_style(self, style: i32) -> Self {
+        self.tag(Tag::WrapStyle(style))
+    }
+
+    /// `\r`, reset to the line's style, or to a named style.
+    pub fn reset(self, style: Option<&str>) -> Self {
+        self.tag(Tag::Reset(style.map(sanitize)))
+    }
+}
+
+impl fmt::Display for TagBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.tags.is_empty() {
+            return Ok(());
+        }
+        f.write_char('{')?;
+        for tag in &self.tags {
+            write!(f, "{tag}")?;
+        }
+        f.write_char('}')
+    }
+}
+
+impl From<TagBlock> for String {
+    fn from(block: TagBlock) -> Self {
+        block.to_string()
+    }
+}
+
+impl fmt::Display for Tag {
+    /// Formats the tag as written in an override block, with its leading backslash.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// Write a tag name with an optional value.
+        fn opt(
+            f: &mut fmt::Formatter<'_>,
+            name: &str,
+            value: Option<impl fmt::Display>,
+        ) -> fmt::Result {
+            match value {
+                Some(value) => write!(f, "\\{name}{value}"),
+                None => write!(f, "\\{name}"),
+            }
+        }
+
+        match self {
+            Tag::Position { x, y } => write!(f, "\\pos({x},{y})"),
+            Tag::Move { from, to, time } => {
+                write!(f, "\\move({},{},{},{}", from.0, from.1, to.0, to.1)?;
+                if let Some((t1, t2)) = time {
+                    write!(f, ",{t1},{t2}")?;
+                }
+                f.write_char(')')
+            }
+            Tag::Origin { x, y } => write!(f, "\\org({x},{y})"),
+            Tag::Fad { fade_in, fade_out } => write!(f, "\\fad({fade_in},{fade_out})"),
+            Tag::Fade { alpha, time } => write!(
+                f,
+                "\\fade({},{},{},{},{},{},{})",
+                alpha[0], alpha[1], alpha[2], time[0], time[1], time[2], time[3]
+            ),
+            Tag::Alignment(an) => write!(f, "\\an{an}"),
+            Tag::LegacyAlignment(a) => write!(f, "\\a{a}"),
+            Tag::Color { index, color } => {
+                let color = color.map(AssColor::to_override_string);
+                match index {
+                    1 => opt(f, "c", color),
+                    index => opt(f, &format!("{index}c"), color),
+                }
+            }
+            Tag::Alpha { index, alpha } => {
+                let alpha = alpha.map(|alpha| format!("&H{alpha:02X}&"));
+                match index {
+                    Some(index) => opt(f, &format!("{index}a"), alpha),
+                    None => opt(f, "alpha", alpha),
+                }
+            }
+            Tag::FontSize(size) => opt(f, "fs", *size),
+            Tag::FontSizeStep(step) => write!(f, "\\fs{step:+}"),
+            Tag::FontName(name) => opt(f, "fn", name.as_deref()),
+            Tag::FontEncoding(encoding) => opt(f, "fe", *encoding),
+            Tag::FontScale => f.write_str("\\fsc"),
+            Tag::FontScaleX(scale) => opt(f, "fscx", *scale),
+            Tag::FontScaleY(scale) => opt(f, "fscy", *scale),
+            Tag::FontSpacing(spacing) => opt(f, "fsp", *spacing),
+            Tag::Bold(bold) => opt(f, "b", *bold),
+            Tag::Italic(italic) => opt(f, "i", italic.map(i32::from)),
+            Tag::Underline(underline) => opt(f, "u", underline.map(i32::from)),
+            Tag::StrikeOut(strike_out) => opt(f, "s", strike_out.map(i32::from)),
+            Tag::Border(width) => opt(f, "bord", *width),
+            Tag::BorderX(width) => opt(f, "xbord", *width),
+            Tag::BorderY(width) => opt(f, "ybord", *width),
+            Tag::Shadow(depth) => opt(f, "shad", *depth),
+            Tag::ShadowX(depth) => opt(f, "xshad", *depth),
+            Tag::ShadowY(depth) => opt(f, "yshad", *depth),
+            Tag::Blur(strength) => opt(f, "blur", *strength),
+            Tag::BlurEdges(strength) => opt(f, "be", *strength),
+            Tag::RotateX(degrees) => opt(f, "frx", *degrees),
+            Tag::RotateY(degrees) => opt(f, "fry", *degrees),
+            Tag::RotateZ(degrees) => opt(f, "frz", *degrees),
+            Tag::ShearX(factor) => opt(f, "fax", *factor),
+            Tag::ShearY(factor) => opt(f, "fay", *factor),
+            Tag::Transform { time, accel, tags } => {
+                f.write_str("\\t(")?;
+                if let Some((t1, t2)) = time {
+                    write!(f, "{t1},{t2},")?;
+                }
+                if let Some(accel) = accel {
+                    write!(f, "{accel},")?;
+                }
+                for tag in tags {
+                    write!(f, "{}", tag.value)?;
+                }
+                f.write_char(')')
+            }
+            Tag::Clip { inverse, shape } => {
+                let name = if *inverse { "iclip" } else { "clip" };
+                match shape {
+                    ClipShape::Rect { x1, y1, x2, y2 } => {
+                        write!(f, "\\{name}({x1},{y1},{x2},{y2})")
+                    }
+                    ClipShape::Drawing {
+                        scale: Some(scale),
+                        commands,
+                    } => write!(f, "\\{name}({scale},{commands})"),
+                    ClipShape::Drawing {
+                        scale: None,
+                        commands,
+                    } => write!(f, "\\{name}({commands})"),
+                }
+            }
+            Tag::Karaoke { kind, duration } => {
+                let name = match kind {
+                    KaraokeKind::Fill => "k",
+                    KaraokeKind::Sweep => "kf",
+                    KaraokeKind::Outline => "ko",
+                };
+                write!(f, "\\{name}{duration}")
+            }
+            Tag::KaraokeTime(time) => write!(f, "\\kt{time}"),
+            Tag::Drawing(scale) => write!(f, "\\p{scale}"),
+            Tag::DrawingBaselineOffset(offset) => write!(f, "\\pbo{offset}"),
+            Tag::WrapStyle(style) => write!(f, "\\q{style}"),
+            Tag::Reset(style) => opt(f, "r", style.as_deref()),
+        }
+    }
+}
+
+/// Escape plain text so it is displayed as written in event text.
+///
+/// Line breaks become `\N`, and braces are escaped as `\{` and `\}`, which Libass displays as
+/// braces. Text that would otherwise be read as the `\N`, `\n` or `\h` escapes is broken up with
+/// an invisible U+2060 WORD JOINER, so it is displayed literally. An empty override block can't
+/// be used for that, as `\{` is itself an escape.
+pub fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => escaped.push_str("\\N"),
+            '{' => escaped.push_str("\\{"),
+            '}' => escaped.push_str("\\}"),
+            '\\' if matches!(chars.peek(), Some('N' | 'n' | 'h')) => escaped.push_str("\\\u{2060}"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Remove characters that would end a tag argument or override block early.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !matches!(c, '\\' | '{' | '}'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::{parse, Item};
+
+    #[test]
+    fn tags_round_trip() {
+        let tags = [
+            Tag::Position { x: 1.5, y: -2.0 },
+            Tag::Move {
+                from: (0.0, 0.0),
+                to: (10.0, 20.0),
+                time: Some((0, 500)),
+            },
+            Tag::Origin { x: 3.0, y: 4.0 },
+            Tag::Fad {
+                fade_in: 100,
+                fade_out: 200,
+            },
+            Tag::Fade {
+                alpha: [255, 0, 255],
+                time: [0, 100, 900, 1000],
+            },
+            Tag::Alignment(7),
+            Tag::LegacyAlignment(5),
+            Tag::Color {
+                index: 3,
+                color: Some(AssColor::rgb(1, 2, 3)),
+            },
+            Tag::Alpha {
+                index: None,
+                alpha: Some(0x80),
+            },
+            Tag::Alpha {
+                index: Some(2),
+                alpha: None,
+            },
+            Tag::FontSize(Some(36.0)),
+            Tag::FontSizeStep(2.0),
+            Tag::FontSizeStep(-1.0),
+            Tag::FontName(Some("Open Sans".to_owned())),
+            Tag::FontEncoding(Some(128)),
+            Tag::FontScale,
+            Tag::FontScaleX(Some(120.0)),
+            Tag::FontScaleY(None),
+            Tag::FontSpacing(Some(-1.0)),
+            Tag::Bold(Some(700)),
+            Tag::Italic(Some(true)),
+            Tag::Underline(Some(false)),
+            Tag::StrikeOut(None),
+            Tag::Border(Some(2.5)),
+            Tag::BorderX(Some(1.0)),
+            Tag::BorderY(None),
+            Tag::Shadow(Some(0.0)),
+            Tag::ShadowX(Some(-2.0)),
+            Tag::ShadowY(Some(2.0)),
+            Tag::Blur(Some(0.6)),
+            Tag::BlurEdges(Some(1.0)),
+            Tag::RotateX(Some(10.0)),
+            Tag::RotateY(Some(-10.0)),
+            Tag::RotateZ(Some(45.0)),
+            Tag::ShearX(Some(0.1)),
+            Tag::ShearY(Some(-0.1)),
+            Tag::Clip {
+                inverse: true,
+                shape: ClipShape::Rect {
+                    x1: 0.0,
+                    y1: 0.0,
+                    x2: 100.0,
+                    y2: 50.0,
+                },
+            },
+            Tag::Clip {
+                inverse: false,
+                shape: ClipShape::Drawing {
+                    scale: None,
+                    commands: "m 0 0 l 10 0 10 10".to_owned(),
+                },
+            },
+            Tag::Karaoke {
+                kind: KaraokeKind::Sweep,
+                duration: 25,
+            },
+            Tag::KaraokeTime(100),
+            Tag::Drawing(1),
+            Tag::DrawingBaselineOffset(-5.0),
+            Tag::WrapStyle(2),
+            Tag::Reset(Some("Sign".to_owned())),
+        ];
+        for tag in tags {
+            let text = format!("{{{tag}}}");
+            let parsed = parse(&text);
+            assert!(parsed.errors.is_empty(), "{text}: {:?}", parsed.errors);
+            assert_eq!(
+                parsed
+                    .items
+                    .iter()
+                    .map(|item| &item.value)
+                    .collect::<Vec<_>>(),
+                [&Item::Tag(tag)],
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn block() {
+        let block = TagBlock::new()
+            .pos(10.0, 20.0)
+            .font_name("A {weird} \\name")
+            .transform(Some((0, 100)), None, TagBlock::new().alpha(0xff));
+        assert_eq!(
+            block.to_string(),
+            r"{\pos(10,20)\fnA weird name\t(0,100,\alpha&HFF&)}"
+        );
+        assert_eq!(TagBlock::new().to_string(), "");
+        assert_eq!(TagBlock::new().bold(true).apply_to("{x}"), r"{\b1}\{x\}");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(escape_text("a\r\nb\nc\rd"), r"a\Nb\Nc\Nd");
+        assert_eq!(escape_text("{x}"), r"\{x\}");
+        assert_eq!(
+            escape_text(r"C:\new\hat\x"),
+            "C:\\\u{2060}new\\\u{2060}hat\\x"
+        );
+        // Escaped text is a single text run, displayed as written.
+        let parsed = parse(&escape_text(r"{\b1} \N"));
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.tags().count(), 0);
+    }
+}
@@ -0,0 +1,263 @@
+//! Override tags in event text
+//!
+//! Event text such as `{\pos(320,40)\c&H00FFFF&}Hello\Nworld` is a mix of text and override
+//! blocks in braces. [`parse`] splits it in to text runs and typed [`Tag`]s, each with the byte
+//! range it came from, and reports tags it doesn't understand instead of failing. [`TagBlock`]
+//! goes the other way, building override blocks for generated text.
+use std::ops::Range;
+
+use crate::{color::AssColor, script::Event};
+
+mod builder;
+mod parse;
+
+pub use builder::{escape_text, TagBlock};
+pub(crate) use parse::find_block;
+pub use parse::parse;
+
+/// A value and the byte range of the event text it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    #[allow(missing_docs)]
+    pub value: T,
+    #[allow(missing_docs)]
+    pub span: Range<usize>,
+}
+
+/// Event text split in to text and tags.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParsedText {
+    /// Text runs, tags and comments in order.
+    pub items: Vec<Spanned<Item>>,
+    /// Tags that could not be parsed. They are left out of `items`.
+    pub errors: Vec<TagError>,
+}
+
+impl ParsedText {
+    /// The tags and their spans, in order, without the text.
+    pub fn tags(&self) -> impl Iterator<Item = (&Tag, &Range<usize>)> + '_ {
+        self.items.iter().filter_map(|item| match &item.value {
+            Item::Tag(tag) => Some((tag, &item.span)),
+            _ => None,
+        })
+    }
+}
+
+/// A piece of event text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    /// Text outside of override blocks, with escapes such as `\N` and `\h` kept as written.
+    Text(String),
+    /// A tag inside an override block.
+    Tag(Tag),
+    /// Text inside an override block that isn't a tag, which renderers ignore.
+    Comment(String),
+}
+
+/// An override tag.
+///
+/// Tags that can be written without an argument to reset to the style's value take an `Option`,
+/// which is `None` in that case. Times are in milliseconds unless noted otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    /// `\pos(x,y)`
+    Position {
+        #[allow(missing_docs)]
+        x: f64,
+        #[allow(missing_docs)]
+        y: f64,
+    },
+    /// `\move(x1,y1,x2,y2[,t1,t2])`
+    Move {
+        #[allow(missing_docs)]
+        from: (f64, f64),
+        #[allow(missing_docs)]
+        to: (f64, f64),
+        /// Start and end of the movement, relative to the start of the event.
+        time: Option<(i32, i32)>,
+    },
+    /// `\org(x,y)`, the origin for rotations.
+    Origin {
+        #[allow(missing_docs)]
+        x: f64,
+        #[allow(missing_docs)]
+        y: f64,
+    },
+    /// `\fad(in,out)`
+    Fad {
+        /// Fade in duration.
+        fade_in: i32,
+        /// Fade out duration.
+        fade_out: i32,
+    },
+    /// `\fade(a1,a2,a3,t1,t2,t3,t4)`
+    Fade {
+        /// Transparency before, during and after the fade.
+        alpha: [i32; 3],
+        /// Start and end of the fade in, then start and end of the fade out.
+        time: [i32; 4],
+    },
+    /// `\an`, numpad alignment.
+    Alignment(i32),
+    /// `\a`, legacy SSA alignment.
+    LegacyAlignment(i32),
+    /// `\c`, `\1c` to `\4c`.
+    Color {
+        /// 1 for primary, 2 secondary, 3 outline, 4 shadow.
+        index: u8,
+        /// The alpha of the color is always 0.
+        color: Option<AssColor>,
+    },
+    /// `\alpha` and `\1a` to `\4a`.
+    Alpha {
+        /// Which color, or `None` for all of them.
+        index: Option<u8>,
+        /// Transparency, where 0 is opaque.
+        alpha: Option<u8>,
+    },
+    /// `\fs`
+    FontSize(Option<f64>),
+    /// `\fs+N` or `\fs-N`, changes the font size by `N` tenths of the current size.
+    FontSizeStep(f64),
+    /// `\fn`
+    FontName(Option<String>),
+    /// `\fe`, font charset.
+    FontEncoding(Option<i32>),
+    /// `\fsc`, resets both scales.
+    FontScale,
+    /// `\fscx`, in percent.
+    FontScaleX(Option<f64>),
+    /// `\fscy`, in percent.
+    FontScaleY(Option<f64>),
+    /// `\fsp`, letter spacing.
+    FontSpacing(Option<f64>),
+    /// `\b`, 0 or 1, or a font weight.
+    Bold(Option<i32>),
+    /// `\i`
+    Italic(Option<bool>),
+    /// `\u`
+    Underline(Option<bool>),
+    /// `\s`
+    StrikeOut(Option<bool>),
+    /// `\bord`
+    Border(Option<f64>),
+    /// `\xbord`
+    BorderX(Option<f64>),
+    /// `\ybord`
+    BorderY(Option<f64>),
+    /// `\shad`
+    Shadow(Option<f64>),
+    /// `\xshad`
+    ShadowX(Option<f64>),
+    /// `\yshad`
+    ShadowY(Option<f64>),
+    /// `\blur`, gaussian blur.
+    Blur(Option<f64>),
+    /// `\be`, edge blur.
+    BlurEdges(Option<f64>),
+    /// `\frx`
+    RotateX(Option<f64>),
+    /// `\fry`
+    RotateY(Option<f64>),
+    /// `\frz` or `\fr`
+    RotateZ(Option<f64>),
+    /// `\fax`
+    ShearX(Option<f64>),
+    /// `\fay`
+    ShearY(Option<f64>),
+    /// `\t([t1,t2,][accel,]tags)`, animate tags.
+    Transform {
+        /// Start and end of the animation, or the whole event when `None`.
+        time: Option<(i32, i32)>,
+        /// Acceleration exponent, 1 when `None`.
+        accel: Option<f64>,
+        /// Tags animated towards.
+        tags: Vec<Spanned<Tag>>,
+    },
+    /// `\clip` or `\iclip`.
+    Clip {
+        /// `\iclip`, which hides what is inside the clip instead.
+        inverse: bool,
+        #[allow(missing_docs)]
+        shape: ClipShape,
+    },
+    /// `\k`, `\K`, `\kf` or `\ko`.
+    Karaoke {
+        #[allow(missing_docs)]
+        kind: KaraokeKind,
+        /// Syllable duration in centiseconds.
+        duration: i32,
+    },
+    /// `\kt`, sets the karaoke start time in centiseconds.
+    KaraokeTime(i32),
+    /// `\p`, drawing mode scale, or 0 to return to text.
+    Drawing(i32),
+    /// `\pbo`, baseline offset of drawings.
+    DrawingBaselineOffset(f64),
+    /// `\q`, wrap style.
+    WrapStyle(i32),
+    /// `\r`, reset to the line's style or a named style.
+    Reset(Option<String>),
+}
+
+/// Shape of a `\clip` or `\iclip`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipShape {
+    /// A rectangle in script pixels.
+    #[allow(missing_docs)]
+    Rect { x1: f64, y1: f64, x2: f64, y2: f64 },
+    /// A vector drawing.
+    Drawing {
+        /// Drawing scale, 1 when `None`.
+        scale: Option<i32>,
+        /// Drawing commands.
+        commands: String,
+    },
+}
+
+/// How a karaoke syllable is highlighted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KaraokeKind {
+    /// `\k`, switches colors at the start of the syllable.
+    Fill,
+    /// `\kf` or `\K`, sweeps from left to right over the syllable.
+    Sweep,
+    /// `\ko`, like `Fill` but the outline is hidden until the syllable starts.
+    Outline,
+}
+
+/// A tag that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagError {
+    #[allow(missing_docs)]
+    pub kind: TagErrorKind,
+    /// Byte range of the tag, or of the unclosed block.
+    pub span: Range<usize>,
+}
+
+/// Why a tag could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagErrorKind {
+    /// Not a known tag. Holds the text after the backslash.
+    Unknown(String),
+    /// A known tag with the wrong number or type of arguments. Holds the tag name.
+    InvalidArguments(String),
+    /// An override block with no closing brace. The rest of the text is kept as text.
+    UnclosedBlock,
+}
+
+/// Replace spans of event text, such as rewritten tags. Spans must be in order and not overlap.
+pub(crate) fn replace_spans(text: &str, edits: Vec<(Range<usize>, String)>) -> String {
+    let mut out = text.to_owned();
+    for (span, replacement) in edits.into_iter().rev() {
+        out.replace_range(span, &replacement);
+    }
+    out
+}
+
+impl Event {
+    /// Parse the event's text in to text runs and tags.
+    pub fn parse_text(&self) -> ParsedText {
+        parse(&self.text)
+    }
+}
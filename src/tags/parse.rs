@@ -0,0 +1,534 @@
+//! Override tag parser
+//!
+use std::ops::Range;
+
+use super::{ClipShape, Item, KaraokeKind, ParsedText, Spanned, Tag, TagError, TagErrorKind};
+use crate::color::{parse_hex_prefix, strip_hex_prefix, AssColor};
+
+/// Every tag name, longer names before their prefixes so the longest match wins.
+///
+/// Arguments follow tag names without a separator, so `\fscx120` has to be matched as `fscx`
+/// rather than `fs` or `fsc`.
+const NAMES: &[&str] = &[
+    "xbord", "ybord", "xshad", "yshad", "iclip", "alpha", "blur", "bord", "shad", "clip", "fscx",
+    "fscy", "fade", "move", "fsc", "fsp", "frx", "fry", "frz", "fax", "fay", "fad", "pos", "org",
+    "pbo", "1c", "2c", "3c", "4c", "1a", "2a", "3a", "4a", "an", "be", "fs", "fn", "fe", "fr",
+    "kf", "ko", "kt", "a", "b", "c", "i", "k", "K", "p", "q", "r", "s", "t", "u",
+];
+
+/// Parse event text in to text runs and tags.
+///
+/// Never fails: tags that can't be parsed are reported in [`ParsedText::errors`] and otherwise
+/// skipped, like renderers do.
+pub fn parse(text: &str) -> ParsedText {
+    let mut out = ParsedText::default();
+    let mut pos = 0;
+    while pos < text.len() {
+        let Some(open) = find_block(text, pos) else {
+            push_text(&mut out, text, pos..text.len());
+            break;
+        };
+        push_text(&mut out, text, pos..open);
+        let Some(close) = text[open..].find('}').map(|i| open + i) else {
+            out.errors.push(TagError {
+                kind: TagErrorKind::UnclosedBlock,
+                span: open..text.len(),
+            });
+            push_text(&mut out, text, open..text.len());
+            break;
+        };
+        let ParsedText { items, errors } = &mut out;
+        parse_block(text, open + 1..close, errors, &mut |item| items.push(item));
+        pos = close + 1;
+    }
+    out
+}
+
+/// Find the next `{` from `pos` that starts an override block.
+///
+/// Libass displays `\{` and `\}` as braces, so escaped braces are skipped.
+pub(crate) fn find_block(text: &str, pos: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = pos;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => return Some(i),
+            b'\\' if matches!(bytes.get(i + 1), Some(b'{' | b'}')) => i += 2,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Add a text run, if it isn't empty.
+fn push_text(out: &mut ParsedText, text: &str, span: Range<usize>) {
+    if !span.is_empty() {
+        out.items.push(Spanned {
+            value: Item::Text(text[span.clone()].to_owned()),
+            span,
+        });
+    }
+}
+
+/// Parse the contents of an override block, or the tags of a `\t`.
+fn parse_block(
+    text: &str,
+    block: Range<usize>,
+    errors: &mut Vec<TagError>,
+    emit: &mut dyn FnMut(Spanned<Item>),
+) {
+    let next_tag = |from: usize| {
+        text[from..block.end]
+            .find('\\')
+            .map_or(block.end, |i| from + i)
+    };
+    let mut pos = block.start;
+    while pos < block.end {
+        if !text[pos..].starts_with('\\') {
+            let end = next_tag(pos);
+            emit(Spanned {
+                value: Item::Comment(text[pos..end].to_owned()),
+                span: pos..end,
+            });
+            pos = end;
+            continue;
+        }
+
+        let name_start = pos + 1;
+        let Some(name) = NAMES
+            .iter()
+            .copied()
+            .find(|name| text[name_start..block.end].starts_with(name))
+        else {
+            let end = next_tag(name_start);
+            errors.push(TagError {
+                kind: TagErrorKind::Unknown(text[name_start..end].to_owned()),
+                span: pos..end,
+            });
+            pos = end;
+            continue;
+        };
+        let args_start = name_start + name.len();
+
+        let (args, end) = if takes_parentheses(name) {
+            parenthesised(text, args_start..block.end)
+                .map_or((None, next_tag(args_start)), |(args, end)| {
+                    (Some(args), end)
+                })
+        } else {
+            let end = next_tag(args_start);
+            (Some(args_start..end), end)
+        };
+
+        let tag = match args {
+            Some(args) if name == "t" => transform(text, args, errors),
+            Some(args) => tag(name, text[args].trim()),
+            None => None,
+        };
+        match tag {
+            Some(tag) => emit(Spanned {
+                value: Item::Tag(tag),
+                span: pos..end,
+            }),
+            // A letter after a short name like `\b` is more likely an unknown tag, such as
+            // `\bogus`, than bad arguments.
+            None if !matches!(name, "fn" | "r")
+                && text[args_start..].starts_with(|c: char| c.is_ascii_alphabetic()) =>
+            {
+                errors.push(TagError {
+                    kind: TagErrorKind::Unknown(text[name_start..end].to_owned()),
+                    span: pos..end,
+                });
+            }
+            None => errors.push(TagError {
+                kind: TagErrorKind::InvalidArguments(name.to_owned()),
+                span: pos..end,
+            }),
+        }
+        pos = end;
+    }
+}
+
+/// Whether the arguments of a tag are in parentheses.
+fn takes_parentheses(name: &str) -> bool {
+    matches!(
+        name,
+        "pos" | "move" | "org" | "fad" | "fade" | "clip" | "iclip" | "t"
+    )
+}
+
+/// Find the parenthesised arguments at the start of `range`.
+///
+/// Returns the range inside the parentheses, and where the tag ends. A missing closing
+/// parenthesis is tolerated, with the arguments running to the end of `range`.
+fn parenthesised(text: &str, range: Range<usize>) -> Option<(Range<usize>, usize)> {
+    let open = range.start + text[range.clone()].find(|c: char| !c.is_whitespace())?;
+    if !text[open..].starts_with('(') {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, c) in text[open..range.end].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((open + 1..open + i, open + i + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    Some((open + 1..range.end, range.end))
+}
+
+/// Parse a `\t`, whose arguments end with tags.
+fn transform(text: &str, args: Range<usize>, errors: &mut Vec<TagError>) -> Option<Tag> {
+    let tags_start = text[args.clone()]
+        .find('\\')
+        .map_or(args.end, |i| args.start + i);
+    let numbers: Vec<&str> = text[args.start..tags_start]
+        .split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .collect();
+    let (time, accel) = match numbers[..] {
+        [] => (None, None),
+        [accel] => (None, Some(float(accel)?)),
+        [t1, t2] => (Some((int(t1)?, int(t2)?)), None),
+        [t1, t2, accel] => (Some((int(t1)?, int(t2)?)), Some(float(accel)?)),
+        _ => return None,
+    };
+
+    let mut tags = Vec::new();
+    parse_block(text, tags_start..args.end, errors, &mut |item| {
+        if let Item::Tag(tag) = item.value {
+            tags.push(Spanned {
+                value: tag,
+                span: item.span,
+            });
+        }
+    });
+    Some(Tag::Transform { time, accel, tags })
+}
+
+/// Parse a tag other than `\t` from its trimmed arguments.
+///
+/// Returns `None` if the arguments are invalid.
+fn tag(name: &str, arg: &str) -> Option<Tag> {
+    let list: Vec<&str> = arg.split(',').map(str::trim).collect();
+    // Empty arguments reset the value to the style's.
+    let opt_float = || optional(arg, float);
+    let opt_bool = || optional(arg, |arg| int(arg).map(|value| value != 0));
+    Some(match name {
+        "pos" => match list[..] {
+            [x, y] => Tag::Position {
+                x: float(x)?,
+                y: float(y)?,
+            },
+            _ => return None,
+        },
+        "org" => match list[..] {
+            [x, y] => Tag::Origin {
+                x: float(x)?,
+                y: float(y)?,
+            },
+            _ => return None,
+        },
+        "move" => {
+            let (points, time) = match list[..] {
+                [x1, y1, x2, y2] => ([x1, y1, x2, y2], None),
+                [x1, y1, x2, y2, t1, t2] => ([x1, y1, x2, y2], Some((int(t1)?, int(t2)?))),
+                _ => return None,
+            };
+            let [x1, y1, x2, y2] = points;
+            Tag::Move {
+                from: (float(x1)?, float(y1)?),
+                to: (float(x2)?, float(y2)?),
+                time,
+            }
+        }
+        "fad" => match list[..] {
+            [fade_in, fade_out] => Tag::Fad {
+                fade_in: int(fade_in)?,
+                fade_out: int(fade_out)?,
+            },
+            _ => return None,
+        },
+        "fade" => match list[..] {
+            [a1, a2, a3, t1, t2, t3, t4] => Tag::Fade {
+                alpha: [int(a1)?, int(a2)?, int(a3)?],
+                time: [int(t1)?, int(t2)?, int(t3)?, int(t4)?],
+            },
+            _ => return None,
+        },
+        "clip" | "iclip" => Tag::Clip {
+            inverse: name == "iclip",
+            shape: match list[..] {
+                [x1, y1, x2, y2] => ClipShape::Rect {
+                    x1: float(x1)?,
+                    y1: float(y1)?,
+                    x2: float(x2)?,
+                    y2: float(y2)?,
+                },
+                [commands] if !commands.is_empty() => ClipShape::Drawing {
+                    scale: None,
+                    commands: commands.to_owned(),
+                },
+                [scale, commands] => ClipShape::Drawing {
+                    scale: Some(int(scale)?),
+                    commands: commands.to_owned(),
+                },
+                _ => return None,
+            },
+        },
+        "an" => Tag::Alignment(int(arg).filter(|an| (1..=9).contains(an))?),
+        "a" => Tag::LegacyAlignment(int(arg)?),
+        "c" | "1c" | "2c" | "3c" | "4c" => Tag::Color {
+            index: name.as_bytes()[0]
+                .checked_sub(b'0')
+                .filter(|i| *i <= 4)
+                .unwrap_or(1),
+            color: optional(arg, |arg| hex(arg).map(AssColor::from_abgr))
+                .map(|color| color.map(|color| AssColor { alpha: 0, ..color }))?,
+        },
+        "alpha" | "1a" | "2a" | "3a" | "4a" => Tag::Alpha {
+            index: name.as_bytes()[0].checked_sub(b'0').filter(|i| *i <= 4),
+            alpha: optional(arg, |arg| {
+                hex(arg).and_then(|alpha| u8::try_from(alpha & 0xff).ok())
+            })?,
+        },
+        "fs" if arg.starts_with(['+', '-']) => Tag::FontSizeStep(float(arg)?),
+        "fs" => Tag::FontSize(opt_float()?),
+        "fn" => Tag::FontName((!arg.is_empty()).then(|| arg.to_owned())),
+        "fe" => Tag::FontEncoding(optional(arg, int)?),
+        "fsc" => Tag::FontScale,
+        "fscx" => Tag::FontScaleX(opt_float()?),
+        "fscy" => Tag::FontScaleY(opt_float()?),
+        "fsp" => Tag::FontSpacing(opt_float()?),
+        "b" => Tag::Bold(optional(arg, int)?),
+        "i" => Tag::Italic(opt_bool()?),
+        "u" => Tag::Underline(opt_bool()?),
+        "s" => Tag::StrikeOut(opt_bool()?),
+        "bord" => Tag::Border(opt_float()?),
+        "xbord" => Tag::BorderX(opt_float()?),
+        "ybord" => Tag::BorderY(opt_float()?),
+        "shad" => Tag::Shadow(opt_float()?),
+        "xshad" => Tag::ShadowX(opt_float()?),
+        "yshad" => Tag::ShadowY(opt_float()?),
+        "blur" => Tag::Blur(opt_float()?),
+        "be" => Tag::BlurEdges(opt_float()?),
+        "frx" => Tag::RotateX(opt_float()?),
+        "fry" => Tag::RotateY(opt_float()?),
+        "frz" | "fr" => Tag::RotateZ(opt_float()?),
+        "fax" => Tag::ShearX(opt_float()?),
+        "fay" => Tag::ShearY(opt_float()?),
+        "k" | "K" | "kf" | "ko" => Tag::Karaoke {
+            kind: match name {
+                "k" => KaraokeKind::Fill,
+                "ko" => KaraokeKind::Outline,
+                _ => KaraokeKind::Sweep,
+            },
+            duration: int(arg)?,
+        },
+        "kt" => Tag::KaraokeTime(int(arg)?),
+        "p" => Tag::Drawing(int(arg)?),
+        "pbo" => Tag::DrawingBaselineOffset(float(arg)?),
+        "q" => Tag::WrapStyle(int(arg)?),
+        "r" => Tag::Reset((!arg.is_empty()).then(|| arg.to_owned())),
+        _ => return None,
+    })
+}
+
+/// Parse an argument that may be left out.
+///
+/// Returns `Some(None)` for an empty argument, and `None` if it is invalid.
+fn optional<T>(arg: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<Option<T>> {
+    if arg.is_empty() {
+        Some(None)
+    } else {
+        parse(arg).map(Some)
+    }
+}
+
+/// Parse a finite decimal number.
+fn float(arg: &str) -> Option<f64> {
+    arg.trim()
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+}
+
+/// Parse an integer.
+fn int(arg: &str) -> Option<i32> {
+    arg.trim().parse().ok()
+}
+
+/// Parse an `&H...&` hex value.
+fn hex(arg: &str) -> Option<u32> {
+    let digits = strip_hex_prefix(arg)?;
+    let count = digits.chars().take_while(char::is_ascii_hexdigit).count();
+    let rest = &digits[count..];
+    (count > 0 && (rest.is_empty() || rest == "&")).then(|| parse_hex_prefix(digits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tags of some event text, without spans.
+    fn tags(text: &str) -> Vec<Tag> {
+        parse(text).tags().map(|(tag, _)| tag.clone()).collect()
+    }
+
+    #[test]
+    fn text_and_spans() {
+        let text = r"{\pos(320,40)\c&H00FFFF&}Hello\N{comment}world";
+        let parsed = parse(text);
+        assert!(parsed.errors.is_empty());
+        let items: Vec<_> = parsed
+            .items
+            .iter()
+            .map(|item| (&text[item.span.clone()], item.value.clone()))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (
+                    r"\pos(320,40)",
+                    Item::Tag(Tag::Position { x: 320.0, y: 40.0 })
+                ),
+                (
+                    r"\c&H00FFFF&",
+                    Item::Tag(Tag::Color {
+                        index: 1,
+                        color: Some(AssColor::rgb(255, 255, 0)),
+                    })
+                ),
+                (r"Hello\N", Item::Text(r"Hello\N".to_owned())),
+                ("comment", Item::Comment("comment".to_owned())),
+                ("world", Item::Text("world".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn longest_name_wins() {
+        assert_eq!(
+            tags(r"{\fscx120\fsc\fs20\fsp2\frz-5\fr10}"),
+            [
+                Tag::FontScaleX(Some(120.0)),
+                Tag::FontScale,
+                Tag::FontSize(Some(20.0)),
+                Tag::FontSpacing(Some(2.0)),
+                Tag::RotateZ(Some(-5.0)),
+                Tag::RotateZ(Some(10.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn resets() {
+        assert_eq!(
+            tags(r"{\fs\bord\c\r\rSign}"),
+            [
+                Tag::FontSize(None),
+                Tag::Border(None),
+                Tag::Color {
+                    index: 1,
+                    color: None,
+                },
+                Tag::Reset(None),
+                Tag::Reset(Some("Sign".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn font_size_steps() {
+        assert_eq!(
+            tags(r"{\fs+2\fs-1.5\fs2}"),
+            [
+                Tag::FontSizeStep(2.0),
+                Tag::FontSizeStep(-1.5),
+                Tag::FontSize(Some(2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn transform() {
+        let text = r"{\t(0,500,2,\fs40\1c&HFF&)}";
+        let [Tag::Transform { time, accel, tags }] = &self::tags(text)[..] else {
+            panic!("not a single transform");
+        };
+        assert_eq!(*time, Some((0, 500)));
+        assert_eq!(*accel, Some(2.0));
+        let animated: Vec<_> = tags.iter().map(|tag| &text[tag.span.clone()]).collect();
+        assert_eq!(animated, [r"\fs40", r"\1c&HFF&"]);
+    }
+
+    #[test]
+    fn clips() {
+        assert_eq!(
+            tags(r"{\clip(1,2,3,4)\iclip(2,m 0 0 l 1 1)}"),
+            [
+                Tag::Clip {
+                    inverse: false,
+                    shape: ClipShape::Rect {
+                        x1: 1.0,
+                        y1: 2.0,
+                        x2: 3.0,
+                        y2: 4.0,
+                    },
+                },
+                Tag::Clip {
+                    inverse: true,
+                    shape: ClipShape::Drawing {
+                        scale: Some(2),
+                        commands: "m 0 0 l 1 1".to_owned(),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_braces() {
+        let parsed = parse(r"a \{not a block\} b");
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.items.len(), 1);
+        assert_eq!(find_block(r"\{x{", 0), Some(3));
+    }
+
+    #[test]
+    fn errors() {
+        let text = r"{\bogus\pos(1)\an0\fs20}x{\b1";
+        let parsed = parse(text);
+        let errors: Vec<_> = parsed
+            .errors
+            .iter()
+            .map(|error| (error.kind.clone(), &text[error.span.clone()]))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (TagErrorKind::Unknown("bogus".to_owned()), r"\bogus"),
+                (TagErrorKind::InvalidArguments("pos".to_owned()), r"\pos(1)"),
+                (TagErrorKind::InvalidArguments("an".to_owned()), r"\an0"),
+                (TagErrorKind::UnclosedBlock, r"{\b1"),
+            ]
+        );
+        // Valid tags are kept, and an unclosed block is text.
+        assert_eq!(
+            parsed.items.last().map(|item| &item.value),
+            Some(&Item::Text(r"{\b1".to_owned()))
+        );
+        assert_eq!(parsed.tags().count(), 1);
+    }
+
+    #[test]
+    fn missing_parenthesis() {
+        assert_eq!(tags(r"{\pos(1,2}"), [Tag::Position { x: 1.0, y: 2.0 }]);
+    }
+}
@@ -0,0 +1,407 @@
+//! Subtitle timestamps
+//!
+//! [`AssTime`] is a timestamp in milliseconds, the unit Libass uses for event times, with
+//! conversions to and from [`Duration`] and the text forms of ASS, SRT and WebVTT. ASS scripts
+//! only store centiseconds, so times are rounded to the nearest centisecond when written as ASS,
+//! the same as Aegisub.
+//!
+//! [`Track::shift`](crate::Track::shift) and [`Track::retime`](crate::Track::retime) move or
+//! rescale the events of a whole track, such as to fix sync or convert between frame rates.
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+};
+
+use time::Duration;
+
+use crate::{
+    error::{Error, Result},
+    script::parse_int,
+};
+
+mod retime;
+
+pub use retime::RetimeOptions;
+
+/// A subtitle timestamp or time offset in milliseconds.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AssTime(i64);
+
+impl AssTime {
+    /// The start of the video.
+    pub const ZERO: Self = Self(0);
+
+    /// Construct from milliseconds, as Libass stores event times.
+    pub const fn from_millis(millis: i64) -> Self {
+        Self(millis)
+    }
+
+    /// The time in milliseconds, as Libass stores event times.
+    pub const fn as_millis(self) -> i64 {
+        self.0
+    }
+
+    /// Convert from a `Duration`, rounding to the nearest millisecond.
+    pub fn from_duration(duration: Duration) -> Self {
+        let micros = duration.whole_microseconds();
+        let millis = (micros + 500).div_euclid(1000);
+        Self(i64::try_from(millis).unwrap_or(if micros < 0 { i64::MIN } else { i64::MAX }))
+    }
+
+    /// Convert to a `Duration`.
+    pub const fn to_duration(self) -> Duration {
+        Duration::milliseconds(self.0)
+    }
+
+    /// Round to the nearest centisecond, with halves rounded up like Aegisub.
+    pub const fn round_to_centis(self) -> Self {
+        Self(self.0.saturating_add(5).div_euclid(10).saturating_mul(10))
+    }
+
+    /// Parse an ASS `H:MM:SS.cc` timestamp the way Libass does.
+    ///
+    /// This never fails, as each field is parsed like `atoi` and missing fields are 0. Like
+    /// VSFilter, the digits after the `.` are a number of centiseconds, so `0:00:01.5` is 1.05
+    /// seconds.
+    pub fn parse_ass(value: &str) -> Self {
+        let mut parts = value.splitn(3, ':');
+        let hours = parts.next().map_or(0, parse_int);
+        let minutes = parts.next().map_or(0, parse_int);
+        let (seconds, centis) = parts
+            .next()
+            .map_or((0, 0), |rest| match rest.split_once('.') {
+                Some((seconds, centis)) => (parse_int(seconds), parse_int(centis)),
+                None => (parse_int(rest), 0),
+            });
+        Self(
+            i64::from(hours) * 3_600_000
+                + i64::from(minutes) * 60_000
+                + i64::from(seconds) * 1000
+                + i64::from(centis) * 10,
+        )
+    }
+
+    /// Format as an ASS `H:MM:SS.cc` timestamp, rounded to the nearest centisecond.
+    ///
+    /// Negative times are written as 0.
+    pub fn to_ass_string(self) -> String {
+        let centis = self.round_to_centis().0.max(0) / 10;
+        format!(
+            "{}:{:02}:{:02}.{:02}",
+            centis / 360_000,
+            centis / 6000 % 60,
+            centis / 100 % 60,
+            centis % 100
+        )
+    }
+
+    /// Parse an SRT `HH:MM:SS,mmm` timestamp.
+    ///
+    /// Also accepts a `.` before the fraction, no hours, and any number of fraction digits, which
+    /// are truncated to milliseconds.
+    pub fn parse_srt(value: &str) -> Option<Self> {
+        parse_clock(value)
+    }
+
+    /// Format as an SRT `HH:MM:SS,mmm` timestamp. Negative times are written as 0.
+    pub fn to_srt_string(self) -> String {
+        self.clock_string(',')
+    }
+
+    /// Parse a WebVTT `[HH:]MM:SS.mmm` timestamp.
+    ///
+    /// As lenient as [`AssTime::parse_srt`], which also accepts a `,` before the fraction.
+    pub fn parse_vtt(value: &str) -> Option<Self> {
+        parse_clock(value)
+    }
+
+    /// Format as a WebVTT `HH:MM:SS.mmm` timestamp. Negative times are written as 0.
+    pub fn to_vtt_string(self) -> String {
+        self.clock_string('.')
+    }
+
+    /// The time for an event to start or end on a frame, at a constant frame rate.
+    ///
+    /// This is halfway between the start of the frame and the frame before it, like Aegisub, so an
+    /// event starting at it is first shown on `frame`, and an event ending at it is last shown on
+    /// the frame before, even after rounding to centiseconds. Frames before the first are 0.
+    ///
+    /// Returns `Error::InvalidFrameRate` if `fps` isn't positive and finite.
+    pub fn from_frame(frame: i64, fps: f64) -> Result<Self> {
+        check_fps(fps)?;
+        Ok(Self::from_frame_unchecked(frame, fps))
+    }
+
+    /// Parse a frame number, converting it with [`AssTime::from_frame`].
+    ///
+    /// Returns `None` if the value isn't a whole number, or `fps` isn't positive and finite.
+    pub fn parse_frame(value: &str, fps: f64) -> Option<Self> {
+        let frame = value.trim().parse().ok()?;
+        Self::from_frame(frame, fps).ok()
+    }
+
+    /// The first frame shown at or after this time, at a constant frame rate.
+    ///
+    /// The inverse of [`AssTime::from_frame`].
+    ///
+    /// Returns `Error::InvalidFrameRate` if `fps` isn't positive and finite.
+    pub fn to_frame(self, fps: f64) -> Result<i64> {
+        check_fps(fps)?;
+        Ok(self.to_frame_unchecked(fps))
+    }
+
+    /// [`AssTime::from_frame`] with a frame rate that has already been checked.
+    fn from_frame_unchecked(frame: i64, fps: f64) -> Self {
+        if frame <= 0 {
+            return Self::ZERO;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        // Frame numbers are far below 2^52.
+        let midpoint = (frame as f64 - 0.5) * 1000.0 / fps;
+        Self(round_to_i64(midpoint))
+    }
+
+    /// [`AssTime::to_frame`] with a frame rate that has already been checked.
+    fn to_frame_unchecked(self, fps: f64) -> i64 {
+        #[allow(clippy::cast_precision_loss)]
+        // Times are far below 2^52 milliseconds.
+        let frames = self.0 as f64 * fps / 1000.0;
+        // Allows for the error in converting frame rates like 24000/1001 to floats.
+        round_to_i64((frames - 1e-6).ceil())
+    }
+
+    /// Format as `HH:MM:SS` and milliseconds after `separator`.
+    fn clock_string(self, separator: char) -> String {
+        let millis = self.0.max(0);
+        format!(
+            "{:02}:{:02}:{:02}{separator}{:03}",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
+
+/// Parse a `[hh:]mm:ss[.,]fff` timestamp, with strict digits.
+fn parse_clock(value: &str) -> Option<AssTime> {
+    let value = value.trim();
+    let (clock, fraction) = match value.split_once(['.', ',']) {
+        Some((clock, fraction)) => (clock, fraction),
+        None => (value, ""),
+    };
+    let mut fields = clock.rsplit(':');
+    let number = |field: &str| -> Option<i64> {
+        let field = field.trim();
+        if field.is_empty() || !field.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        field.parse().ok()
+    };
+    let seconds = number(fields.next()?)?;
+    let minutes = number(fields.next()?)?;
+    let hours = fields.next().map_or(Some(0), number)?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let millis = if fraction.is_empty() {
+        0
+    } else {
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        // Scale to milliseconds, truncating extra precision.
+        let digits: String = fraction.chars().chain("000".chars()).take(3).collect();
+        digits.parse().ok()?
+    };
+    let total = hours
+        .checked_mul(3_600_000)?
+        .checked_add(minutes.checked_mul(60_000)?)?
+        .checked_add(seconds.checked_mul(1000)?)?
+        .checked_add(millis)?;
+    Some(AssTime(total))
+}
+
+/// Check a frame rate is positive and finite.
+fn check_fps(fps: f64) -> Result<()> {
+    if fps.is_finite() && fps > 0.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidFrameRate(fps))
+    }
+}
+
+/// Round a float to an integer, saturating at the limits.
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) const fn round_to_i64(value: f64) -> i64 {
+    // Float to int casts saturate.
+    value.round() as i64
+}
+
+impl fmt::Display for AssTime {
+    /// Formats as an ASS timestamp, see [`AssTime::to_ass_string`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_ass_string())
+    }
+}
+
+impl From<Duration> for AssTime {
+    /// See [`AssTime::from_duration`].
+    fn from(duration: Duration) -> Self {
+        Self::from_duration(duration)
+    }
+}
+
+impl From<AssTime> for Duration {
+    fn from(time: AssTime) -> Self {
+        time.to_duration()
+    }
+}
+
+impl Add for AssTime {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for AssTime {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl AddAssign for AssTime {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for AssTime {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for AssTime {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(self.0.saturating_neg())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ass_round_trip() {
+        for text in ["0:00:00.00", "0:01:02.03", "1:23:45.67", "123:00:00.99"] {
+            assert_eq!(AssTime::parse_ass(text).to_ass_string(), text);
+        }
+        assert_eq!(AssTime::parse_ass("0:00:01.5").as_millis(), 1050);
+        assert_eq!(AssTime::parse_ass("1:2").as_millis(), 3_720_000);
+        assert_eq!(AssTime::parse_ass("junk").as_millis(), 0);
+        assert_eq!(AssTime::from_millis(-10).to_ass_string(), "0:00:00.00");
+    }
+
+    #[test]
+    fn centiseconds() {
+        let round = |millis| AssTime::from_millis(millis).round_to_centis().as_millis();
+        assert_eq!(round(1234), 1230);
+        assert_eq!(round(1235), 1240);
+        assert_eq!(round(-5), 0);
+        assert_eq!(round(-6), -10);
+        assert_eq!(round(i64::MIN), i64::MIN);
+        assert_eq!(round(i64::MAX), i64::MAX - 7);
+        assert_eq!(AssTime::from_millis(i64::MIN).to_ass_string(), "0:00:00.00");
+        assert_eq!(AssTime::from_millis(59_995).to_ass_string(), "0:01:00.00");
+    }
+
+    #[test]
+    fn clock_round_trip() {
+        let time = AssTime::from_millis(3_723_004);
+        assert_eq!(time.to_srt_string(), "01:02:03,004");
+        assert_eq!(time.to_vtt_string(), "01:02:03.004");
+        assert_eq!(AssTime::parse_srt(&time.to_srt_string()), Some(time));
+        assert_eq!(AssTime::parse_vtt(&time.to_vtt_string()), Some(time));
+        assert_eq!(AssTime::from_millis(-1).to_srt_string(), "00:00:00,000");
+    }
+
+    #[test]
+    fn clock_parsing() {
+        let parse = |text| AssTime::parse_vtt(text).map(AssTime::as_millis);
+        assert_eq!(parse("02:03.5"), Some(123_500));
+        assert_eq!(parse(" 00:00:01,23456 "), Some(1234));
+        assert_eq!(parse("100:00:00.000"), Some(360_000_000));
+        assert_eq!(parse("00:01"), Some(1000));
+        for invalid in [
+            "",
+            "1",
+            "00:00:0x.000",
+            "00:00:01.-1",
+            "0:0:0:0",
+            "-1:00",
+            "00::01",
+        ] {
+            assert_eq!(parse(invalid), None, "{invalid}");
+        }
+        assert_eq!(parse("99999999999999999:00:00"), None);
+    }
+
+    #[test]
+    fn frames() {
+        let fps = 24000.0 / 1001.0;
+        assert_eq!(AssTime::from_frame(0, fps).unwrap(), AssTime::ZERO);
+        assert_eq!(AssTime::from_frame(-3, fps).unwrap(), AssTime::ZERO);
+        assert_eq!(AssTime::from_frame(1, 25.0).unwrap().as_millis(), 20);
+        for frame in 0..1000 {
+            let time = AssTime::from_frame(frame, fps).unwrap();
+            assert_eq!(time.to_frame(fps).unwrap(), frame);
+            // Still on the frame after rounding for an ASS script.
+            assert_eq!(time.round_to_centis().to_frame(fps).unwrap(), frame);
+        }
+        assert_eq!(
+            AssTime::parse_frame(" 25 ", 25.0),
+            Some(AssTime::from_millis(980))
+        );
+        assert_eq!(AssTime::parse_frame("1.5", 25.0), None);
+
+        for fps in [0.0, -25.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                AssTime::from_frame(1, fps),
+                Err(Error::InvalidFrameRate(_))
+            ));
+            assert!(AssTime::ZERO.to_frame(fps).is_err());
+            assert_eq!(AssTime::parse_frame("1", fps), None);
+        }
+    }
+
+    #[test]
+    fn arithmetic_and_durations() {
+        let time = AssTime::from_millis(1500);
+        assert_eq!(time + AssTime::from_millis(500), AssTime::from_millis(2000));
+        assert_eq!(
+            time - AssTime::from_millis(2000),
+            AssTime::from_millis(-500)
+        );
+        assert_eq!((-AssTime::from_millis(i64::MIN)).as_millis(), i64::MAX);
+        assert_eq!(
+            (AssTime::from_millis(i64::MAX) + time).as_millis(),
+            i64::MAX
+        );
+
+        assert_eq!(AssTime::from(Duration::microseconds(1499)).as_millis(), 1);
+        assert_eq!(AssTime::from(Duration::microseconds(1500)).as_millis(), 2);
+        assert_eq!(AssTime::from(Duration::microseconds(-1500)).as_millis(), -1);
+        assert_eq!(AssTime::from(Duration::MAX).as_millis(), i64::MAX);
+        assert_eq!(AssTime::from(Duration::MIN).as_millis(), i64::MIN);
+        assert_eq!(Duration::from(time), Duration::milliseconds(1500));
+        assert_eq!(time.to_string(), "0:00:01.50");
+    }
+}
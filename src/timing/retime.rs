@@ -0,0 +1,378 @@
+//! Shifting and rescaling event times
+//!
+use std::{
+    borrow::Cow,
+    ffi::{CStr, CString},
+    ops::Range,
+};
+
+use super::{check_fps, AssTime};
+use crate::{
+    error::Result,
+    script::ScriptDocument,
+    tags::{replace_spans, Item, Tag},
+    track::{replace_c_str, Track},
+};
+
+/// Which events to retime, and whether to rescale the times in their override tags.
+///
+/// The default changes every event and leaves tags alone.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct RetimeOptions {
+    /// Only change events that start in this range, before they are changed.
+    pub range: Option<Range<AssTime>>,
+    /// Only change events with this style.
+    pub style: Option<String>,
+    /// When converting frame rates, also rescale the times of `\t`, `\move`, `\fad`, `\fade`, and
+    /// karaoke tags.
+    ///
+    /// Tag times are relative to the start of the event, so shifting never changes them.
+    pub scale_tags: bool,
+}
+
+impl RetimeOptions {
+    /// Only change events that start in a range.
+    pub const fn range(mut self, range: Range<AssTime>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Only change events with a style.
+    pub fn style(mut self, name: impl Into<String>) -> Self {
+        self.style = Some(name.into());
+        self
+    }
+
+    /// Set whether tag times are rescaled when converting frame rates.
+    pub const fn scale_tags(mut self, scale_tags: bool) -> Self {
+        self.scale_tags = scale_tags;
+        self
+    }
+
+    /// Whether an event is changed. Like Libass, a leading `*` of a style name is ignored.
+    fn matches(&self, start: AssTime, style: &str) -> bool {
+        let unstar = |name: &str| name.strip_prefix('*').unwrap_or(name).to_owned();
+        self.range
+            .as_ref()
+            .is_none_or(|range| range.contains(&start))
+            && self
+                .style
+                .as_deref()
+                .is_none_or(|name| unstar(name) == unstar(style))
+    }
+}
+
+/// How event times are changed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Change {
+    /// Add an offset.
+    Shift(AssTime),
+    /// Move from frames at one rate to the same frames at another.
+    FrameRate {
+        #[allow(clippy::missing_docs_in_private_items)]
+        from: f64,
+        #[allow(clippy::missing_docs_in_private_items)]
+        to: f64,
+    },
+}
+
+impl Change {
+    /// A frame rate conversion, checking both rates.
+    fn frame_rate(from: f64, to: f64) -> Result<Self> {
+        check_fps(from)?;
+        check_fps(to)?;
+        Ok(Self::FrameRate { from, to })
+    }
+
+    /// The new time of an event start or end.
+    fn time(self, time: AssTime) -> AssTime {
+        match self {
+            Self::Shift(offset) => (time + offset).max(AssTime::ZERO),
+            Self::FrameRate { from, to } => {
+                AssTime::from_frame_unchecked(time.to_frame_unchecked(from), to)
+            }
+        }
+    }
+
+    /// How much durations are scaled by, if tags should be rescaled.
+    fn tag_factor(self, options: &RetimeOptions) -> Option<f64> {
+        match self {
+            Self::FrameRate { from, to } if options.scale_tags => Some(from / to),
+            _ => None,
+        }
+    }
+}
+
+impl Track {
+    /// Move events by an offset, such as to fix subtitles that are out of sync.
+    ///
+    /// Times that would be negative become 0, so events moved before the start of the video are
+    /// shortened, or hidden if they end before it.
+    pub fn shift(&mut self, offset: AssTime, options: &RetimeOptions) {
+        // Only times change, which can't fail.
+        let _ = retime_track(self, Change::Shift(offset), options);
+    }
+
+    /// Convert event times from one frame rate to another, such as speeding up 23.976 fps
+    /// subtitles to 25 fps for PAL video.
+    ///
+    /// Each event keeps the frames it is shown on, so it starts and ends on the same frames of
+    /// the converted video. See [`AssTime::from_frame`].
+    ///
+    /// Returns `Error::InvalidFrameRate` if either rate isn't positive, or `Error::Alloc` if new
+    /// event text could not be allocated.
+    pub fn retime(&mut self, from_fps: f64, to_fps: f64, options: &RetimeOptions) -> Result<()> {
+        retime_track(self, Change::frame_rate(from_fps, to_fps)?, options)
+    }
+}
+
+impl ScriptDocument {
+    /// Move events by an offset, the same as [`Track::shift`].
+    pub fn shift(&mut self, offset: AssTime, options: &RetimeOptions) {
+        retime_document(self, Change::Shift(offset), options);
+    }
+
+    /// Convert event times from one frame rate to another, the same as [`Track::retime`].
+    ///
+    /// Returns `Error::InvalidFrameRate` if either rate isn't positive.
+    pub fn retime(&mut self, from_fps: f64, to_fps: f64, options: &RetimeOptions) -> Result<()> {
+        retime_document(self, Change::frame_rate(from_fps, to_fps)?, options);
+        Ok(())
+    }
+}
+
+/// Change the times of the events of a document.
+fn retime_document(doc: &mut ScriptDocument, change: Change, options: &RetimeOptions) {
+    let factor = change.tag_factor(options);
+    for event in &mut doc.events {
+        if !options.matches(AssTime::from(event.start), &event.style) {
+            continue;
+        }
+        event.start = change.time(AssTime::from(event.start)).to_duration();
+        event.end = change.time(AssTime::from(event.end)).to_duration();
+        if let Some(text) = factor.and_then(|factor| scale_tags(&event.text, factor)) {
+            event.text = text;
+        }
+    }
+}
+
+/// Change the times of the events of a track.
+fn retime_track(track: &mut Track, change: Change, options: &RetimeOptions) -> Result<()> {
+    let factor = change.tag_factor(options);
+    // Safety:
+    // The track is valid and borrowed mutably, so nothing else can access its events. Its arrays
+    // hold `n_styles` and `n_events` initialised elements, and strings are null or valid C
+    // strings allocated with the C allocator.
+    unsafe {
+        let raw = track.track.as_ptr();
+        let n_styles = usize::try_from((*raw).n_styles).unwrap_or(0);
+        let n_events = usize::try_from((*raw).n_events).unwrap_or(0);
+        if (*raw).events.is_null() {
+            return Ok(());
+        }
+        let styles = (*raw).styles;
+        let events = std::slice::from_raw_parts_mut((*raw).events, n_events);
+
+        for event in events {
+            let style = usize::try_from(event.Style)
+                .ok()
+                .filter(|sid| !styles.is_null() && *sid < n_styles)
+                .map(|sid| (*styles.add(sid)).Name)
+                .filter(|name| !name.is_null())
+                .map_or(Cow::Borrowed(""), |name| {
+                    CStr::from_ptr(name).to_string_lossy()
+                });
+            let start = AssTime::from_millis(event.Start);
+            if !options.matches(start, &style) {
+                continue;
+            }
+            let end = AssTime::from_millis(event.Start.saturating_add(event.Duration));
+            let (start, end) = (change.time(start), change.time(end));
+            event.Start = start.as_millis();
+            event.Duration = (end - start).as_millis();
+
+            let Some(factor) = factor.filter(|_| !event.Text.is_null()) else {
+                continue;
+            };
+            // Tags are ASCII, so text that isn't UTF-8 is left alone rather than mangled.
+            let Ok(text) = CStr::from_ptr(event.Text).to_str() else {
+                continue;
+            };
+            let scaled = scale_tags(text, factor).and_then(|text| CString::new(text).ok());
+            if let Some(scaled) = scaled {
+                replace_c_str(&mut event.Text, scaled.as_ptr())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Scale the times of the tags in event text, or `None` if it has none.
+///
+/// Only the changed tags are rewritten, so the rest of the text is kept as written.
+fn scale_tags(text: &str, factor: f64) -> Option<String> {
+    let scale = |time: i32| scale_time(time, factor);
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    for item in crate::tags::parse(text).items {
+        let Item::Tag(tag) = item.value else {
+            continue;
+        };
+        let span = item.span;
+        let replacement = match tag {
+            Tag::Move {
+                from,
+                to,
+                time: Some((t1, t2)),
+            } => Tag::Move {
+                from,
+                to,
+                time: Some((scale(t1), scale(t2))),
+            }
+            .to_string(),
+            Tag::Fad { fade_in, fade_out } => Tag::Fad {
+                fade_in: scale(fade_in),
+                fade_out: scale(fade_out),
+            }
+            .to_string(),
+            Tag::Fade { alpha, time } => Tag::Fade {
+                alpha,
+                time: time.map(scale),
+            }
+            .to_string(),
+            Tag::Karaoke { duration, .. } => {
+                // Keep the name as written, as `\K` and `\kf` are the same tag.
+                let name_len = if text[span.start + 2..].starts_with(['f', 'o']) {
+                    3
+                } else {
+                    2
+                };
+                format!(
+                    "{}{}",
+                    &text[span.start..span.start + name_len],
+                    scale(duration)
+                )
+            }
+            Tag::KaraokeTime(time) => Tag::KaraokeTime(scale(time)).to_string(),
+            Tag::Transform {
+                time: Some((t1, t2)),
+                accel,
+                ..
+            } => {
+                // Only the times are replaced, keeping animated tags that couldn't be parsed.
+                let Some(tags_start) = text[span.start + 1..span.end]
+                    .find('\\')
+                    .map(|i| span.start + 1 + i)
+                else {
+                    continue;
+                };
+                let mut prefix = format!("\\t({},{},", scale(t1), scale(t2));
+                if let Some(accel) = accel {
+                    prefix.push_str(&format!("{accel},"));
+                }
+                edits.push((span.start..tags_start, prefix));
+                continue;
+            }
+            _ => continue,
+        };
+        edits.push((span, replacement));
+    }
+
+    (!edits.is_empty()).then(|| replace_spans(text, edits))
+}
+
+/// Scale a tag time, rounding to the nearest unit.
+#[allow(clippy::cast_possible_truncation)]
+fn scale_time(time: i32, factor: f64) -> i32 {
+    // Float to int casts saturate.
+    (f64::from(time) * factor).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+    use crate::{error::Error, script::Event};
+
+    /// A document with one event per (start, end, style, text).
+    fn document(events: &[(i64, i64, &str, &str)]) -> ScriptDocument {
+        let mut doc = ScriptDocument::new();
+        for &(start, end, style, text) in events {
+            doc.events.push(Event {
+                start: Duration::milliseconds(start),
+                end: Duration::milliseconds(end),
+                style: style.to_owned(),
+                text: text.to_owned(),
+                ..Event::default()
+            });
+        }
+        doc
+    }
+
+    /// The (start, end) of each event in milliseconds.
+    fn times(doc: &ScriptDocument) -> Vec<(i128, i128)> {
+        doc.events
+            .iter()
+            .map(|event| {
+                (
+                    event.start.whole_milliseconds(),
+                    event.end.whole_milliseconds(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shift() {
+        let mut doc = document(&[
+            (1000, 2000, "Default", ""),
+            (3000, 4000, "Sign", ""),
+            (5000, 6000, "*Sign", ""),
+        ]);
+        doc.shift(AssTime::from_millis(-1500), &RetimeOptions::default());
+        assert_eq!(times(&doc), [(0, 500), (1500, 2500), (3500, 4500)]);
+
+        let options = RetimeOptions::default()
+            .style("Sign")
+            .range(AssTime::from_millis(3000)..AssTime::from_millis(4000));
+        doc.shift(AssTime::from_millis(100), &options);
+        assert_eq!(times(&doc), [(0, 500), (1500, 2500), (3600, 4600)]);
+    }
+
+    #[test]
+    fn retime() {
+        let mut doc = document(&[(1000, 2000, "Default", r"{\fad(100,200)\k50}a")]);
+        let options = RetimeOptions::default().scale_tags(true);
+        doc.retime(24.0, 25.0, &options).unwrap();
+        // Frames 24 to 48 at 25 fps.
+        assert_eq!(times(&doc), [(940, 1900)]);
+        assert_eq!(doc.events[0].text, r"{\fad(96,192)\k48}a");
+
+        assert!(matches!(
+            doc.retime(0.0, 25.0, &options),
+            Err(Error::InvalidFrameRate(_))
+        ));
+        assert!(doc.retime(25.0, f64::NAN, &options).is_err());
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(scale_tags("no tags", 2.0), None);
+        assert_eq!(scale_tags(r"{\b1\pos(1,2)}a", 2.0), None);
+        assert_eq!(
+            scale_tags(
+                r"{\move(0,0,10,10,100,200)\fade(255,0,255,0,10,20,30)\kf5\K6\ko7\kt8}",
+                2.0
+            )
+            .unwrap(),
+            r"{\move(0,0,10,10,200,400)\fade(255,0,255,0,20,40,60)\kf10\K12\ko14\kt16}"
+        );
+        // Animated tags are kept as written.
+        assert_eq!(
+            scale_tags(r"{\t(10,20,0.5,\frz10\xyz)}", 2.0).unwrap(),
+            r"{\t(20,40,0.5,\frz10\xyz)}"
+        );
+        assert_eq!(scale_time(i32::MAX, 2.0), i32::MAX);
+    }
+}
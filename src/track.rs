@@ -1,58 +1,186 @@
 //! Libass track handle methods
 //!
-use std::{marker::PhantomData, ptr::NonNull};
+use std::{
+    cell::Cell,
+    ffi::{c_char, c_int},
+    marker::PhantomData,
+    ptr::{self, NonNull},
+};
 
-use thiserror::Error;
-
-use crate::library::Library;
+use crate::{
+    error::{Error, Result},
+    library::Library,
+};
 
 /// Handle to a Libass track object.
+///
+/// The track owns a handle to the `Library` it was created from, so it has no lifetime and can be
+/// stored alongside a `Renderer` in a long lived struct.
+///
+/// # Threading
+///
+/// A track is `Send`, so it can be parsed on one thread and rendered on another. It is not `Sync`
+/// because Libass writes to the track while rendering it: missing headers are filled in lazily
+/// and each event keeps collision detection state for the renderer that last drew it. Two
+/// renderers drawing the same track at once would race on that state.
 #[derive(PartialEq, Debug)]
 #[allow(clippy::missing_docs_in_private_items)]
-pub struct Track<'lib> {
+pub struct Track {
     pub(crate) track: NonNull<libass_sys::ASS_Track>,
     pub(crate) phantom: PhantomData<libass_sys::ASS_Track>,
-    pub(crate) lib: &'lib Library,
+    pub(crate) lib: Library,
+    /// Features explicitly set with `Track::set_feature`, indexed by `Feature`.
+    ///
+    /// Libass doesn't provide a getter, so this is needed to replay them on copies.
+    pub(crate) features: Cell<[Option<bool>; FEATURE_COUNT]>,
 }
 
-impl Track<'_> {
+// Safety:
+// Libass tracks have no thread affinity. Any access to the shared library goes through the
+// library lock.
+unsafe impl Send for Track {}
+
+impl Track {
+    /// The library this track was created from.
+    pub const fn library(&self) -> &Library {
+        &self.lib
+    }
+
     /// Explicilty processes styles that have been overridden.
     pub fn force_process_styles(&self) {
+        // Reads the style overrides from the library.
+        let _lock = self.lib.read();
         unsafe { libass_sys::ass_process_force_style(self.track.as_ptr()) }
     }
 
     /// Enable or disable features for the track.
-    /// Will return as Some if successful. Will return None if the status
-    /// of the feature is unknown.
-    pub fn set_feature(&self, feat: Feature, enable: bool) -> Option<()> {
+    ///
+    /// Returns `Error::UnsupportedFeature` if this build of Libass doesn't know the feature.
+    pub fn set_feature(&self, feat: Feature, enable: bool) -> Result<()> {
         let code = unsafe {
             libass_sys::ass_track_set_feature(self.track.as_ptr(), feat as _, enable.into())
         };
 
         match code {
-            0 => Some(()),
-            _ => None,
+            0 => {
+                let mut features = self.features.get();
+                features[feat as usize] = Some(enable);
+                self.features.set(features);
+                Ok(())
+            }
+            _ => Err(Error::UnsupportedFeature(feat)),
         }
     }
 
+    /// Deep copy the track in to a new, independent track of the same library.
+    ///
+    /// The copy has an identical header, styles, and events, so it can be used as a snapshot
+    /// before editing the original. Features set with `Track::set_feature` are replayed on the
+    /// copy. Libass' internal parser state, such as the ReadOrder values used to drop duplicate
+    /// Matroska chunks, is not copied.
+    ///
+    /// Returns `Error::Alloc` if any allocation fails.
+    pub fn try_clone(&self) -> Result<Track> {
+        let copy = self.lib.new_track()?;
+        let src = self.track.as_ptr();
+        let dst = copy.track.as_ptr();
+
+        // Safety:
+        // Both tracks are valid and the source is only read. Libass frees every string in a track
+        // with `free`, so they are all duplicated with the C allocator. Style and event slots are
+        // allocated by Libass, and the arrays are re-read after every allocation since they may
+        // be reallocated. If anything fails the partially filled copy is still a valid track and
+        // is freed when dropped.
+        unsafe {
+            // Libass may create a default style for new tracks.
+            for sid in 0..(*dst).n_styles {
+                libass_sys::ass_free_style(dst, sid);
+            }
+            (*dst).n_styles = 0;
+
+            replace_c_str(&mut (*dst).style_format, (*src).style_format)?;
+            replace_c_str(&mut (*dst).event_format, (*src).event_format)?;
+            replace_c_str(&mut (*dst).Language, (*src).Language)?;
+            replace_c_str(&mut (*dst).name, (*src).name)?;
+            (*dst).track_type = (*src).track_type;
+            (*dst).PlayResX = (*src).PlayResX;
+            (*dst).PlayResY = (*src).PlayResY;
+            (*dst).Timer = (*src).Timer;
+            (*dst).WrapStyle = (*src).WrapStyle;
+            (*dst).ScaledBorderAndShadow = (*src).ScaledBorderAndShadow;
+            (*dst).Kerning = (*src).Kerning;
+            (*dst).YCbCrMatrix = (*src).YCbCrMatrix;
+            (*dst).default_style = (*src).default_style;
+            (*dst).LayoutResX = (*src).LayoutResX;
+            (*dst).LayoutResY = (*src).LayoutResY;
+
+            for sid in 0..count((*src).n_styles) {
+                let from = &*(*src).styles.add(sid);
+                let new_sid = usize::try_from(libass_sys::ass_alloc_style(dst)).map_err(|_| {
+                    Error::Alloc {
+                        function: "ass_alloc_style",
+                    }
+                })?;
+                let to = &mut *(*dst).styles.add(new_sid);
+                *to = *from;
+                to.Name = ptr::null_mut();
+                to.FontName = ptr::null_mut();
+                replace_c_str(&mut to.Name, from.Name)?;
+                replace_c_str(&mut to.FontName, from.FontName)?;
+            }
+
+            for eid in 0..count((*src).n_events) {
+                let from = &*(*src).events.add(eid);
+                let new_eid = usize::try_from(libass_sys::ass_alloc_event(dst)).map_err(|_| {
+                    Error::Alloc {
+                        function: "ass_alloc_event",
+                    }
+                })?;
+                let to = &mut *(*dst).events.add(new_eid);
+                *to = *from;
+                to.Name = ptr::null_mut();
+                to.Effect = ptr::null_mut();
+                to.Text = ptr::null_mut();
+                to.render_priv = ptr::null_mut();
+                replace_c_str(&mut to.Name, from.Name)?;
+                replace_c_str(&mut to.Effect, from.Effect)?;
+                replace_c_str(&mut to.Text, from.Text)?;
+            }
+        }
+
+        for (feat, enable) in Feature::ALL.into_iter().zip(self.features.get()) {
+            if let Some(enable) = enable {
+                // Safety: Same as `Track::set_feature`.
+                unsafe { libass_sys::ass_track_set_feature(dst, feat as _, enable.into()) };
+            }
+        }
+        copy.features.set(self.features.get());
+
+        Ok(copy)
+    }
+
     /// Allocate new style for track.
-    pub fn alloc_style(&self) -> Result<Style, AllocError> {
+    pub fn alloc_style(&self) -> Result<Style<'_>> {
         let code = unsafe { libass_sys::ass_alloc_style(self.track.as_ptr()) };
         if code >= 0 {
             Ok(Style(code, self))
         } else {
-            Err(AllocError("ass_alloc_style".to_string()))
+            Err(Error::Alloc {
+                function: "ass_alloc_style",
+            })
         }
     }
 
     /// Allocate new event handle
-    pub fn alloc_event(&self) -> Result<Event, AllocError> {
+    pub fn alloc_event(&self) -> Result<Event<'_>> {
         let code = unsafe { libass_sys::ass_alloc_event(self.track.as_ptr()) };
 
         if code >= 0 {
             Ok(Event(code, self))
         } else {
-            Err(AllocError("ass_alloc_event".to_string()))
+            Err(Error::Alloc {
+                function: "ass_alloc_event",
+            })
         }
     }
 
@@ -64,51 +192,40 @@ impl Track<'_> {
     /// within the stream?
     ///
     /// Currently the only way this function can fail is if a slice that is too large to be indexed
-    /// by an i32 is passed.
-    pub fn process_slice(&self, data: &str) -> Result<(), SliceTooLong> {
+    /// by an i32 is passed, which returns `Error::InvalidLength`.
+    pub fn process_slice(&self, data: &str) -> Result<()> {
+        let length = c_int_len(data, "ass_process_data")?;
+
+        // Embedded fonts may be extracted in to the library so it must be locked for writing.
+        let _lock = self.lib.write();
         // Safety:
         // Inspecting the C function, it soundly copies the data over and does not leak the
         // reference.
-
-        match data.len().try_into() {
-            Ok(length) => unsafe {
-                libass_sys::ass_process_data(
-                    self.track.as_ptr(),
-                    data.as_ptr().cast_mut() as _,
-                    length,
-                );
-                Ok(())
-            },
-            Err(_) => Err(SliceTooLong(
-                data.len().to_string(),
-                "ass_process_data".to_string(),
-            )),
-        }
+        unsafe {
+            libass_sys::ass_process_data(self.track.as_ptr(), data.as_ptr().cast_mut() as _, length)
+        };
+        Ok(())
     }
 
     /// Parse and process the Codec Private section of the subtitle stream in the Matroska format.
     ///
     /// Currently can only fail if provided a slice that cannot be indexed by an i32.
     #[allow(dead_code)]
-    fn process_codec_private(&self, data: &str) -> Result<(), SliceTooLong> {
+    fn process_codec_private(&self, data: &str) -> Result<()> {
+        let length = c_int_len(data, "ass_process_codec_private")?;
+
+        let _lock = self.lib.write();
         // Safety:
         // Inspecting the C function, it soundly copies the data in to the library internals and
         // does not leak the reference.
-
-        match data.len().try_into() {
-            Ok(length) => unsafe {
-                libass_sys::ass_process_codec_private(
-                    self.track.as_ptr(),
-                    data.as_ptr().cast_mut() as _,
-                    length,
-                );
-                Ok(())
-            },
-            Err(_) => Err(SliceTooLong(
-                data.len().to_string(),
-                "ass_process_codec_private".to_string(),
-            )),
-        }
+        unsafe {
+            libass_sys::ass_process_codec_private(
+                self.track.as_ptr(),
+                data.as_ptr().cast_mut() as _,
+                length,
+            )
+        };
+        Ok(())
     }
 
     /// Parse a chuck of subtitle data that corresponds to exactly one Matroska event.
@@ -116,42 +233,74 @@ impl Track<'_> {
     /// TODO: Find a library the has some MKV types to feed this thing.
     /// TODO: Time? What's the time library to use now days.
     #[allow(dead_code)]
-    fn process_chunk(&self, data: &str, timestamp: i64, duration: i64) {
+    fn process_chunk(&self, data: &str, timestamp: i64, duration: i64) -> Result<()> {
+        let length = c_int_len(data, "ass_process_chunk")?;
+
+        let _lock = self.lib.read();
         unsafe {
             libass_sys::ass_process_chunk(
                 self.track.as_ptr(),
                 data.as_ptr().cast_mut() as _,
-                data.len().try_into().unwrap(),
+                length,
                 timestamp,
                 duration,
             )
-        }
+        };
+        Ok(())
     }
 }
 
-impl Drop for Track<'_> {
+impl Drop for Track {
     fn drop(&mut self) {
         // Safety:
         // :ferrisclueless:
         //
         // This specific function doesn't need to be called before
         // the library is, but most of the methods on it can't be called
-        // after the library is dropped.
+        // after the library is dropped. The library handle is a field so
+        // it is dropped after this.
         unsafe { libass_sys::ass_free_track(self.track.as_ptr()) }
     }
 }
 
-/// Allocation failure in Libass
-#[derive(Error, Debug)]
-#[error("Allocation failure in Libass function {0}")]
-pub struct AllocError(String);
+/// Length of `data` as a C int, as Libass takes for buffer sizes.
+fn c_int_len(data: &str, function: &'static str) -> Result<c_int> {
+    data.len().try_into().map_err(|_| Error::InvalidLength {
+        len: data.len(),
+        max: usize::try_from(c_int::MAX).unwrap_or(usize::MAX),
+        function,
+    })
+}
+
+/// Number of items in a Libass array, treating negative counts as empty.
+fn count(n: c_int) -> usize {
+    usize::try_from(n).unwrap_or(0)
+}
 
-/// Slice that is too large for Libass to be able to index (i32)
-#[derive(Error, Debug)]
-#[error("Slice too long ({0}) for Libass indexing via i32 in {1}.")]
-pub struct SliceTooLong(String, String);
+/// Replace a string owned by a Libass track with a copy of `src`.
+///
+/// If the copy could not be allocated `dst` is left null.
+///
+/// # Safety
+///
+/// `dst` must be null or allocated with the C allocator, and `src` must be null or a valid C
+/// string.
+pub(crate) unsafe fn replace_c_str(dst: &mut *mut c_char, src: *const c_char) -> Result<()> {
+    libc::free(dst.cast());
+    *dst = ptr::null_mut();
 
-#[derive(Debug)]
+    if !src.is_null() {
+        *dst = NonNull::new(libc::strdup(src))
+            .ok_or(Error::Alloc { function: "strdup" })?
+            .as_ptr();
+    }
+    Ok(())
+}
+
+/// Number of `Feature` variants.
+pub(crate) const FEATURE_COUNT: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(i32)]
 #[non_exhaustive]
 /// Features that can be enabled or disabled for a track
@@ -202,6 +351,16 @@ pub enum Feature {
     WrapUnicode = libass_sys::ASS_Feature::ASS_FEATURE_WRAP_UNICODE,
 }
 
+impl Feature {
+    /// Every feature, in order of their Libass value.
+    pub(crate) const ALL: [Feature; FEATURE_COUNT] = [
+        Feature::IncompatibleExtensions,
+        Feature::BidirectionalBrackets,
+        Feature::WholeTextLayout,
+        Feature::WrapUnicode,
+    ];
+}
+
 /// Style for a track
 ///
 /// TODO: Also must think about deallocation.
@@ -213,7 +372,7 @@ pub enum Feature {
 /// just from Ass.h. So will probably just have to rely upon
 /// dropping the Track
 #[derive(Debug, PartialEq)]
-pub struct Style<'track, 'lib>(i32, &'track Track<'lib>);
+pub struct Style<'track>(i32, &'track Track);
 
 /// Event handle for a track
 ///
@@ -221,4 +380,4 @@ pub struct Style<'track, 'lib>(i32, &'track Track<'lib>);
 /// Easy way: Hold reference to Track.  
 /// Hard way: IDK some way to brand it? :ferrisclueless:
 #[derive(Debug, PartialEq)]
-pub struct Event<'track, 'lib>(i32, &'track Track<'lib>);
+pub struct Event<'track>(i32, &'track Track);
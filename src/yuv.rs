@@ -0,0 +1,610 @@
+//! Blending in to YUV frames
+//!
+//! Video is usually decoded to YUV, so converting frames to RGB to composite subtitles and back
+//! again is wasted work. [`Renderer::render_yuv`] blends Libass output straight in to the planes
+//! of a [`YuvFrameMut`], converting subtitle colors with the [`ColorSpace`] of the script's
+//! `YCbCr Matrix` header, or one given explicitly.
+use libass_sys::ASS_YCbCrMatrix as matrix;
+use time::Duration;
+
+use crate::{
+    error::{Error, Result},
+    frame::offset,
+    render::{ChangeDetection, Renderer},
+    track::Track,
+};
+
+/// Coefficients for converting RGB to YCbCr.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ColorMatrix {
+    /// ITU-R BT.601, used by standard definition video.
+    Bt601,
+    /// ITU-R BT.709, used by high definition video.
+    Bt709,
+    /// SMPTE 240M.
+    Smpte240m,
+    /// FCC, from the original NTSC standard.
+    Fcc,
+}
+
+impl ColorMatrix {
+    /// The red and blue coefficients, `Kr` and `Kb`.
+    const fn coefficients(self) -> (f64, f64) {
+        match self {
+            Self::Bt601 => (0.299, 0.114),
+            Self::Bt709 => (0.2126, 0.0722),
+            Self::Smpte240m => (0.212, 0.087),
+            Self::Fcc => (0.3, 0.11),
+        }
+    }
+}
+
+/// How the colors of a YUV frame are encoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ColorSpace {
+    #[allow(missing_docs)]
+    pub matrix: ColorMatrix,
+    /// Whether values use the full 0 to 255 range (PC), instead of 16 to 235 for luma and 16 to
+    /// 240 for chroma (TV).
+    pub full_range: bool,
+}
+
+impl Default for ColorSpace {
+    /// BT.601 with TV range, which VSFilter uses for scripts without a `YCbCr Matrix` header.
+    fn default() -> Self {
+        Self {
+            matrix: ColorMatrix::Bt601,
+            full_range: false,
+        }
+    }
+}
+
+impl ColorSpace {
+    /// The color space named by a track's `YCbCr Matrix` header.
+    ///
+    /// Returns `None` if the header is missing, unknown, or `None`, where subtitle colors should
+    /// be converted with the video's own color space.
+    pub fn from_track(track: &Track) -> Option<Self> {
+        // Safety:
+        // The track is valid, and only a plain value is read.
+        let value = unsafe { (*track.track.as_ptr()).YCbCrMatrix };
+        let (matrix, full_range) = match value {
+            matrix::YCBCR_BT601_TV => (ColorMatrix::Bt601, false),
+            matrix::YCBCR_BT601_PC => (ColorMatrix::Bt601, true),
+            matrix::YCBCR_BT709_TV => (ColorMatrix::Bt709, false),
+            matrix::YCBCR_BT709_PC => (ColorMatrix::Bt709, true),
+            matrix::YCBCR_SMPTE240M_TV => (ColorMatrix::Smpte240m, false),
+            matrix::YCBCR_SMPTE240M_PC => (ColorMatrix::Smpte240m, true),
+            matrix::YCBCR_FCC_TV => (ColorMatrix::Fcc, false),
+            matrix::YCBCR_FCC_PC => (ColorMatrix::Fcc, true),
+            _ => return None,
+        };
+        Some(Self { matrix, full_range })
+    }
+
+    /// Convert an RGB color to 8-bit Y, Cb and Cr.
+    fn to_ycbcr(self, [r, g, b]: [u8; 3]) -> [u8; 3] {
+        let (kr, kb) = self.matrix.coefficients();
+        let [r, g, b] = [r, g, b].map(|c| f64::from(c) / 255.0);
+        let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+        let cb = (b - y) / (2.0 * (1.0 - kb));
+        let cr = (r - y) / (2.0 * (1.0 - kr));
+        let (luma_range, chroma_range, black) = if self.full_range {
+            (255.0, 255.0, 0.0)
+        } else {
+            (219.0, 224.0, 16.0)
+        };
+        [
+            black + y * luma_range,
+            128.0 + cb * chroma_range,
+            128.0 + cr * chroma_range,
+        ]
+        .map(to_u8)
+    }
+}
+
+/// Layout of the planes of a YUV frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum YuvFormat {
+    /// Separate Y, U and V planes, with chroma at half the width and height.
+    Yuv420p,
+    /// A Y plane, and a plane of interleaved U and V at half the width and height.
+    Nv12,
+    /// Separate Y, U and V planes, all at full size.
+    Yuv444p,
+}
+
+/// A borrowed plane of 8-bit samples.
+#[derive(Debug)]
+pub struct Plane<'a> {
+    /// Samples, row by row.
+    data: &'a mut [u8],
+    /// Bytes from the start of one row to the start of the next.
+    stride: usize,
+}
+
+impl<'a> Plane<'a> {
+    #[allow(missing_docs)]
+    pub const fn new(data: &'a mut [u8], stride: usize) -> Self {
+        Self { data, stride }
+    }
+
+    /// Check the plane can hold `height` rows of `row_len` bytes.
+    const fn check(&self, name: &'static str, row_len: usize, height: usize) -> Result<()> {
+        // Sizes too large to add up can't fit in the plane either.
+        let needed = match height.checked_sub(1) {
+            None => Some(0),
+            Some(rows) => match self.stride.checked_mul(rows) {
+                Some(start) => start.checked_add(row_len),
+                None => None,
+            },
+        };
+        match needed {
+            Some(needed) if self.stride >= row_len && self.data.len() >= needed => Ok(()),
+            _ => Err(Error::InvalidPlane {
+                plane: name,
+                stride: self.stride,
+                len: self.data.len(),
+            }),
+        }
+    }
+}
+
+/// The chroma planes of a frame.
+#[derive(Debug)]
+enum Chroma<'a> {
+    /// Separate U and V planes.
+    Planar {
+        /// The U plane.
+        u: Plane<'a>,
+        /// The V plane.
+        v: Plane<'a>,
+    },
+    /// One plane of U and V pairs.
+    Interleaved(Plane<'a>),
+}
+
+/// A borrowed 8-bit YUV frame that Libass output can be blended in to.
+#[derive(Debug)]
+pub struct YuvFrameMut<'a> {
+    /// Layout of the planes.
+    format: YuvFormat,
+    /// Width in pixels.
+    width: usize,
+    /// Height in pixels.
+    height: usize,
+    /// The Y plane.
+    luma: Plane<'a>,
+    /// The U and V planes.
+    chroma: Chroma<'a>,
+}
+
+impl<'a> YuvFrameMut<'a> {
+    /// Borrow a `YUV420P` frame.
+    ///
+    /// Chroma planes are half the width and height, rounded up. Returns `Error::InvalidPlane` if a
+    /// plane is too small for the frame.
+    pub fn yuv420p(
+        width: usize,
+        height: usize,
+        y: Plane<'a>,
+        u: Plane<'a>,
+        v: Plane<'a>,
+    ) -> Result<Self> {
+        Self::new(
+            YuvFormat::Yuv420p,
+            width,
+            height,
+            y,
+            Chroma::Planar { u, v },
+        )
+    }
+
+    /// Borrow an `NV12` frame.
+    ///
+    /// The chroma plane is half the height, and holds a U and V pair for every two pixels of a
+    /// row. Returns `Error::InvalidPlane` if a plane is too small for the frame.
+    pub fn nv12(width: usize, height: usize, y: Plane<'a>, uv: Plane<'a>) -> Result<Self> {
+        Self::new(YuvFormat::Nv12, width, height, y, Chroma::Interleaved(uv))
+    }
+
+    /// Borrow a `YUV444P` frame.
+    ///
+    /// Returns `Error::InvalidPlane` if a plane is too small for the frame.
+    pub fn yuv444p(
+        width: usize,
+        height: usize,
+        y: Plane<'a>,
+        u: Plane<'a>,
+        v: Plane<'a>,
+    ) -> Result<Self> {
+        Self::new(
+            YuvFormat::Yuv444p,
+            width,
+            height,
+            y,
+            Chroma::Planar { u, v },
+        )
+    }
+
+    /// Check the planes and build the frame.
+    fn new(
+        format: YuvFormat,
+        width: usize,
+        height: usize,
+        luma: Plane<'a>,
+        chroma: Chroma<'a>,
+    ) -> Result<Self> {
+        let frame = Self {
+            format,
+            width,
+            height,
+            luma,
+            chroma,
+        };
+        let (chroma_width, chroma_height) = frame.chroma_size();
+        frame.luma.check("Y", width, height)?;
+        match &frame.chroma {
+            Chroma::Planar { u, v } => {
+                u.check("U", chroma_width, chroma_height)?;
+                v.check("V", chroma_width, chroma_height)?;
+            }
+            Chroma::Interleaved(uv) => {
+                uv.check("UV", chroma_width.saturating_mul(2), chroma_height)?
+            }
+        }
+        Ok(frame)
+    }
+
+    #[allow(missing_docs)]
+    pub const fn format(&self) -> YuvFormat {
+        self.format
+    }
+
+    /// Width in pixels.
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels.
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixels covered by each chroma sample, horizontally and vertically.
+    const fn subsampling(&self) -> (usize, usize) {
+        match self.format {
+            YuvFormat::Yuv420p | YuvFormat::Nv12 => (2, 2),
+            YuvFormat::Yuv444p => (1, 1),
+        }
+    }
+
+    /// Width and height of the chroma planes, in samples.
+    const fn chroma_size(&self) -> (usize, usize) {
+        let (sx, sy) = self.subsampling();
+        (self.width.div_ceil(sx), self.height.div_ceil(sy))
+    }
+
+    /// Blend a list of Libass images in to the frame, in order.
+    ///
+    /// Parts of images that are outside of the frame are clipped.
+    ///
+    /// # Safety
+    ///
+    /// `image` must be null or the head of a list of images returned by `ass_render_frame` that
+    /// is still valid, i.e. the renderer has not been used or dropped since.
+    unsafe fn composite(&mut self, mut image: *const libass_sys::ASS_Image, space: ColorSpace) {
+        while let Some(img) = image.as_ref() {
+            self.blend(img, space);
+            image = img.next;
+        }
+    }
+
+    /// Blend a single Libass alpha bitmap in to the frame.
+    ///
+    /// Each chroma sample is blended with the average opacity of the pixels it covers.
+    ///
+    /// # Safety
+    ///
+    /// The bitmap of `img` must be valid for `stride * h` bytes.
+    unsafe fn blend(&mut self, img: &libass_sys::ASS_Image, space: ColorSpace) {
+        let (Ok(width), Ok(height), Ok(stride), Some(left), Some(top)) = (
+            usize::try_from(img.w),
+            usize::try_from(img.h),
+            usize::try_from(img.stride),
+            offset(img.dst_x, 0),
+            offset(img.dst_y, 0),
+        ) else {
+            return;
+        };
+        if img.bitmap.is_null() || width == 0 || height == 0 {
+            return;
+        }
+        let bitmap = core::slice::from_raw_parts(img.bitmap, stride * (height - 1) + width);
+
+        // Color is RRGGBBAA, where AA is transparency rather than opacity.
+        let [r, g, b, transparency] = img.color.to_be_bytes();
+        let opacity = 255 - u32::from(transparency);
+        let [y, cb, cr] = space.to_ycbcr([r, g, b]);
+
+        // The part of the image inside the frame, in frame pixels.
+        let right = (left + width).min(self.width);
+        let bottom = (top + height).min(self.height);
+        if left >= right || top >= bottom {
+            return;
+        }
+        let alpha =
+            |x: usize, y: usize| u32::from(bitmap[(y - top) * stride + (x - left)]) * opacity / 255;
+
+        for row in top..bottom {
+            for col in left..right {
+                let a = alpha(col, row);
+                if a != 0 {
+                    let sample = &mut self.luma.data[row * self.luma.stride + col];
+                    *sample = mix(*sample, y, a);
+                }
+            }
+        }
+
+        let (sx, sy) = self.subsampling();
+        for chroma_row in top / sy..bottom.div_ceil(sy) {
+            for chroma_col in left / sx..right.div_ceil(sx) {
+                // Pixels outside of the image count as transparent, but pixels outside of the
+                // frame don't count at all.
+                let (mut sum, mut count) = (0, 0);
+                for row in chroma_row * sy..((chroma_row + 1) * sy).min(self.height) {
+                    for col in chroma_col * sx..((chroma_col + 1) * sx).min(self.width) {
+                        if (top..bottom).contains(&row) && (left..right).contains(&col) {
+                            sum += alpha(col, row);
+                        }
+                        count += 1;
+                    }
+                }
+                let a = (sum + count / 2) / count;
+                if a == 0 {
+                    continue;
+                }
+                match &mut self.chroma {
+                    Chroma::Planar { u, v } => {
+                        let u = &mut u.data[chroma_row * u.stride + chroma_col];
+                        *u = mix(*u, cb, a);
+                        let v = &mut v.data[chroma_row * v.stride + chroma_col];
+                        *v = mix(*v, cr, a);
+                    }
+                    Chroma::Interleaved(uv) => {
+                        let index = chroma_row * uv.stride + chroma_col * 2;
+                        uv.data[index] = mix(uv.data[index], cb, a);
+                        uv.data[index + 1] = mix(uv.data[index + 1], cr, a);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Renderer {
+    /// Render the subtitles of `track` that are visible at `timestamp` and blend them in to
+    /// `frame`.
+    ///
+    /// Subtitle colors are converted with `color_space`, or if it is `None`, the color space of
+    /// the track's `YCbCr Matrix` header, falling back to `ColorSpace::default()`. Pass the
+    /// video's color space to override the header, such as for scripts with `YCbCr Matrix: None`.
+    /// The frame should be the size set with `Renderer::set_frame_size`, anything outside of it
+    /// is clipped.
+    ///
+    /// Returns how the output differs from the previous frame this renderer drew, or
+    /// `Error::TimestampOutOfRange` if the timestamp cannot be represented by Libass.
+    pub fn render_yuv(
+        &self,
+        track: &Track,
+        timestamp: &Duration,
+        frame: &mut YuvFrameMut<'_>,
+        color_space: Option<ColorSpace>,
+    ) -> Result<ChangeDetection> {
+        let space = color_space
+            .or_else(|| ColorSpace::from_track(track))
+            .unwrap_or_default();
+        let mut change = Some(ChangeDetection::Identical);
+        let images = self.render_frame(track, timestamp, &mut change)?;
+
+        // Safety:
+        // The images were just returned and the renderer is not used again until they have been
+        // blended.
+        unsafe { frame.composite(images, space) };
+        Ok(change.unwrap_or(ChangeDetection::DifferentContent))
+    }
+}
+
+/// Blend `src` over `dst` with opacity `a` out of 255.
+fn mix(dst: u8, src: u8, a: u32) -> u8 {
+    let value = (u32::from(dst) * (255 - a) + u32::from(src) * a + 127) / 255;
+    u8::try_from(value).unwrap_or(u8::MAX)
+}
+
+/// Round a sample to 8 bits, clamping it to the valid range.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const fn to_u8(value: f64) -> u8 {
+    // Clamped to 0..=255 first, so the cast is exact.
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    /// Opaque red in BT.601 TV range.
+    const RED: [u8; 3] = [81, 90, 240];
+
+    /// An image of `bitmap` rows `stride` bytes apart, at (`x`, `y`) in an RRGGBBAA color.
+    fn image(
+        bitmap: &mut [u8],
+        (w, h, stride): (i32, i32, i32),
+        (x, y): (i32, i32),
+        color: u32,
+    ) -> libass_sys::ASS_Image {
+        libass_sys::ASS_Image {
+            w,
+            h,
+            stride,
+            bitmap: bitmap.as_mut_ptr(),
+            color,
+            dst_x: x,
+            dst_y: y,
+            next: ptr::null_mut(),
+            type_: libass_sys::ass_image__bindgen_ty_1::IMAGE_TYPE_CHARACTER,
+        }
+    }
+
+    #[test]
+    fn color_spaces() {
+        let space = |matrix, full_range| ColorSpace { matrix, full_range };
+        let tv = ColorSpace::default();
+        assert_eq!(tv, space(ColorMatrix::Bt601, false));
+        assert_eq!(tv.to_ycbcr([255, 0, 0]), RED);
+        assert_eq!(tv.to_ycbcr([255, 255, 255]), [235, 128, 128]);
+        assert_eq!(tv.to_ycbcr([0, 0, 0]), [16, 128, 128]);
+
+        let pc = space(ColorMatrix::Bt601, true);
+        assert_eq!(pc.to_ycbcr([255, 255, 255]), [255, 128, 128]);
+        assert_eq!(pc.to_ycbcr([0, 0, 0]), [0, 128, 128]);
+        assert_eq!(pc.to_ycbcr([0, 0, 255]), [29, 255, 107]);
+
+        assert_eq!(
+            space(ColorMatrix::Bt709, false).to_ycbcr([255, 0, 0]),
+            [63, 102, 240]
+        );
+        assert_eq!(
+            space(ColorMatrix::Bt709, true).to_ycbcr([0, 255, 0]),
+            [182, 30, 12]
+        );
+    }
+
+    #[test]
+    fn mixing() {
+        assert_eq!(mix(0, 255, 255), 255);
+        assert_eq!(mix(10, 255, 0), 10);
+        assert_eq!(mix(100, 200, 128), 150);
+        assert_eq!(mix(255, 0, 1), 254);
+    }
+
+    #[test]
+    fn yuv420p_clipping() {
+        // A 3x3 frame has 2x2 chroma. The image runs off the right and bottom edges.
+        let (mut y, mut u, mut v) = ([16; 9], [128; 4], [128; 4]);
+        let mut frame = YuvFrameMut::yuv420p(
+            3,
+            3,
+            Plane::new(&mut y, 3),
+            Plane::new(&mut u, 2),
+            Plane::new(&mut v, 2),
+        )
+        .unwrap();
+        let mut bitmap = [255, 255, 0, 0, 255, 255, 0, 0, 255, 255];
+        let img = image(&mut bitmap, (2, 3, 4), (2, 1), 0xff00_0000);
+        unsafe { frame.composite(&img, ColorSpace::default()) };
+
+        assert_eq!(y, [16, 16, 16, 16, 16, RED[0], 16, 16, RED[0]]);
+        // The top right sample covers one covered and one uncovered pixel, and the bottom right
+        // one a single covered pixel, as the rest of it is outside the frame.
+        assert_eq!(u, [128, 109, 128, RED[1]]);
+        assert_eq!(v, [128, 184, 128, RED[2]]);
+    }
+
+    #[test]
+    fn nv12_interleaving() {
+        let (mut y, mut uv) = ([16; 8], [128; 6]);
+        let mut frame =
+            YuvFrameMut::nv12(4, 2, Plane::new(&mut y, 4), Plane::new(&mut uv, 6)).unwrap();
+        assert_eq!(frame.format(), YuvFormat::Nv12);
+        let mut bitmap = [255];
+        let img = image(&mut bitmap, (1, 1, 1), (1, 0), 0xff00_0000);
+        unsafe { frame.composite(&img, ColorSpace::default()) };
+
+        assert_eq!(y, [16, RED[0], 16, 16, 16, 16, 16, 16]);
+        // A quarter of the first sample is covered, and padding after the row is left alone.
+        assert_eq!(uv, [118, 156, 128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn yuv444p_transparency() {
+        let (mut y, mut u, mut v) = ([0; 4], [128; 4], [128; 4]);
+        let mut frame = YuvFrameMut::yuv444p(
+            2,
+            2,
+            Plane::new(&mut y, 2),
+            Plane::new(&mut u, 2),
+            Plane::new(&mut v, 2),
+        )
+        .unwrap();
+        // Half transparent white over a partly covered bitmap, and an image outside the frame.
+        let mut bitmap = [255, 0, 0, 128];
+        let mut outside = [255];
+        let mut second = image(&mut outside, (1, 1, 1), (2, 0), 0xffff_ff00);
+        let mut first = image(&mut bitmap, (2, 2, 2), (0, 0), 0xffff_ff80);
+        first.next = &mut second;
+        let space = ColorSpace {
+            matrix: ColorMatrix::Bt601,
+            full_range: true,
+        };
+        unsafe { frame.composite(&first, space) };
+
+        assert_eq!(y, [127, 0, 0, 63]);
+        assert_eq!((u, v), ([128; 4], [128; 4]));
+    }
+
+    #[test]
+    fn invalid_planes() {
+        let (mut y, mut u, mut v) = ([0; 15], [0; 6], [0; 5]);
+        let result = YuvFrameMut::yuv420p(
+            5,
+            3,
+            Plane::new(&mut y, 5),
+            Plane::new(&mut u, 3),
+            Plane::new(&mut v, 3),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::InvalidPlane {
+                plane: "V",
+                stride: 3,
+                len: 5
+            })
+        ));
+
+        let (mut y, mut uv) = ([0; 16], [0; 8]);
+        let result = YuvFrameMut::nv12(4, 4, Plane::new(&mut y, 4), Plane::new(&mut uv, 3));
+        assert!(matches!(
+            result,
+            Err(Error::InvalidPlane { plane: "UV", .. })
+        ));
+
+        // The last row doesn't need padding.
+        let (mut y, mut u, mut v) = ([0; 7], [0; 7], [0; 7]);
+        let frame = YuvFrameMut::yuv444p(
+            2,
+            2,
+            Plane::new(&mut y, 5),
+            Plane::new(&mut u, 5),
+            Plane::new(&mut v, 5),
+        )
+        .unwrap();
+        assert_eq!((frame.width(), frame.height()), (2, 2));
+
+        // Sizes that overflow are rejected rather than wrapping.
+        let (mut y, mut uv) = ([0; 16], [0; 8]);
+        let result = YuvFrameMut::nv12(
+            4,
+            4,
+            Plane::new(&mut y, usize::MAX / 2),
+            Plane::new(&mut uv, 4),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::InvalidPlane { plane: "Y", .. })
+        ));
+    }
+}